@@ -0,0 +1,6 @@
+ALTER TABLE users ADD COLUMN referral_code TEXT;
+UPDATE users SET referral_code = upper(substr(hex(randomblob(4)), 1, 8)) WHERE referral_code IS NULL;
+CREATE UNIQUE INDEX idx_users_referral_code ON users(referral_code);
+CREATE INDEX idx_users_email ON users(email COLLATE NOCASE);
+
+CREATE INDEX idx_transactions_type ON credit_transactions(user_id, transaction_type, created_at);
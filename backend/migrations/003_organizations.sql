@@ -0,0 +1,42 @@
+CREATE TABLE organizations (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    credits_balance INTEGER NOT NULL DEFAULT 0,
+    created_by TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+    FOREIGN KEY (created_by) REFERENCES users(id)
+);
+
+CREATE TABLE organization_members (
+    org_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    role TEXT NOT NULL DEFAULT 'member',
+    monthly_credit_cap INTEGER,
+    joined_at TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (org_id, user_id),
+    FOREIGN KEY (org_id) REFERENCES organizations(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_org_members_user ON organization_members(user_id);
+
+CREATE TABLE organization_invitations (
+    id TEXT PRIMARY KEY,
+    org_id TEXT NOT NULL,
+    token TEXT UNIQUE NOT NULL,
+    email TEXT,
+    role TEXT NOT NULL DEFAULT 'member',
+    invited_by TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    expires_at TEXT NOT NULL,
+    accepted_at TEXT,
+    accepted_by TEXT,
+    FOREIGN KEY (org_id) REFERENCES organizations(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_org_invitations_org ON organization_invitations(org_id, created_at DESC);
+
+ALTER TABLE videos ADD COLUMN org_id TEXT REFERENCES organizations(id) ON DELETE SET NULL;
+CREATE INDEX idx_videos_org ON videos(org_id, created_at DESC);
+
+ALTER TABLE credit_transactions ADD COLUMN org_id TEXT REFERENCES organizations(id) ON DELETE SET NULL;
+CREATE INDEX idx_transactions_org ON credit_transactions(org_id, created_at DESC);
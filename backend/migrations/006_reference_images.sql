@@ -0,0 +1,14 @@
+CREATE TABLE reference_images (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    storage_key TEXT NOT NULL,
+    mime_type TEXT NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_reference_images_user ON reference_images(user_id, created_at DESC);
+
+ALTER TABLE videos ADD COLUMN reference_image_id TEXT REFERENCES reference_images(id) ON DELETE SET NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE videos ADD COLUMN parent_video_id TEXT REFERENCES videos(id) ON DELETE SET NULL;
+CREATE INDEX idx_videos_parent ON videos(parent_video_id, created_at DESC);
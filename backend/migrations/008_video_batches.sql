@@ -0,0 +1,13 @@
+CREATE TABLE video_batches (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    org_id TEXT,
+    total_credits INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (org_id) REFERENCES organizations(id) ON DELETE SET NULL
+);
+CREATE INDEX idx_video_batches_user ON video_batches(user_id, created_at DESC);
+
+ALTER TABLE videos ADD COLUMN batch_id TEXT REFERENCES video_batches(id) ON DELETE SET NULL;
+CREATE INDEX idx_videos_batch ON videos(batch_id);
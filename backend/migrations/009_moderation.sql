@@ -0,0 +1,17 @@
+CREATE TABLE moderation_violations (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    source TEXT NOT NULL CHECK(source IN ('blocklist', 'provider')),
+    categories TEXT NOT NULL,
+    prompt TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_moderation_violations_user ON moderation_violations(user_id, created_at DESC);
+
+CREATE TABLE user_moderation_stats (
+    user_id TEXT PRIMARY KEY,
+    violation_count INTEGER NOT NULL DEFAULT 0,
+    last_violation_at TEXT,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,20 @@
+CREATE TABLE prompt_templates (
+    id TEXT PRIMARY KEY,
+    user_id TEXT,
+    name TEXT NOT NULL,
+    description TEXT,
+    body TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_prompt_templates_user ON prompt_templates(user_id, name);
+
+ALTER TABLE videos ADD COLUMN template_id TEXT REFERENCES prompt_templates(id) ON DELETE SET NULL;
+ALTER TABLE videos ADD COLUMN template_variables TEXT;
+
+INSERT INTO prompt_templates (id, user_id, name, description, body, created_at, updated_at) VALUES
+    ('tpl_cinematic', NULL, 'Cinematic', 'Wide anamorphic shot with film grain', '{{prompt}}. Cinematic wide shot, anamorphic lens, shallow depth of field, soft film grain, golden hour lighting.', '2025-01-01T00:00:00+00:00', '2025-01-01T00:00:00+00:00'),
+    ('tpl_product', NULL, 'Product showcase', 'Slow orbit around a product on a seamless backdrop', 'A slow 360-degree orbit around {{product}} on a seamless {{backdrop}} backdrop. Softbox studio lighting, crisp reflections, macro detail.', '2025-01-01T00:00:00+00:00', '2025-01-01T00:00:00+00:00'),
+    ('tpl_drone', NULL, 'Drone flyover', 'Aerial tracking shot over a location', 'Aerial drone shot flying low over {{location}} at {{time_of_day}}, smooth forward tracking motion, volumetric light, high detail.', '2025-01-01T00:00:00+00:00', '2025-01-01T00:00:00+00:00'),
+    ('tpl_handheld', NULL, 'Handheld documentary', 'Naturalistic handheld footage', '{{prompt}}. Handheld documentary footage, natural available light, subtle camera shake, 35mm.', '2025-01-01T00:00:00+00:00', '2025-01-01T00:00:00+00:00');
@@ -0,0 +1,4 @@
+ALTER TABLE videos ADD COLUMN started_at TEXT;
+UPDATE videos SET started_at = created_at WHERE status != 'pending_submission';
+
+CREATE INDEX idx_videos_status_started ON videos(status, started_at);
@@ -0,0 +1,3 @@
+ALTER TABLE videos ADD COLUMN storage_key TEXT;
+ALTER TABLE videos ADD COLUMN storage_size INTEGER;
+ALTER TABLE videos ADD COLUMN storage_checksum TEXT;
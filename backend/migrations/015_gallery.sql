@@ -0,0 +1,22 @@
+CREATE TABLE gallery_items (
+    video_id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    like_count INTEGER NOT NULL DEFAULT 0,
+    published_at TEXT NOT NULL DEFAULT (datetime('now')),
+    taken_down_at TEXT,
+    taken_down_by TEXT,
+    takedown_reason TEXT,
+    FOREIGN KEY (video_id) REFERENCES videos(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_gallery_recent ON gallery_items(published_at DESC, video_id DESC) WHERE taken_down_at IS NULL;
+CREATE INDEX idx_gallery_top ON gallery_items(like_count DESC, published_at DESC, video_id DESC) WHERE taken_down_at IS NULL;
+
+CREATE TABLE gallery_likes (
+    video_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (video_id, user_id),
+    FOREIGN KEY (video_id) REFERENCES gallery_items(video_id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,35 @@
+ALTER TABLE videos ADD COLUMN favorite INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX idx_videos_favorite ON videos(user_id, favorite, created_at DESC);
+
+CREATE TABLE collections (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE UNIQUE INDEX idx_collections_user_name ON collections(user_id, name);
+
+-- Removing a collection or a video only removes the membership rows.
+CREATE TABLE collection_videos (
+    collection_id TEXT NOT NULL,
+    video_id TEXT NOT NULL,
+    added_at TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (collection_id, video_id),
+    FOREIGN KEY (collection_id) REFERENCES collections(id) ON DELETE CASCADE,
+    FOREIGN KEY (video_id) REFERENCES videos(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_collection_videos_video ON collection_videos(video_id);
+
+CREATE TABLE video_tags (
+    video_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (video_id, tag),
+    FOREIGN KEY (video_id) REFERENCES videos(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_video_tags_user ON video_tags(user_id, tag);
@@ -0,0 +1,40 @@
+-- One row per video, keyed by the video's rowid. Tags live in their own
+-- table, so this is a standalone FTS table rather than external content.
+CREATE VIRTUAL TABLE videos_fts USING fts5(
+    prompt,
+    original_prompt,
+    tags,
+    tokenize = 'unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
+
+INSERT INTO videos_fts (rowid, prompt, original_prompt, tags)
+SELECT v.rowid, v.prompt, COALESCE(v.original_prompt, ''),
+       COALESCE((SELECT group_concat(t.tag, ' ') FROM video_tags t WHERE t.video_id = v.id), '')
+FROM videos v;
+
+CREATE TRIGGER videos_fts_insert AFTER INSERT ON videos BEGIN
+    INSERT INTO videos_fts (rowid, prompt, original_prompt, tags)
+    VALUES (new.rowid, new.prompt, COALESCE(new.original_prompt, ''), '');
+END;
+
+CREATE TRIGGER videos_fts_update AFTER UPDATE OF prompt, original_prompt ON videos BEGIN
+    UPDATE videos_fts SET prompt = new.prompt, original_prompt = COALESCE(new.original_prompt, '')
+    WHERE rowid = new.rowid;
+END;
+
+CREATE TRIGGER videos_fts_delete AFTER DELETE ON videos BEGIN
+    DELETE FROM videos_fts WHERE rowid = old.rowid;
+END;
+
+CREATE TRIGGER video_tags_fts_insert AFTER INSERT ON video_tags BEGIN
+    UPDATE videos_fts
+    SET tags = COALESCE((SELECT group_concat(tag, ' ') FROM video_tags WHERE video_id = new.video_id), '')
+    WHERE rowid = (SELECT rowid FROM videos WHERE id = new.video_id);
+END;
+
+CREATE TRIGGER video_tags_fts_delete AFTER DELETE ON video_tags BEGIN
+    UPDATE videos_fts
+    SET tags = COALESCE((SELECT group_concat(tag, ' ') FROM video_tags WHERE video_id = old.video_id), '')
+    WHERE rowid = (SELECT rowid FROM videos WHERE id = old.video_id);
+END;
@@ -143,3 +143,18 @@ pub fn extract_user_from_request(req: &Request) -> Result<String, AppError> {
 
     Ok(user_id.to_string())
 }
+
+/// Platform admins are listed in `ADMIN_USER_IDS` (comma-separated user IDs).
+pub fn is_admin(env: &Env, user_id: &str) -> bool {
+    env.var("ADMIN_USER_IDS")
+        .map(|v| v.to_string().split(',').any(|id| id.trim() == user_id))
+        .unwrap_or(false)
+}
+
+pub fn require_admin(env: &Env, user_id: &str) -> Result<(), AppError> {
+    if !is_admin(env, user_id) {
+        return Err(AppError::Forbidden("This action requires an admin".into()));
+    }
+
+    Ok(())
+}
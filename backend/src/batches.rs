@@ -0,0 +1,248 @@
+use crate::db::{self, get_db, VIDEO_COLUMNS};
+use crate::error::AppError;
+use crate::generation_queue::{self, QueueLimits};
+use crate::models::{BatchCounts, BatchResponse, BatchStatus, Video, VideoBatch, VideoStatus};
+use serde::Deserialize;
+use worker::{wasm_bindgen::JsValue, Env};
+
+const DEFAULT_MAX_BATCH_SIZE: usize = 10;
+
+const BATCH_COLUMNS: &str = "id, user_id, org_id, total_credits, created_at";
+
+#[derive(Deserialize)]
+struct SumResult {
+    total: Option<f64>,
+}
+
+pub fn max_batch_size(env: &Env) -> usize {
+    env.var("MAX_BATCH_SIZE")
+        .map(|v| v.to_string().parse().unwrap_or(DEFAULT_MAX_BATCH_SIZE))
+        .unwrap_or(DEFAULT_MAX_BATCH_SIZE)
+        .max(1)
+}
+
+/// Stores the batch row and all of its videos in one D1 batch. The queue
+/// capacity check guards the batch row and every video insert requires that
+/// row, so either the whole batch is queued or nothing is.
+pub async fn insert_batch(env: &Env, batch: &VideoBatch, videos: &[Video], limits: &QueueLimits) -> Result<(), AppError> {
+    let db = get_db(env)?;
+    let (condition, condition_bindings) = limits.capacity_condition(&batch.user_id, videos.len() as i64);
+
+    let mut bindings = vec![
+        batch.id.clone().into(),
+        batch.user_id.clone().into(),
+        batch.org_id.as_deref().map(JsValue::from_str).unwrap_or(JsValue::NULL),
+        (batch.total_credits as f64).into(),
+        batch.created_at.to_rfc3339().into(),
+    ];
+    bindings.extend(condition_bindings);
+
+    let mut statements = Vec::with_capacity(videos.len() + 1);
+    statements.push(
+        db.prepare(format!("INSERT INTO video_batches (id, user_id, org_id, total_credits, created_at) SELECT ?, ?, ?, ?, ? WHERE {}", condition))
+            .bind(&bindings)?,
+    );
+
+    for video in videos {
+        statements.push(db::insert_video_statement(
+            &db,
+            video,
+            "EXISTS (SELECT 1 FROM video_batches WHERE id = ?)",
+            vec![batch.id.clone().into()],
+        )?);
+    }
+
+    let results = db
+        .batch(statements)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    let inserted = results
+        .first()
+        .and_then(|r| r.meta().ok().flatten())
+        .and_then(|meta| meta.changes)
+        .unwrap_or(0);
+
+    if inserted == 0 {
+        generation_queue::check_capacity(env, &batch.user_id, limits, videos.len() as i64).await?;
+        return Err(AppError::QueueFull(
+            "Your queue filled up while this batch was being created. Wait for some videos to finish and try again.".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+pub async fn get_batch(env: &Env, batch_id: &str, user_id: &str) -> Result<VideoBatch, AppError> {
+    let db = get_db(env)?;
+
+    db.prepare(format!("SELECT {} FROM video_batches WHERE id = ? AND user_id = ?", BATCH_COLUMNS))
+        .bind(&[batch_id.into(), user_id.into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("Batch not found".into()))
+}
+
+pub async fn list_batch_videos(env: &Env, batch_id: &str) -> Result<Vec<Video>, AppError> {
+    let db = get_db(env)?;
+
+    let result = db
+        .prepare(format!("SELECT {} FROM videos WHERE batch_id = ? ORDER BY created_at ASC, id ASC", VIDEO_COLUMNS))
+        .bind(&[batch_id.into()])?
+        .all()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    result
+        .results()
+        .map_err(|e| AppError::DatabaseError(e.to_string()))
+}
+
+pub async fn get_refunded_credits(env: &Env, batch_id: &str) -> Result<i64, AppError> {
+    let db = get_db(env)?;
+
+    let result: Option<SumResult> = db
+        .prepare("SELECT SUM(amount) as total FROM credit_transactions WHERE transaction_type = 'refund' AND video_id IN (SELECT id FROM videos WHERE batch_id = ?)")
+        .bind(&[batch_id.into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(result.and_then(|r| r.total).unwrap_or(0.0) as i64)
+}
+
+fn count_statuses(videos: &[Video]) -> BatchCounts {
+    let mut counts = BatchCounts::default();
+
+    for video in videos {
+        let slot = match video.status {
+            VideoStatus::PendingSubmission => &mut counts.pending_submission,
+            VideoStatus::Queued => &mut counts.queued,
+            VideoStatus::InProgress => &mut counts.in_progress,
+            VideoStatus::Completed => &mut counts.completed,
+            VideoStatus::Failed => &mut counts.failed,
+            VideoStatus::Cancelled => &mut counts.cancelled,
+        };
+        *slot += 1;
+    }
+
+    counts
+}
+
+/// A batch is running while any child is unfinished. Once all are finished it
+/// is `completed` or `partially_completed` if any child completed, otherwise
+/// `cancelled` if every child was cancelled, otherwise `failed`.
+fn rollup_status(counts: &BatchCounts, total: i64) -> BatchStatus {
+    if counts.pending_submission == total {
+        BatchStatus::Pending
+    } else if counts.pending_submission + counts.queued + counts.in_progress > 0 {
+        BatchStatus::InProgress
+    } else if counts.completed == total {
+        BatchStatus::Completed
+    } else if counts.completed > 0 {
+        BatchStatus::PartiallyCompleted
+    } else if counts.cancelled == total {
+        BatchStatus::Cancelled
+    } else {
+        BatchStatus::Failed
+    }
+}
+
+/// Mean progress across children, counting finished ones as 100%.
+fn aggregate_progress(videos: &[Video]) -> i32 {
+    if videos.is_empty() {
+        return 0;
+    }
+
+    let sum: i64 = videos
+        .iter()
+        .map(|video| match video.status {
+            VideoStatus::Completed | VideoStatus::Failed | VideoStatus::Cancelled => 100,
+            _ => video.progress.clamp(0, 100) as i64,
+        })
+        .sum();
+
+    (sum / videos.len() as i64) as i32
+}
+
+pub fn summarize(batch: VideoBatch, videos: Vec<Video>, refunded_credits: i64) -> BatchResponse {
+    let total = videos.len() as i64;
+    let counts = count_statuses(&videos);
+
+    BatchResponse {
+        id: batch.id,
+        status: rollup_status(&counts, total),
+        total,
+        progress: aggregate_progress(&videos),
+        counts,
+        credits_cost: batch.total_credits,
+        refunded_credits,
+        org_id: batch.org_id,
+        created_at: batch.created_at,
+        videos,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    fn video(status: VideoStatus, progress: i32) -> Video {
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let mut video = Video::new(
+            "user".into(),
+            "video_1".into(),
+            "sora-2".into(),
+            "a lighthouse".into(),
+            "1280x720".into(),
+            8,
+            100,
+            now,
+        );
+        video.status = status;
+        video.progress = progress;
+        video
+    }
+
+    fn status_of(videos: &[Video]) -> BatchStatus {
+        rollup_status(&count_statuses(videos), videos.len() as i64)
+    }
+
+    #[test]
+    fn rolls_up_running_batches() {
+        let pending = [video(VideoStatus::PendingSubmission, 0), video(VideoStatus::PendingSubmission, 0)];
+        assert_eq!(status_of(&pending), BatchStatus::Pending);
+
+        let running = [video(VideoStatus::Completed, 100), video(VideoStatus::PendingSubmission, 0)];
+        assert_eq!(status_of(&running), BatchStatus::InProgress);
+    }
+
+    #[test]
+    fn rolls_up_finished_batches() {
+        let done = [video(VideoStatus::Completed, 100), video(VideoStatus::Completed, 100)];
+        assert_eq!(status_of(&done), BatchStatus::Completed);
+
+        let partial = [video(VideoStatus::Completed, 100), video(VideoStatus::Failed, 0)];
+        assert_eq!(status_of(&partial), BatchStatus::PartiallyCompleted);
+
+        let cancelled = [video(VideoStatus::Cancelled, 0), video(VideoStatus::Cancelled, 40)];
+        assert_eq!(status_of(&cancelled), BatchStatus::Cancelled);
+
+        let failed = [video(VideoStatus::Failed, 0), video(VideoStatus::Cancelled, 0)];
+        assert_eq!(status_of(&failed), BatchStatus::Failed);
+    }
+
+    #[test]
+    fn progress_counts_finished_children_as_done() {
+        let videos = [
+            video(VideoStatus::Completed, 100),
+            video(VideoStatus::Failed, 10),
+            video(VideoStatus::InProgress, 40),
+            video(VideoStatus::PendingSubmission, 0),
+        ];
+        assert_eq!(aggregate_progress(&videos), 60);
+        assert_eq!(aggregate_progress(&[]), 0);
+    }
+}
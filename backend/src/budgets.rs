@@ -0,0 +1,342 @@
+use crate::db::{get_db, now_datetime};
+use crate::error::AppError;
+use crate::models::{BudgetPeriodStatus, BudgetResponse, UserBudget};
+use crate::notifications;
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use serde::Deserialize;
+use worker::{console_log, wasm_bindgen::JsValue, Env};
+
+const ALERT_THRESHOLDS: [i64; 3] = [50, 80, 100];
+
+#[derive(Debug, Clone, Copy)]
+enum BudgetPeriod {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl BudgetPeriod {
+    const ALL: [BudgetPeriod; 3] = [BudgetPeriod::Daily, BudgetPeriod::Weekly, BudgetPeriod::Monthly];
+
+    fn name(&self) -> &'static str {
+        match self {
+            BudgetPeriod::Daily => "daily",
+            BudgetPeriod::Weekly => "weekly",
+            BudgetPeriod::Monthly => "monthly",
+        }
+    }
+
+    fn noun(&self) -> &'static str {
+        match self {
+            BudgetPeriod::Daily => "day",
+            BudgetPeriod::Weekly => "week",
+            BudgetPeriod::Monthly => "month",
+        }
+    }
+
+    fn limit(&self, budget: &UserBudget) -> Option<i64> {
+        match self {
+            BudgetPeriod::Daily => budget.daily_limit,
+            BudgetPeriod::Weekly => budget.weekly_limit,
+            BudgetPeriod::Monthly => budget.monthly_limit,
+        }
+    }
+
+    fn start(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let today = Utc
+            .with_ymd_and_hms(now.year(), now.month(), now.day(), 0, 0, 0)
+            .single()
+            .unwrap_or(now);
+
+        match self {
+            BudgetPeriod::Daily => today,
+            BudgetPeriod::Weekly => today - Duration::days(now.weekday().num_days_from_monday() as i64),
+            BudgetPeriod::Monthly => Utc
+                .with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+                .single()
+                .unwrap_or(today),
+        }
+    }
+
+    fn end(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let start = self.start(now);
+
+        match self {
+            BudgetPeriod::Daily => start + Duration::days(1),
+            BudgetPeriod::Weekly => start + Duration::days(7),
+            BudgetPeriod::Monthly => {
+                let (year, month) = if now.month() == 12 {
+                    (now.year() + 1, 1)
+                } else {
+                    (now.year(), now.month() + 1)
+                };
+                Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0)
+                    .single()
+                    .unwrap_or(start + Duration::days(31))
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SumResult {
+    total: Option<f64>,
+}
+
+pub async fn get_budget(env: &Env, user_id: &str) -> Result<Option<UserBudget>, AppError> {
+    let db = get_db(env)?;
+
+    db.prepare("SELECT user_id, daily_limit, weekly_limit, monthly_limit, updated_at FROM user_budgets WHERE user_id = ?")
+        .bind(&[user_id.into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))
+}
+
+pub async fn save_budget(env: &Env, budget: &UserBudget) -> Result<(), AppError> {
+    let db = get_db(env)?;
+
+    let to_js = |limit: Option<i64>| limit.map(|l| JsValue::from_f64(l as f64)).unwrap_or(JsValue::NULL);
+
+    db.prepare("INSERT INTO user_budgets (user_id, daily_limit, weekly_limit, monthly_limit, updated_at) VALUES (?, ?, ?, ?, ?) ON CONFLICT(user_id) DO UPDATE SET daily_limit = excluded.daily_limit, weekly_limit = excluded.weekly_limit, monthly_limit = excluded.monthly_limit, updated_at = excluded.updated_at")
+        .bind(&[
+            budget.user_id.clone().into(),
+            to_js(budget.daily_limit),
+            to_js(budget.weekly_limit),
+            to_js(budget.monthly_limit),
+            budget.updated_at.to_rfc3339().into(),
+        ])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+async fn get_spent_since(env: &Env, user_id: &str, since: DateTime<Utc>) -> Result<i64, AppError> {
+    let db = get_db(env)?;
+
+    let result: Option<SumResult> = db
+        .prepare("SELECT SUM(amount) as total FROM credit_transactions WHERE user_id = ? AND org_id IS NULL AND transaction_type IN ('video_generation', 'prompt_enhancement', 'refund') AND created_at >= ?")
+        .bind(&[user_id.into(), since.to_rfc3339().into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok((-result.and_then(|r| r.total).unwrap_or(0.0) as i64).max(0))
+}
+
+/// SQL condition that holds while spending `amount` more keeps the user
+/// within every budget they've set, with the values for its placeholders.
+/// Used as a guard on the charge itself, so parallel requests can't each pass
+/// `check_budget` and overspend together.
+pub async fn budget_condition(env: &Env, user_id: &str, amount: i64) -> Result<(String, Vec<JsValue>), AppError> {
+    let Some(budget) = get_budget(env, user_id).await? else {
+        return Ok(("1".into(), Vec::new()));
+    };
+
+    let now = now_datetime();
+    let mut conditions = vec!["1".to_string()];
+    let mut bindings = Vec::new();
+
+    for period in BudgetPeriod::ALL {
+        let Some(limit) = period.limit(&budget) else {
+            continue;
+        };
+
+        conditions.push("(SELECT MAX(COALESCE(-SUM(amount), 0), 0) FROM credit_transactions WHERE user_id = ? AND org_id IS NULL AND transaction_type IN ('video_generation', 'prompt_enhancement', 'refund') AND created_at >= ?) + ? <= ?".into());
+        bindings.extend([
+            JsValue::from_str(user_id),
+            JsValue::from_str(&period.start(now).to_rfc3339()),
+            JsValue::from_f64(amount as f64),
+            JsValue::from_f64(limit as f64),
+        ]);
+    }
+
+    Ok((conditions.join(" AND "), bindings))
+}
+
+pub async fn get_budget_status(env: &Env, user_id: &str) -> Result<BudgetResponse, AppError> {
+    let mut response = BudgetResponse {
+        daily: None,
+        weekly: None,
+        monthly: None,
+    };
+
+    let Some(budget) = get_budget(env, user_id).await? else {
+        return Ok(response);
+    };
+
+    let now = now_datetime();
+
+    for period in BudgetPeriod::ALL {
+        let Some(limit) = period.limit(&budget) else {
+            continue;
+        };
+
+        let spent = get_spent_since(env, user_id, period.start(now)).await?;
+        let status = BudgetPeriodStatus {
+            limit,
+            spent,
+            remaining: (limit - spent).max(0),
+            resets_at: period.end(now),
+        };
+
+        match period {
+            BudgetPeriod::Daily => response.daily = Some(status),
+            BudgetPeriod::Weekly => response.weekly = Some(status),
+            BudgetPeriod::Monthly => response.monthly = Some(status),
+        }
+    }
+
+    Ok(response)
+}
+
+pub async fn check_budget(env: &Env, user_id: &str, amount: i64) -> Result<(), AppError> {
+    let Some(budget) = get_budget(env, user_id).await? else {
+        return Ok(());
+    };
+
+    let now = now_datetime();
+
+    for period in BudgetPeriod::ALL {
+        let Some(limit) = period.limit(&budget) else {
+            continue;
+        };
+
+        let spent = get_spent_since(env, user_id, period.start(now)).await?;
+
+        if spent + amount > limit {
+            return Err(AppError::BudgetExceeded(format!(
+                "This video costs {} credits, but only {} of your {} budget of {} credits remain. It resets at {}.",
+                amount,
+                (limit - spent).max(0),
+                period.name(),
+                limit,
+                period.end(now).to_rfc3339()
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn record_budget_alerts(env: &Env, user_id: &str) -> Result<(), AppError> {
+    let Some(budget) = get_budget(env, user_id).await? else {
+        return Ok(());
+    };
+
+    let db = get_db(env)?;
+    let now = now_datetime();
+
+    for period in BudgetPeriod::ALL {
+        let Some(limit) = period.limit(&budget).filter(|l| *l > 0) else {
+            continue;
+        };
+
+        let period_start = period.start(now).to_rfc3339();
+        let spent = get_spent_since(env, user_id, period.start(now)).await?;
+        let percent_used = spent * 100 / limit;
+
+        for threshold in ALERT_THRESHOLDS.iter().filter(|t| percent_used >= **t) {
+            let inserted = db
+                .prepare("INSERT OR IGNORE INTO budget_alerts (user_id, period, period_start, threshold, created_at) VALUES (?, ?, ?, ?, ?)")
+                .bind(&[
+                    user_id.into(),
+                    period.name().into(),
+                    period_start.clone().into(),
+                    (*threshold as f64).into(),
+                    now.to_rfc3339().into(),
+                ])?
+                .run()
+                .await
+                .map_err(|e| AppError::DatabaseError(e.to_string()))?
+                .meta()?
+                .and_then(|meta| meta.changes)
+                .unwrap_or(0);
+
+            if inserted == 0 {
+                continue;
+            }
+
+            console_log!("Budget alert: user={}, period={}, threshold={}%", user_id, period.name(), threshold);
+
+            let title = if *threshold >= 100 {
+                format!("You've reached your {} budget", period.name())
+            } else {
+                format!("{}% of your {} budget used", threshold, period.name())
+            };
+
+            notifications::create_notification(
+                env,
+                user_id,
+                "budget_alert",
+                &title,
+                &format!(
+                    "You've spent {} of {} credits this {}. It resets at {}.",
+                    spent,
+                    limit,
+                    period.noun(),
+                    period.end(now).to_rfc3339()
+                ),
+                Some(serde_json::json!({
+                    "period": period.name(),
+                    "threshold": threshold,
+                    "limit": limit,
+                    "spent": spent,
+                })),
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(year: i32, month: u32, day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, 30, 0).unwrap()
+    }
+
+    fn midnight(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, 0, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn daily_period_covers_the_utc_day() {
+        let now = at(2026, 3, 18, 23);
+        assert_eq!(BudgetPeriod::Daily.start(now), midnight(2026, 3, 18));
+        assert_eq!(BudgetPeriod::Daily.end(now), midnight(2026, 3, 19));
+    }
+
+    #[test]
+    fn weekly_period_starts_on_monday() {
+        // 2026-03-18 is a Wednesday.
+        let now = at(2026, 3, 18, 12);
+        assert_eq!(BudgetPeriod::Weekly.start(now), midnight(2026, 3, 16));
+        assert_eq!(BudgetPeriod::Weekly.end(now), midnight(2026, 3, 23));
+
+        // Monday starts its own week and Sunday still belongs to the previous one.
+        assert_eq!(BudgetPeriod::Weekly.start(at(2026, 3, 16, 0)), midnight(2026, 3, 16));
+        assert_eq!(BudgetPeriod::Weekly.start(at(2026, 3, 22, 23)), midnight(2026, 3, 16));
+
+        // Weeks can span a month and a year boundary.
+        let new_year = at(2027, 1, 1, 8);
+        assert_eq!(BudgetPeriod::Weekly.start(new_year), midnight(2026, 12, 28));
+        assert_eq!(BudgetPeriod::Weekly.end(new_year), midnight(2027, 1, 4));
+    }
+
+    #[test]
+    fn monthly_period_rolls_over_in_december() {
+        let now = at(2026, 12, 31, 23);
+        assert_eq!(BudgetPeriod::Monthly.start(now), midnight(2026, 12, 1));
+        assert_eq!(BudgetPeriod::Monthly.end(now), midnight(2027, 1, 1));
+
+        let february = at(2028, 2, 29, 6);
+        assert_eq!(BudgetPeriod::Monthly.start(february), midnight(2028, 2, 1));
+        assert_eq!(BudgetPeriod::Monthly.end(february), midnight(2028, 3, 1));
+    }
+}
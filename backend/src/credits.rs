@@ -1,77 +1,280 @@
-use crate::db;
+use crate::budgets;
+use crate::db::{self, get_db, now_rfc3339};
 use crate::error::AppError;
-use worker::Env;
-use chrono::DateTime;
+use crate::models::{OrgRole, User, Video, VideoStatus};
+use crate::organizations;
+use crate::rate_limit::{self, GiftLimits};
+use worker::{wasm_bindgen::JsValue, Env};
+use serde::Deserialize;
 
-fn now_rfc3339() -> String {
-    let ms = worker::Date::now().as_millis();
-    let secs = (ms / 1000) as i64;
-    let nsecs = ((ms % 1000) * 1_000_000) as u32;
-    DateTime::from_timestamp(secs, nsecs)
-        .unwrap_or_else(|| DateTime::from_timestamp(0, 0).unwrap())
-        .to_rfc3339()
+async fn check_member_cap(env: &Env, org_id: &str, user_id: &str, amount: i64) -> Result<(), AppError> {
+    let member = organizations::require_role(env, org_id, user_id, OrgRole::Member).await?;
+
+    if let Some(cap) = member.monthly_credit_cap {
+        let spent = organizations::get_member_monthly_spend(env, org_id, user_id).await?;
+        if spent + amount > cap {
+            return Err(AppError::BadRequest(format!(
+                "This would exceed your monthly organization cap of {} credits ({} used this month)",
+                cap, spent
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// One ledger entry taken by `reserve_credits`.
+pub struct Charge<'a> {
+    pub video_id: Option<&'a str>,
+    pub amount: i64,
+    pub transaction_type: &'static str,
+    pub description: &'static str,
+}
+
+impl<'a> Charge<'a> {
+    pub fn video(video_id: &'a str, amount: i64) -> Self {
+        Self {
+            video_id: Some(video_id),
+            amount,
+            transaction_type: "video_generation",
+            description: "Video generation cost",
+        }
+    }
+
+    pub fn prompt_enhancement(video_id: Option<&'a str>, amount: i64) -> Self {
+        Self {
+            video_id,
+            amount,
+            transaction_type: "prompt_enhancement",
+            description: "Prompt enhancement",
+        }
+    }
 }
 
-pub async fn deduct_credits_with_lock(
+/// Takes several charges in a single D1 batch, so either all of them are paid
+/// for or none are. The balance check is a guard on the first ledger insert
+/// and the debit is relative, so concurrent requests can't spend the same
+/// credits twice. Personal charges are also guarded by the user's spending
+/// budgets. Each video gets its own ledger entry, which lets `refund_credits`
+/// refund failed videos one at a time.
+pub async fn reserve_credits(
     env: &Env,
     user_id: &str,
-    video_id: &str,
-    amount: i64,
+    org_id: Option<&str>,
+    charges: &[Charge<'_>],
 ) -> Result<i64, AppError> {
+    let total: i64 = charges.iter().map(|charge| charge.amount).sum();
+    let videos = charges.iter().filter(|charge| charge.transaction_type == "video_generation").count();
+
+    if charges.is_empty() {
+        return ensure_balance(env, user_id, org_id, 0).await;
+    }
+
+    if let Some(org_id) = org_id {
+        check_member_cap(env, org_id, user_id, total).await?;
+    }
+
     let database = env
         .d1("DB")
         .map_err(|e| AppError::InternalError(format!("Failed to get DB: {}", e)))?;
 
-    database
-        .prepare("INSERT INTO user_locks (user_id) VALUES (?)")
-        .bind(&[user_id.into()])?
-        .run()
-        .await
-        .map_err(|_| AppError::ConcurrentGeneration)?;
+    let (account_table, account_id) = match org_id {
+        Some(org_id) => ("organizations", org_id),
+        None => ("users", user_id),
+    };
+    let org_value = org_id.map(JsValue::from_str).unwrap_or(JsValue::NULL);
+    let (budget_guard, budget_bindings) = match org_id {
+        Some(_) => ("1".to_string(), Vec::new()),
+        None => budgets::budget_condition(env, user_id, total).await?,
+    };
+    let first_transaction_id = uuid::Uuid::new_v4().to_string();
+    let reserved = "EXISTS (SELECT 1 FROM credit_transactions WHERE id = ?)";
+    let now = now_rfc3339();
 
-    let user = db::get_user_by_id(env, user_id).await?;
+    let mut statements = Vec::with_capacity(charges.len() + 2);
+    let mut charged = 0;
 
-    if user.credits_balance < amount {
-        release_lock(env, user_id).await?;
-        return Err(AppError::InsufficientCredits);
+    for (index, charge) in charges.iter().enumerate() {
+        charged += charge.amount;
+
+        let (transaction_id, guard, guard_values) = if index == 0 {
+            let mut values = vec![JsValue::from_f64(total as f64)];
+            values.extend(budget_bindings.iter().cloned());
+            (first_transaction_id.clone(), format!("credits_balance >= ? AND {}", budget_guard), values)
+        } else {
+            (uuid::Uuid::new_v4().to_string(), reserved.to_string(), vec![JsValue::from_str(&first_transaction_id)])
+        };
+
+        let mut bindings: Vec<JsValue> = vec![
+            transaction_id.into(),
+            user_id.into(),
+            (-charge.amount as f64).into(),
+            (charged as f64).into(),
+            charge.transaction_type.into(),
+            charge.description.into(),
+            charge.video_id.map(JsValue::from_str).unwrap_or(JsValue::NULL),
+            org_value.clone(),
+            now.clone().into(),
+            account_id.into(),
+        ];
+        bindings.extend(guard_values);
+
+        statements.push(
+            database
+                .prepare(format!("INSERT INTO credit_transactions (id, user_id, amount, balance_after, transaction_type, description, video_id, org_id, created_at) SELECT ?, ?, ?, credits_balance - ?, ?, ?, ?, ?, ? FROM {} WHERE id = ? AND {}", account_table, guard))
+                .bind(&bindings)?,
+        );
     }
 
-    let new_balance = user.credits_balance - amount;
+    statements.push(
+        database
+            .prepare(format!("UPDATE {} SET credits_balance = credits_balance - ?, updated_at = ? WHERE id = ? AND {}", account_table, reserved))
+            .bind(&[
+                (total as f64).into(),
+                now.clone().into(),
+                account_id.into(),
+                first_transaction_id.clone().into(),
+            ])?,
+    );
+
+    if videos > 0 {
+        statements.push(
+            database
+                .prepare(format!("UPDATE users SET total_videos_generated = total_videos_generated + ?, updated_at = ? WHERE id = ? AND {}", reserved))
+                .bind(&[
+                    (videos as f64).into(),
+                    now.into(),
+                    user_id.into(),
+                    first_transaction_id.into(),
+                ])?,
+        );
+    }
 
-    database
-        .prepare("UPDATE users SET credits_balance = ?, total_videos_generated = total_videos_generated + 1, updated_at = ? WHERE id = ?")
-        .bind(&[(new_balance as f64).into(), now_rfc3339().into(), user_id.into()])?
-        .run()
+    let results = database
+        .batch(statements)
         .await
         .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
-    db::insert_transaction(
-        env,
-        user_id,
-        -amount,
-        new_balance,
-        "video_generation",
-        "Video generation cost",
-        Some(video_id),
-        None,
-    )
-    .await?;
+    let reserved_rows = results
+        .first()
+        .and_then(|r| r.meta().ok().flatten())
+        .and_then(|meta| meta.changes)
+        .unwrap_or(0);
 
-    Ok(new_balance)
+    if reserved_rows == 0 {
+        if org_id.is_none() {
+            budgets::check_budget(env, user_id, total).await?;
+        }
+        return Err(AppError::InsufficientCredits);
+    }
+
+    current_balance(env, user_id, org_id).await
 }
 
-pub async fn release_lock(env: &Env, user_id: &str) -> Result<(), AppError> {
-    let db = env
-        .d1("DB")
-        .map_err(|e| AppError::InternalError(format!("Failed to get DB: {}", e)))?;
+async fn current_balance(env: &Env, user_id: &str, org_id: Option<&str>) -> Result<i64, AppError> {
+    match org_id {
+        Some(org_id) => Ok(organizations::get_organization(env, org_id).await?.credits_balance),
+        None => Ok(db::get_user_by_id(env, user_id).await?.credits_balance),
+    }
+}
 
-    db.prepare("DELETE FROM user_locks WHERE user_id = ?")
-        .bind(&[user_id.into()])?
-        .run()
+/// Fails with `InsufficientCredits` unless the user's balance (or the
+/// organization's) covers `amount`. Returns the current balance.
+pub async fn ensure_balance(env: &Env, user_id: &str, org_id: Option<&str>, amount: i64) -> Result<i64, AppError> {
+    let balance = match org_id {
+        Some(org_id) => {
+            organizations::require_role(env, org_id, user_id, OrgRole::Member).await?;
+            organizations::get_organization(env, org_id).await?.credits_balance
+        }
+        None => db::get_user_by_id(env, user_id).await?.credits_balance,
+    };
+
+    if balance < amount {
+        return Err(AppError::InsufficientCredits);
+    }
+
+    Ok(balance)
+}
+
+#[derive(Deserialize)]
+struct BalanceRow {
+    credits_balance: f64,
+}
+
+/// Adds `amount` to the user's balance, or to the organization's when
+/// `org_id` is set, and records it in the ledger. The ledger row and a
+/// relative balance update go in one D1 batch, so concurrent writers can't
+/// overwrite each other. A video is only ever refunded once: returns `None`
+/// if `video_id` already has a refund (or the account doesn't exist).
+#[allow(clippy::too_many_arguments)]
+async fn credit_account(
+    env: &Env,
+    user_id: &str,
+    org_id: Option<&str>,
+    amount: i64,
+    transaction_type: &str,
+    description: &str,
+    video_id: Option<&str>,
+    apple_transaction_id: Option<&str>,
+) -> Result<Option<i64>, AppError> {
+    let database = get_db(env)?;
+
+    let (account_table, account_id) = match org_id {
+        Some(org_id) => ("organizations", org_id),
+        None => ("users", user_id),
+    };
+    let transaction_id = uuid::Uuid::new_v4().to_string();
+    let now = now_rfc3339();
+    let optional = |value: Option<&str>| value.map(JsValue::from_str).unwrap_or(JsValue::NULL);
+
+    let mut bindings: Vec<JsValue> = vec![
+        transaction_id.clone().into(),
+        user_id.into(),
+        (amount as f64).into(),
+        (amount as f64).into(),
+        transaction_type.into(),
+        description.into(),
+        optional(video_id),
+        optional(apple_transaction_id),
+        optional(org_id),
+        now.clone().into(),
+        account_id.into(),
+    ];
+    let mut guard = "";
+    if let (Some(video_id), "refund") = (video_id, transaction_type) {
+        guard = " AND NOT EXISTS (SELECT 1 FROM credit_transactions WHERE video_id = ? AND transaction_type = 'refund')";
+        bindings.push(video_id.into());
+    }
+
+    let statements = vec![
+        database
+            .prepare(format!("INSERT INTO credit_transactions (id, user_id, amount, balance_after, transaction_type, description, video_id, revenuecat_transaction_id, org_id, created_at) SELECT ?, ?, ?, credits_balance + ?, ?, ?, ?, ?, ?, ? FROM {} WHERE id = ?{}", account_table, guard))
+            .bind(&bindings)?,
+        database
+            .prepare(format!("UPDATE {} SET credits_balance = credits_balance + ?, updated_at = ? WHERE id = ? AND EXISTS (SELECT 1 FROM credit_transactions WHERE id = ?) RETURNING credits_balance", account_table))
+            .bind(&[
+                (amount as f64).into(),
+                now.into(),
+                account_id.into(),
+                transaction_id.into(),
+            ])?,
+    ];
+
+    let results = database
+        .batch(statements)
         .await
         .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
-    Ok(())
+    let row = match results.get(1) {
+        Some(result) => result
+            .results::<BalanceRow>()
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?
+            .into_iter()
+            .next(),
+        None => None,
+    };
+
+    Ok(row.map(|r| r.credits_balance as i64))
 }
 
 pub async fn add_credits(
@@ -81,83 +284,312 @@ pub async fn add_credits(
     description: &str,
     apple_transaction_id: Option<&str>,
 ) -> Result<i64, AppError> {
-    let user = db::get_user_by_id(env, user_id).await?;
-    let new_balance = user.credits_balance + amount;
+    credit_account(env, user_id, None, amount, "purchase", description, None, apple_transaction_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".into()))
+}
 
-    let db = env
-        .d1("DB")
-        .map_err(|e| AppError::InternalError(format!("Failed to get DB: {}", e)))?;
+pub async fn add_org_credits(
+    env: &Env,
+    org_id: &str,
+    user_id: &str,
+    amount: i64,
+    description: &str,
+    apple_transaction_id: Option<&str>,
+) -> Result<i64, AppError> {
+    credit_account(env, user_id, Some(org_id), amount, "purchase", description, None, apple_transaction_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Organization not found".into()))
+}
 
-    db.prepare("UPDATE users SET credits_balance = ?, updated_at = ? WHERE id = ?")
-        .bind(&[
-            (new_balance as f64).into(),
-            now_rfc3339().into(),
-            user_id.into(),
-        ])?
-        .run()
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+#[derive(Deserialize)]
+struct ChargeRecord {
+    org_id: Option<String>,
+    amount: i64,
+}
 
-    db::insert_transaction(
+/// The account that paid for a video's charges and how much they add up to.
+/// Charges are stored as negative amounts.
+fn charges_refund(charges: &[ChargeRecord]) -> (Option<String>, i64) {
+    let org_id = charges.iter().find_map(|charge| charge.org_id.clone());
+    let amount = -charges.iter().map(|charge| charge.amount).sum::<i64>();
+    (org_id, amount)
+}
+
+pub async fn refund_credits(
+    env: &Env,
+    user_id: &str,
+    video_id: &str,
+    amount: i64,
+) -> Result<i64, AppError> {
+    refund_credits_with_description(
         env,
         user_id,
+        video_id,
         amount,
-        new_balance,
-        "purchase",
-        description,
-        None,
-        apple_transaction_id,
+        "Video generation failed - credits refunded",
     )
-    .await?;
-
-    Ok(new_balance)
+    .await
 }
 
-pub async fn refund_credits(
+/// Refunds a video to whichever account paid for it. Refunding the same
+/// video again does nothing and returns the current balance.
+pub async fn refund_credits_with_description(
     env: &Env,
     user_id: &str,
     video_id: &str,
     amount: i64,
+    description: &str,
 ) -> Result<i64, AppError> {
-    let db = env
-        .d1("DB")
-        .map_err(|e| AppError::InternalError(format!("Failed to get DB: {}", e)))?;
+    let charge: Option<ChargeRecord> = get_db(env)?
+        .prepare("SELECT org_id, amount FROM credit_transactions WHERE video_id = ? AND transaction_type = 'video_generation' LIMIT 1")
+        .bind(&[video_id.into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    let org_id = charge.and_then(|c| c.org_id);
+
+    match credit_account(env, user_id, org_id.as_deref(), amount, "refund", description, Some(video_id), None).await? {
+        Some(new_balance) => Ok(new_balance),
+        None => current_balance(env, user_id, org_id.as_deref()).await,
+    }
+}
 
-    let existing_refund = db
-        .prepare("SELECT id FROM credit_transactions WHERE video_id = ? AND transaction_type = 'refund' LIMIT 1")
+/// Refunds everything charged for a failed video, its generation and any
+/// prompt enhancement, as one ledger entry to the account that paid. Returns
+/// the amount refunded, or 0 if the video had already been refunded.
+pub async fn refund_video_charges(
+    env: &Env,
+    user_id: &str,
+    video_id: &str,
+    description: &str,
+) -> Result<i64, AppError> {
+    let charges: Vec<ChargeRecord> = get_db(env)?
+        .prepare("SELECT org_id, amount FROM credit_transactions WHERE video_id = ? AND transaction_type IN ('video_generation', 'prompt_enhancement')")
         .bind(&[video_id.into()])?
-        .first::<String>(None)
-        .await;
+        .all()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .results()
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    let (org_id, amount) = charges_refund(&charges);
+    if amount <= 0 {
+        return Ok(0);
+    }
+
+    match credit_account(env, user_id, org_id.as_deref(), amount, "refund", description, Some(video_id), None).await? {
+        Some(_) => Ok(amount),
+        None => Ok(0),
+    }
+}
+
+pub struct CancellationRefundPolicy {
+    partial_refund_percent: i64,
+    no_refund_after_progress: i32,
+}
+
+impl CancellationRefundPolicy {
+    pub fn from_env(env: &Env) -> Self {
+        let partial_refund_percent = env
+            .var("CANCEL_PARTIAL_REFUND_PERCENT")
+            .map(|v| v.to_string().parse::<i64>().unwrap_or(50))
+            .unwrap_or(50)
+            .clamp(0, 100);
 
-    if let Ok(Some(_)) = existing_refund {
-        let user = db::get_user_by_id(env, user_id).await?;
-        return Ok(user.credits_balance);
+        let no_refund_after_progress = env
+            .var("CANCEL_NO_REFUND_AFTER_PROGRESS")
+            .map(|v| v.to_string().parse::<i32>().unwrap_or(80))
+            .unwrap_or(80);
+
+        Self {
+            partial_refund_percent,
+            no_refund_after_progress,
+        }
+    }
+
+    pub fn refund_for(&self, video: &Video) -> i64 {
+        match video.status {
+            VideoStatus::PendingSubmission | VideoStatus::Queued => video.credits_cost,
+            VideoStatus::InProgress if video.progress < self.no_refund_after_progress => {
+                video.credits_cost * self.partial_refund_percent / 100
+            }
+            _ => 0,
+        }
     }
+}
+
+// Net of every personal ledger entry that moves giftable credits. Spending
+// is charged to bought and received credits first, so welcome or promotional
+// credits never become giftable.
+const GIFTABLE_SQL: &str = "SELECT COALESCE(SUM(amount), 0) FROM credit_transactions WHERE user_id = ? AND org_id IS NULL \
+AND transaction_type IN ('purchase', 'gift_received', 'gift_sent', 'video_generation', 'prompt_enhancement', 'refund')";
 
+#[derive(Deserialize)]
+struct GiftableRow {
+    giftable: f64,
+}
+
+pub async fn get_giftable_credits(env: &Env, user_id: &str) -> Result<i64, AppError> {
     let user = db::get_user_by_id(env, user_id).await?;
-    let new_balance = user.credits_balance + amount;
 
-    db.prepare("UPDATE users SET credits_balance = ?, updated_at = ? WHERE id = ?")
-        .bind(&[
-            (new_balance as f64).into(),
-            now_rfc3339().into(),
-            user_id.into(),
-        ])?
-        .run()
+    let row: Option<GiftableRow> = get_db(env)?
+        .prepare(format!("SELECT ({}) AS giftable", GIFTABLE_SQL))
+        .bind(&[user_id.into()])?
+        .first(None)
         .await
         .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
-    db::insert_transaction(
-        env,
-        user_id,
-        amount,
-        new_balance,
-        "refund",
-        "Video generation failed - credits refunded",
-        Some(video_id),
-        None,
-    )
-    .await?;
+    let giftable = row.map(|r| r.giftable as i64).unwrap_or(0);
+    Ok(giftable.min(user.credits_balance).max(0))
+}
+
+/// Fails unless `amount` is covered by the user's giftable credits.
+pub async fn ensure_giftable(env: &Env, user_id: &str, amount: i64) -> Result<(), AppError> {
+    let giftable = get_giftable_credits(env, user_id).await?;
+
+    if amount > giftable {
+        return Err(AppError::BadRequest(format!(
+            "Only purchased or received credits that haven't been spent can be gifted. You can gift up to {} credits.",
+            giftable
+        )));
+    }
+
+    Ok(())
+}
+
+pub async fn gift_credits(
+    env: &Env,
+    sender: &User,
+    recipient: &User,
+    amount: i64,
+    message: Option<&str>,
+    limits: &GiftLimits,
+) -> Result<i64, AppError> {
+    let database = get_db(env)?;
+
+    let gift_id = uuid::Uuid::new_v4().to_string();
+    let sent_transaction_id = uuid::Uuid::new_v4().to_string();
+    let received_transaction_id = uuid::Uuid::new_v4().to_string();
+    let now = now_rfc3339();
+
+    let sent_metadata = serde_json::json!({
+        "gift_id": gift_id,
+        "recipient_referral_code": recipient.referral_code,
+        "message": message,
+    })
+    .to_string();
+
+    let received_metadata = serde_json::json!({
+        "gift_id": gift_id,
+        "sender_referral_code": sender.referral_code,
+        "message": message,
+    })
+    .to_string();
+
+    let received_description = match message {
+        Some(message) => format!("Gift received: {}", message),
+        None => "Gift received".to_string(),
+    };
+
+    let sent_exists = "EXISTS (SELECT 1 FROM credit_transactions WHERE id = ?)";
 
-    Ok(new_balance)
+    // The balance, giftable credits and daily limits are all checked by the
+    // statement that records the gift, so concurrent gifts can't overshoot.
+    let mut sent_bindings: Vec<JsValue> = vec![
+        sent_transaction_id.clone().into(),
+        (-amount as f64).into(),
+        (amount as f64).into(),
+        "Gift sent".into(),
+        sent_metadata.into(),
+        now.clone().into(),
+        sender.id.clone().into(),
+        (amount as f64).into(),
+        sender.id.clone().into(),
+        (amount as f64).into(),
+    ];
+    sent_bindings.extend(limits.bindings(&sender.id, amount));
+
+    let statements = vec![
+        database
+            .prepare(format!("INSERT INTO credit_transactions (id, user_id, amount, balance_after, transaction_type, description, metadata, created_at) SELECT ?, id, ?, credits_balance - ?, 'gift_sent', ?, ?, ? FROM users WHERE id = ? AND credits_balance >= ? AND ({}) >= ? AND {}", GIFTABLE_SQL, GiftLimits::CONDITION_SQL))
+            .bind(&sent_bindings)?,
+        database
+            .prepare(format!("UPDATE users SET credits_balance = credits_balance - ?, updated_at = ? WHERE id = ? AND {}", sent_exists))
+            .bind(&[
+                (amount as f64).into(),
+                now.clone().into(),
+                sender.id.clone().into(),
+                sent_transaction_id.clone().into(),
+            ])?,
+        database
+            .prepare(format!("INSERT INTO credit_transactions (id, user_id, amount, balance_after, transaction_type, description, metadata, created_at) SELECT ?, id, ?, credits_balance + ?, 'gift_received', ?, ?, ? FROM users WHERE id = ? AND {}", sent_exists))
+            .bind(&[
+                received_transaction_id.into(),
+                (amount as f64).into(),
+                (amount as f64).into(),
+                received_description.into(),
+                received_metadata.into(),
+                now.clone().into(),
+                recipient.id.clone().into(),
+                sent_transaction_id.clone().into(),
+            ])?,
+        database
+            .prepare(format!("UPDATE users SET credits_balance = credits_balance + ?, updated_at = ? WHERE id = ? AND {}", sent_exists))
+            .bind(&[
+                (amount as f64).into(),
+                now.into(),
+                recipient.id.clone().into(),
+                sent_transaction_id.into(),
+            ])?,
+    ];
+
+    let results = database
+        .batch(statements)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    let debited = results
+        .first()
+        .and_then(|r| r.meta().ok().flatten())
+        .and_then(|meta| meta.changes)
+        .unwrap_or(0);
+
+    if debited == 0 {
+        rate_limit::check_gift_limit(env, &sender.id, amount).await?;
+        ensure_giftable(env, &sender.id, amount).await?;
+        return Err(AppError::InsufficientCredits);
+    }
+
+    let user = db::get_user_by_id(env, &sender.id).await?;
+    Ok(user.credits_balance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn charge(org_id: Option<&str>, amount: i64) -> ChargeRecord {
+        ChargeRecord {
+            org_id: org_id.map(String::from),
+            amount,
+        }
+    }
+
+    #[test]
+    fn refunds_the_video_and_enhancement_charges_together() {
+        let charges = [charge(None, -120), charge(None, -5)];
+        assert_eq!(charges_refund(&charges), (None, 125));
+    }
+
+    #[test]
+    fn refunds_to_the_organization_that_paid() {
+        let charges = [charge(Some("org_1"), -40), charge(Some("org_1"), -5)];
+        assert_eq!(charges_refund(&charges), (Some("org_1".to_string()), 45));
+    }
+
+    #[test]
+    fn nothing_charged_refunds_nothing() {
+        assert_eq!(charges_refund(&[]), (None, 0));
+    }
 }
@@ -0,0 +1,298 @@
+use serde::Serialize;
+use worker::Request;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Currency {
+    Usd,
+    Eur,
+    Gbp,
+    Jpy,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Money {
+    pub amount_minor: i64,
+    pub currency: &'static str,
+    pub formatted: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct NumberFormat {
+    decimal_separator: char,
+    group_separator: &'static str,
+    symbol_after: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PriceContext {
+    pub currency: Currency,
+    format: NumberFormat,
+}
+
+impl Currency {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Currency::Usd => "USD",
+            Currency::Eur => "EUR",
+            Currency::Gbp => "GBP",
+            Currency::Jpy => "JPY",
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Currency::Usd => "$",
+            Currency::Eur => "€",
+            Currency::Gbp => "£",
+            Currency::Jpy => "¥",
+        }
+    }
+
+    fn minor_digits(&self) -> u32 {
+        match self {
+            Currency::Jpy => 0,
+            _ => 2,
+        }
+    }
+
+    // Minor units of this currency worth 100 credits (1 credit = 1 US cent).
+    fn minor_per_100_credits(&self) -> i64 {
+        match self {
+            Currency::Usd => 100,
+            Currency::Eur => 92,
+            Currency::Gbp => 79,
+            Currency::Jpy => 150,
+        }
+    }
+
+    fn from_storefront(code: &str) -> Option<Self> {
+        match code.trim().to_ascii_uppercase().as_str() {
+            "USA" | "US" => Some(Currency::Usd),
+            "GBR" | "GB" => Some(Currency::Gbp),
+            "JPN" | "JP" => Some(Currency::Jpy),
+            "DEU" | "DE" | "FRA" | "FR" | "ESP" | "ES" | "ITA" | "IT" | "NLD" | "NL" | "AUT" | "AT"
+            | "BEL" | "BE" | "IRL" | "IE" | "FIN" | "FI" | "PRT" | "PT" | "GRC" | "GR" => {
+                Some(Currency::Eur)
+            }
+            _ => None,
+        }
+    }
+
+    fn from_language(language: &str) -> Option<Self> {
+        match language {
+            "ja" => Some(Currency::Jpy),
+            "de" | "fr" | "es" | "it" | "nl" | "pt" | "fi" | "el" => Some(Currency::Eur),
+            _ => None,
+        }
+    }
+}
+
+impl NumberFormat {
+    fn for_language(language: &str) -> Self {
+        match language {
+            "de" | "fr" | "es" | "it" | "nl" | "pt" | "fi" | "el" => NumberFormat {
+                decimal_separator: ',',
+                group_separator: if language == "fr" { "\u{202f}" } else { "." },
+                symbol_after: true,
+            },
+            _ => NumberFormat {
+                decimal_separator: '.',
+                group_separator: ",",
+                symbol_after: false,
+            },
+        }
+    }
+}
+
+impl PriceContext {
+    pub fn from_request(req: &Request) -> Self {
+        let accept_language = req.headers().get("Accept-Language").ok().flatten();
+        let storefront = req.headers().get("X-Storefront").ok().flatten();
+        Self::resolve(accept_language.as_deref(), storefront.as_deref())
+    }
+
+    pub fn resolve(accept_language: Option<&str>, storefront: Option<&str>) -> Self {
+        let (language, region) = accept_language
+            .and_then(primary_language_tag)
+            .unwrap_or_else(|| ("en".to_string(), None));
+
+        // A region decides the currency on its own (pt-BR is not EUR); the
+        // language only helps when the locale has no region.
+        let currency = storefront
+            .and_then(Currency::from_storefront)
+            .or_else(|| match region.as_deref() {
+                Some(region) => Currency::from_storefront(region),
+                None => Currency::from_language(&language),
+            })
+            .unwrap_or(Currency::Usd);
+
+        PriceContext {
+            currency,
+            format: NumberFormat::for_language(&language),
+        }
+    }
+
+    pub fn credits_equivalent(&self, credits: i64) -> Money {
+        let rate = self.currency.minor_per_100_credits();
+        let amount_minor = (credits * rate + 50).div_euclid(100);
+        self.money(amount_minor)
+    }
+
+    pub fn money(&self, amount_minor: i64) -> Money {
+        Money {
+            amount_minor,
+            currency: self.currency.code(),
+            formatted: self.format_minor(amount_minor),
+        }
+    }
+
+    fn format_minor(&self, amount_minor: i64) -> String {
+        let digits = self.currency.minor_digits();
+        let divisor = 10_i64.pow(digits);
+        let abs = amount_minor.unsigned_abs() as i64;
+        let major = group_thousands(abs / divisor, self.format.group_separator);
+
+        let number = if digits == 0 {
+            major
+        } else {
+            format!(
+                "{}{}{:0width$}",
+                major,
+                self.format.decimal_separator,
+                abs % divisor,
+                width = digits as usize
+            )
+        };
+
+        let sign = if amount_minor < 0 { "-" } else { "" };
+        let symbol = self.currency.symbol();
+
+        if self.format.symbol_after {
+            format!("{}{}\u{a0}{}", sign, number, symbol)
+        } else {
+            format!("{}{}{}", sign, symbol, number)
+        }
+    }
+}
+
+fn primary_language_tag(header: &str) -> Option<(String, Option<String>)> {
+    let tag = header
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (!tag.is_empty() && tag != "*").then_some((tag, quality))
+        })
+        .fold(None::<(&str, f32)>, |best, (tag, quality)| match best {
+            Some((_, best_quality)) if best_quality >= quality => best,
+            _ => Some((tag, quality)),
+        })?
+        .0;
+
+    let mut subtags = tag.split(['-', '_']);
+    let language = subtags.next()?.to_ascii_lowercase();
+    let region = subtags
+        .find(|s| s.len() == 2 && s.chars().all(|c| c.is_ascii_alphabetic()))
+        .map(|s| s.to_ascii_uppercase());
+
+    Some((language, region))
+}
+
+fn group_thousands(value: i64, separator: &str) -> String {
+    let digits = value.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3 * separator.len());
+
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push_str(separator);
+        }
+        grouped.push(c);
+    }
+
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_separators_and_symbol_placement() {
+        let en = PriceContext::resolve(None, None);
+        assert_eq!(en.money(123456).formatted, "$1,234.56");
+        assert_eq!(en.money(-250).formatted, "-$2.50");
+        assert_eq!(en.money(5).formatted, "$0.05");
+
+        let de = PriceContext::resolve(Some("de-DE,de;q=0.9"), None);
+        assert_eq!(de.currency, Currency::Eur);
+        assert_eq!(de.money(123456).formatted, "1.234,56\u{a0}€");
+
+        let fr = PriceContext::resolve(Some("fr-FR"), None);
+        assert_eq!(fr.money(123456789).formatted, "1\u{202f}234\u{202f}567,89\u{a0}€");
+    }
+
+    #[test]
+    fn zero_decimal_currencies_have_no_minor_part() {
+        let ja = PriceContext::resolve(Some("ja-JP"), None);
+        assert_eq!(ja.currency, Currency::Jpy);
+        assert_eq!(ja.money(150000).formatted, "¥150,000");
+        assert_eq!(ja.credits_equivalent(1000).formatted, "¥1,500");
+        // 1.5 yen rounds half up.
+        assert_eq!(ja.credits_equivalent(1).amount_minor, 2);
+    }
+
+    #[test]
+    fn converts_credits_with_rounding() {
+        let usd = PriceContext::resolve(None, None);
+        assert_eq!(usd.credits_equivalent(1234).amount_minor, 1234);
+        assert_eq!(usd.credits_equivalent(1234).currency, "USD");
+
+        let eur = PriceContext::resolve(Some("de"), None);
+        assert_eq!(eur.credits_equivalent(1000).amount_minor, 920);
+        assert_eq!(eur.credits_equivalent(1).amount_minor, 1);
+        assert_eq!(eur.credits_equivalent(1000).formatted, "9,20\u{a0}€");
+    }
+
+    #[test]
+    fn falls_back_from_storefront_to_region_then_language() {
+        // The storefront wins over the Accept-Language region.
+        assert_eq!(PriceContext::resolve(Some("en-US"), Some("JPN")).currency, Currency::Jpy);
+        // An unknown storefront falls back to the region.
+        assert_eq!(PriceContext::resolve(Some("de-AT"), Some("XYZ")).currency, Currency::Eur);
+        assert_eq!(PriceContext::resolve(Some("en-GB"), None).currency, Currency::Gbp);
+        // A region without a priced currency is USD, whatever the language.
+        assert_eq!(PriceContext::resolve(Some("pt-BR"), None).currency, Currency::Usd);
+        assert_eq!(PriceContext::resolve(Some("de-CH"), None).currency, Currency::Usd);
+        // Without a region, the language decides.
+        assert_eq!(PriceContext::resolve(Some("pt"), None).currency, Currency::Eur);
+        assert_eq!(PriceContext::resolve(Some("ja"), None).currency, Currency::Jpy);
+        // Script subtags are skipped and unknown locales end up in USD.
+        assert_eq!(PriceContext::resolve(Some("zh-Hant-TW"), None).currency, Currency::Usd);
+        assert_eq!(PriceContext::resolve(Some("*"), None).currency, Currency::Usd);
+        // The storefront picks the currency, the language still picks the format.
+        let gb_in_german = PriceContext::resolve(Some("de-DE"), Some("GBR"));
+        assert_eq!(gb_in_german.money(1999).formatted, "19,99\u{a0}£");
+    }
+
+    #[test]
+    fn picks_the_highest_quality_language() {
+        assert_eq!(
+            primary_language_tag("en-US;q=0.5, de-DE;q=0.9, *;q=1"),
+            Some(("de".to_string(), Some("DE".to_string())))
+        );
+        assert_eq!(primary_language_tag("ja_jp"), Some(("ja".to_string(), Some("JP".to_string()))));
+        assert_eq!(primary_language_tag(""), None);
+    }
+
+    #[test]
+    fn groups_thousands() {
+        assert_eq!(group_thousands(0, ","), "0");
+        assert_eq!(group_thousands(999, ","), "999");
+        assert_eq!(group_thousands(1000, ","), "1,000");
+        assert_eq!(group_thousands(1234567, "."), "1.234.567");
+    }
+}
@@ -1,10 +1,11 @@
 use crate::error::AppError;
-use crate::models::{User, Video, CreditTransaction};
-use worker::{Env, D1Database, wasm_bindgen::JsValue};
+use crate::library::VideoFilter;
+use crate::models::{User, Video, CreditTransaction, RemixSummary};
+use worker::{Env, D1Database, D1PreparedStatement, wasm_bindgen::JsValue};
 use chrono::{DateTime, Utc};
 use serde::Deserialize;
 
-fn now_datetime() -> DateTime<Utc> {
+pub fn now_datetime() -> DateTime<Utc> {
     let ms = worker::Date::now().as_millis();
     let secs = (ms / 1000) as i64;
     let nsecs = ((ms % 1000) * 1_000_000) as u32;
@@ -12,15 +13,33 @@ fn now_datetime() -> DateTime<Utc> {
         .unwrap_or_else(|| DateTime::from_timestamp(0, 0).unwrap())
 }
 
-fn now_rfc3339() -> String {
+pub fn now_rfc3339() -> String {
     now_datetime().to_rfc3339()
 }
 
+pub fn get_db(env: &Env) -> Result<D1Database, AppError> {
+    env.d1("DB")
+        .map_err(|e| AppError::InternalError(format!("Failed to get DB: {}", e)))
+}
+
+pub const VIDEO_COLUMNS: &str = "id, user_id, openai_video_id, status, model, prompt, size, seconds, video_url, thumbnail_url, spritesheet_url, download_url_expires_at, credits_cost, progress, created_at, completed_at, failed_at, cancelled_at, error_message, org_id, reference_image_id, parent_video_id, batch_id, template_id, template_variables, original_prompt, started_at, storage_key, storage_size, storage_checksum, favorite";
+
+pub const TRANSACTION_COLUMNS: &str = "id, user_id, amount, balance_after, transaction_type, description, video_id, revenuecat_transaction_id, metadata, org_id, created_at";
+
+const USER_COLUMNS: &str = "id, apple_user_id, email, referral_code, credits_balance, total_videos_generated, created_at, updated_at";
+
 #[derive(Deserialize)]
 struct CountResult {
     count: f64,
 }
 
+#[derive(Deserialize)]
+struct AggregateResult {
+    count: f64,
+    total: Option<f64>,
+}
+
+
 pub async fn get_or_create_user(
     env: &Env,
     apple_user_id: &str,
@@ -29,23 +48,14 @@ pub async fn get_or_create_user(
     let db = get_db(env)?;
 
     let existing = db
-        .prepare("SELECT id, apple_user_id, email, credits_balance, total_videos_generated, created_at, updated_at FROM users WHERE apple_user_id = ?")
+        .prepare(format!("SELECT {} FROM users WHERE apple_user_id = ?", USER_COLUMNS))
         .bind(&[apple_user_id.into()])?
         .first::<serde_json::Value>(None)
         .await
         .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
     if let Some(user_data) = existing {
-        let user = User {
-            id: user_data.get("id").and_then(|v| v.as_str()).unwrap_or("").to_string(),
-            apple_user_id: user_data.get("apple_user_id").and_then(|v| v.as_str()).unwrap_or("").to_string(),
-            email: user_data.get("email").and_then(|v| v.as_str()).map(|s| s.to_string()),
-            credits_balance: user_data.get("credits_balance").and_then(|v| v.as_f64()).unwrap_or(0.0) as i64,
-            total_videos_generated: user_data.get("total_videos_generated").and_then(|v| v.as_f64()).unwrap_or(0.0) as i64,
-            created_at: user_data.get("created_at").and_then(|v| v.as_str()).and_then(|s| s.parse().ok()).unwrap_or_else(|| now_datetime()),
-            updated_at: user_data.get("updated_at").and_then(|v| v.as_str()).and_then(|s| s.parse().ok()).unwrap_or_else(|| now_datetime()),
-        };
-        return Ok((user, false));
+        return Ok((user_from_row(&user_data), false));
     }
 
     use crate::pricing::WELCOME_CREDITS;
@@ -57,11 +67,12 @@ pub async fn get_or_create_user(
     let created_at_str = user.created_at.to_rfc3339();
     let updated_at_str = user.updated_at.to_rfc3339();
 
-    db.prepare("INSERT INTO users (id, apple_user_id, email, credits_balance, total_videos_generated, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)")
+    db.prepare("INSERT INTO users (id, apple_user_id, email, referral_code, credits_balance, total_videos_generated, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
         .bind(&[
             user.id.clone().into(),
             user.apple_user_id.clone().into(),
             email.unwrap_or_default().into(),
+            user.referral_code.clone().unwrap_or_default().into(),
             (user.credits_balance as f64).into(),
             (user.total_videos_generated as f64).into(),
             created_at_str.into(),
@@ -90,54 +101,92 @@ pub async fn get_or_create_user(
 }
 
 pub async fn get_user_by_id(env: &Env, user_id: &str) -> Result<User, AppError> {
+    find_user(env, "id = ?", user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".into()))
+}
+
+pub async fn get_user_by_referral_code(env: &Env, referral_code: &str) -> Result<Option<User>, AppError> {
+    find_user(env, "referral_code = ?", &referral_code.trim().to_uppercase()).await
+}
+
+pub async fn get_user_by_email(env: &Env, email: &str) -> Result<Option<User>, AppError> {
+    let email = email.trim();
+    if email.is_empty() {
+        return Ok(None);
+    }
+
+    find_user(env, "email = ? COLLATE NOCASE", email).await
+}
+
+async fn find_user(env: &Env, condition: &str, value: &str) -> Result<Option<User>, AppError> {
     let db = get_db(env)?;
 
-    let user_data = db.prepare("SELECT id, apple_user_id, email, credits_balance, total_videos_generated, created_at, updated_at FROM users WHERE id = ?")
-        .bind(&[user_id.into()])?
+    let user_data = db
+        .prepare(format!("SELECT {} FROM users WHERE {} LIMIT 1", USER_COLUMNS, condition))
+        .bind(&[value.into()])?
         .first::<serde_json::Value>(None)
         .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?
-        .ok_or_else(|| AppError::NotFound("User not found".into()))?;
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(user_data.as_ref().map(user_from_row))
+}
 
-    Ok(User {
+fn user_from_row(user_data: &serde_json::Value) -> User {
+    User {
         id: user_data.get("id").and_then(|v| v.as_str()).unwrap_or("").to_string(),
         apple_user_id: user_data.get("apple_user_id").and_then(|v| v.as_str()).unwrap_or("").to_string(),
         email: user_data.get("email").and_then(|v| v.as_str()).map(|s| s.to_string()),
+        referral_code: user_data.get("referral_code").and_then(|v| v.as_str()).filter(|s| !s.is_empty()).map(|s| s.to_string()),
         credits_balance: user_data.get("credits_balance").and_then(|v| v.as_f64()).unwrap_or(0.0) as i64,
         total_videos_generated: user_data.get("total_videos_generated").and_then(|v| v.as_f64()).unwrap_or(0.0) as i64,
-        created_at: user_data.get("created_at").and_then(|v| v.as_str()).and_then(|s| s.parse().ok()).unwrap_or_else(|| now_datetime()),
-        updated_at: user_data.get("updated_at").and_then(|v| v.as_str()).and_then(|s| s.parse().ok()).unwrap_or_else(|| now_datetime()),
-    })
+        created_at: user_data.get("created_at").and_then(|v| v.as_str()).and_then(|s| s.parse().ok()).unwrap_or_else(now_datetime),
+        updated_at: user_data.get("updated_at").and_then(|v| v.as_str()).and_then(|s| s.parse().ok()).unwrap_or_else(now_datetime),
+    }
 }
 
-pub async fn insert_video(env: &Env, video: &Video) -> Result<(), AppError> {
-    let db = get_db(env)?;
-
-    db.prepare("INSERT INTO videos (id, user_id, openai_video_id, status, model, prompt, size, seconds, credits_cost, progress, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
-        .bind(&[
-            video.id.clone().into(),
-            video.user_id.clone().into(),
-            video.openai_video_id.clone().into(),
-            video.status.to_string().into(),
-            video.model.clone().into(),
-            video.prompt.clone().into(),
-            video.size.clone().into(),
-            video.seconds.into(),
-            (video.credits_cost as f64).into(),
-            video.progress.into(),
-            video.created_at.to_rfc3339().into(),
-        ])?
-        .run()
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-    Ok(())
+/// An insert for `video` that only adds the row while `condition` holds.
+/// `condition_bindings` fill the condition's placeholders.
+pub fn insert_video_statement(
+    db: &D1Database,
+    video: &Video,
+    condition: &str,
+    condition_bindings: Vec<JsValue>,
+) -> Result<D1PreparedStatement, AppError> {
+    let mut bindings: Vec<JsValue> = vec![
+        video.id.clone().into(),
+        video.user_id.clone().into(),
+        video.openai_video_id.clone().into(),
+        video.status.to_string().into(),
+        video.model.clone().into(),
+        video.prompt.clone().into(),
+        video.size.clone().into(),
+        video.seconds.into(),
+        (video.credits_cost as f64).into(),
+        video.progress.into(),
+        video.created_at.to_rfc3339().into(),
+        video.org_id.as_deref().map(JsValue::from_str).unwrap_or(JsValue::NULL),
+        video.reference_image_id.as_deref().map(JsValue::from_str).unwrap_or(JsValue::NULL),
+        video.parent_video_id.as_deref().map(JsValue::from_str).unwrap_or(JsValue::NULL),
+        video.batch_id.as_deref().map(JsValue::from_str).unwrap_or(JsValue::NULL),
+        video.template_id.as_deref().map(JsValue::from_str).unwrap_or(JsValue::NULL),
+        match &video.template_variables {
+            Some(variables) => serde_json::to_string(variables)?.into(),
+            None => JsValue::NULL,
+        },
+        video.original_prompt.as_deref().map(JsValue::from_str).unwrap_or(JsValue::NULL),
+    ];
+    bindings.extend(condition_bindings);
+
+    Ok(db
+        .prepare(format!("INSERT INTO videos (id, user_id, openai_video_id, status, model, prompt, size, seconds, credits_cost, progress, created_at, org_id, reference_image_id, parent_video_id, batch_id, template_id, template_variables, original_prompt) SELECT ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ? WHERE {}", condition))
+        .bind(&bindings)?)
 }
 
 pub async fn get_video_by_id(env: &Env, video_id: &str) -> Result<Video, AppError> {
     let db = get_db(env)?;
 
-    db.prepare("SELECT id, user_id, openai_video_id, status, model, prompt, size, seconds, video_url, thumbnail_url, spritesheet_url, download_url_expires_at, credits_cost, progress, created_at, completed_at, failed_at, error_message FROM videos WHERE id = ?")
+    db.prepare(format!("SELECT {} FROM videos WHERE id = ?", VIDEO_COLUMNS))
         .bind(&[video_id.into()])?
         .first(None)
         .await
@@ -145,95 +194,50 @@ pub async fn get_video_by_id(env: &Env, video_id: &str) -> Result<Video, AppErro
         .ok_or_else(|| AppError::NotFound("Video not found".into()))
 }
 
-pub async fn get_video_by_openai_id(env: &Env, openai_video_id: &str) -> Result<Video, AppError> {
+/// Deletes a finished video. Returns false if it is still running, so a
+/// job can't lose its row between the caller's check and the delete.
+pub async fn delete_finished_video(env: &Env, video_id: &str) -> Result<bool, AppError> {
     let db = get_db(env)?;
 
-    db.prepare("SELECT id, user_id, openai_video_id, status, model, prompt, size, seconds, video_url, thumbnail_url, spritesheet_url, download_url_expires_at, credits_cost, progress, created_at, completed_at, failed_at, error_message FROM videos WHERE openai_video_id = ?")
-        .bind(&[openai_video_id.into()])?
-        .first(None)
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?
-        .ok_or_else(|| AppError::NotFound("Video not found".into()))
-}
-
-pub async fn update_video_progress(
-    env: &Env,
-    openai_video_id: &str,
-    status: &str,
-    progress: i32,
-) -> Result<(), AppError> {
-    let db = get_db(env)?;
-
-    db.prepare("UPDATE videos SET status = ?, progress = ? WHERE openai_video_id = ?")
-        .bind(&[status.into(), progress.into(), openai_video_id.into()])?
-        .run()
-        .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-    Ok(())
-}
-
-pub async fn update_video_completed(
-    env: &Env,
-    openai_video_id: &str,
-    video_url: &str,
-    thumbnail_url: &str,
-    spritesheet_url: &str,
-) -> Result<(), AppError> {
-    let db = get_db(env)?;
-    let now = now_datetime();
-    let expires_at = now + chrono::Duration::hours(24);
-
-    db.prepare("UPDATE videos SET status = ?, video_url = ?, thumbnail_url = ?, spritesheet_url = ?, download_url_expires_at = ?, completed_at = ?, progress = 100 WHERE openai_video_id = ?")
-        .bind(&[
-            "completed".into(),
-            video_url.into(),
-            thumbnail_url.into(),
-            spritesheet_url.into(),
-            expires_at.to_rfc3339().into(),
-            now.to_rfc3339().into(),
-            openai_video_id.into(),
-        ])?
+    let result = db
+        .prepare("DELETE FROM videos WHERE id = ? AND status IN ('completed', 'failed', 'cancelled')")
+        .bind(&[video_id.into()])?
         .run()
         .await
         .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
-    Ok(())
+    Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) > 0)
 }
 
-pub async fn update_video_failed(
-    env: &Env,
-    openai_video_id: &str,
-    error_message: &str,
-) -> Result<(), AppError> {
+pub async fn get_video_by_openai_id(env: &Env, openai_video_id: &str) -> Result<Video, AppError> {
     let db = get_db(env)?;
-    let now = now_datetime();
 
-    db.prepare("UPDATE videos SET status = ?, error_message = ?, failed_at = ? WHERE openai_video_id = ?")
-        .bind(&[
-            "failed".into(),
-            error_message.into(),
-            now.to_rfc3339().into(),
-            openai_video_id.into(),
-        ])?
-        .run()
+    db.prepare(format!("SELECT {} FROM videos WHERE openai_video_id = ?", VIDEO_COLUMNS))
+        .bind(&[openai_video_id.into()])?
+        .first(None)
         .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-    Ok(())
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("Video not found".into()))
 }
 
 pub async fn list_user_videos(
     env: &Env,
     user_id: &str,
+    filter: &VideoFilter,
     limit: i32,
     offset: i32,
 ) -> Result<(Vec<Video>, i64), AppError> {
     let db = get_db(env)?;
+    let conditions = filter.conditions_sql();
+
+    let mut bindings: Vec<JsValue> = vec![user_id.into()];
+    bindings.extend(filter.bindings());
+    let count_bindings = bindings.clone();
+    bindings.extend([limit.into(), offset.into()]);
 
     let videos: Vec<Video> = db
-        .prepare("SELECT id, user_id, openai_video_id, status, model, prompt, size, seconds, video_url, thumbnail_url, spritesheet_url, download_url_expires_at, credits_cost, progress, created_at, completed_at, failed_at, error_message FROM videos WHERE user_id = ? ORDER BY created_at DESC LIMIT ? OFFSET ?")
-        .bind(&[user_id.into(), limit.into(), offset.into()])?
+        .prepare(format!("SELECT {} FROM videos WHERE user_id = ?{} ORDER BY created_at DESC LIMIT ? OFFSET ?", VIDEO_COLUMNS, conditions))
+        .bind(&bindings)?
         .all()
         .await
         .map_err(|e| AppError::DatabaseError(e.to_string()))?
@@ -241,8 +245,8 @@ pub async fn list_user_videos(
         .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
     let count_result: Option<CountResult> = db
-        .prepare("SELECT COUNT(*) as count FROM videos WHERE user_id = ?")
-        .bind(&[user_id.into()])?
+        .prepare(format!("SELECT COUNT(*) as count FROM videos WHERE user_id = ?{}", conditions))
+        .bind(&count_bindings)?
         .first(None)
         .await
         .map_err(|e| AppError::DatabaseError(e.to_string()))?;
@@ -252,36 +256,20 @@ pub async fn list_user_videos(
     Ok((videos, total))
 }
 
-pub async fn insert_transaction(
+pub async fn list_video_remixes(
     env: &Env,
+    parent_video_id: &str,
     user_id: &str,
-    amount: i64,
-    balance_after: i64,
-    transaction_type: &str,
-    description: &str,
-    video_id: Option<&str>,
-    apple_transaction_id: Option<&str>,
-) -> Result<(), AppError> {
+) -> Result<Vec<RemixSummary>, AppError> {
     let db = get_db(env)?;
-    let transaction_id = uuid::Uuid::new_v4().to_string();
 
-    db.prepare("INSERT INTO credit_transactions (id, user_id, amount, balance_after, transaction_type, description, video_id, revenuecat_transaction_id, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")
-        .bind(&[
-            transaction_id.into(),
-            user_id.into(),
-            (amount as f64).into(),
-            (balance_after as f64).into(),
-            transaction_type.into(),
-            description.into(),
-            video_id.map(|v| JsValue::from_str(v)).unwrap_or(JsValue::NULL),
-            apple_transaction_id.map(|v| JsValue::from_str(v)).unwrap_or(JsValue::NULL),
-            now_rfc3339().into(),
-        ])?
-        .run()
+    db.prepare("SELECT id, status, prompt, created_at FROM videos WHERE parent_video_id = ? AND user_id = ? ORDER BY created_at DESC")
+        .bind(&[parent_video_id.into(), user_id.into()])?
+        .all()
         .await
-        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
-
-    Ok(())
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .results::<RemixSummary>()
+        .map_err(|e| AppError::DatabaseError(e.to_string()))
 }
 
 pub async fn get_user_transactions(
@@ -291,7 +279,7 @@ pub async fn get_user_transactions(
 ) -> Result<Vec<CreditTransaction>, AppError> {
     let db = get_db(env)?;
 
-    db.prepare("SELECT id, user_id, amount, balance_after, transaction_type, description, video_id, revenuecat_transaction_id, metadata, created_at FROM credit_transactions WHERE user_id = ? ORDER BY created_at DESC LIMIT ?")
+    db.prepare(format!("SELECT {} FROM credit_transactions WHERE user_id = ? AND org_id IS NULL ORDER BY created_at DESC LIMIT ?", TRANSACTION_COLUMNS))
         .bind(&[user_id.into(), limit.into()])?
         .all()
         .await
@@ -300,7 +288,22 @@ pub async fn get_user_transactions(
         .map_err(|e| AppError::DatabaseError(e.to_string()))
 }
 
-fn get_db(env: &Env) -> Result<D1Database, AppError> {
-    env.d1("DB")
-        .map_err(|e| AppError::InternalError(format!("Failed to get DB: {}", e)))
+pub async fn sum_transaction_amounts(
+    env: &Env,
+    user_id: &str,
+    transaction_type: &str,
+    since: Option<&str>,
+) -> Result<(i64, i64), AppError> {
+    let db = get_db(env)?;
+
+    let result: Option<AggregateResult> = db
+        .prepare("SELECT COUNT(*) as count, SUM(amount) as total FROM credit_transactions WHERE user_id = ? AND transaction_type = ? AND created_at >= ? AND org_id IS NULL")
+        .bind(&[user_id.into(), transaction_type.into(), since.unwrap_or("").into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(result
+        .map(|r| (r.count as i64, r.total.unwrap_or(0.0) as i64))
+        .unwrap_or((0, 0)))
 }
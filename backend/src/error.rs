@@ -1,15 +1,20 @@
+use crate::openai_client::OpenAIErrorKind;
 use worker::{Response, Result};
 use serde_json::json;
 
 #[derive(Debug)]
 pub enum AppError {
     Unauthorized(String),
+    Forbidden(String),
     BadRequest(String),
     NotFound(String),
     InsufficientCredits,
-    ConcurrentGeneration,
+    BudgetExceeded(String),
+    QueueFull(String),
+    ContentPolicy(Vec<String>),
     RateLimitExceeded(String),
     ExternalApiError(String),
+    OpenAIError(OpenAIErrorKind, String),
     DatabaseError(String),
     InternalError(String),
 }
@@ -18,12 +23,16 @@ impl std::fmt::Display for AppError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             AppError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
+            AppError::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
             AppError::BadRequest(msg) => write!(f, "Bad request: {}", msg),
             AppError::NotFound(msg) => write!(f, "Not found: {}", msg),
             AppError::InsufficientCredits => write!(f, "Insufficient credits"),
-            AppError::ConcurrentGeneration => write!(f, "Another video is currently being generated"),
+            AppError::BudgetExceeded(msg) => write!(f, "Budget exceeded: {}", msg),
+            AppError::QueueFull(msg) => write!(f, "Queue full: {}", msg),
+            AppError::ContentPolicy(categories) => write!(f, "Content policy violation: {}", categories.join(", ")),
             AppError::RateLimitExceeded(msg) => write!(f, "Rate limit exceeded: {}", msg),
             AppError::ExternalApiError(msg) => write!(f, "External API error: {}", msg),
+            AppError::OpenAIError(kind, msg) => write!(f, "OpenAI error ({}): {}", kind.code(), msg),
             AppError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
             AppError::InternalError(msg) => write!(f, "Internal error: {}", msg),
         }
@@ -48,6 +57,7 @@ impl AppError {
     pub fn to_response(&self) -> Result<Response> {
         let (status, error_code, message) = match self {
             AppError::Unauthorized(msg) => (401, "unauthorized", msg.clone()),
+            AppError::Forbidden(msg) => (403, "forbidden", msg.clone()),
             AppError::BadRequest(msg) => (400, "bad_request", msg.clone()),
             AppError::NotFound(msg) => (404, "not_found", msg.clone()),
             AppError::InsufficientCredits => (
@@ -55,24 +65,31 @@ impl AppError {
                 "insufficient_credits",
                 "You don't have enough credits. Purchase more to continue.".to_string(),
             ),
-            AppError::ConcurrentGeneration => (
-                409,
-                "concurrent_generation",
-                "You already have a video being generated. Please wait for it to complete.".to_string(),
+            AppError::BudgetExceeded(msg) => (402, "budget_exceeded", msg.clone()),
+            AppError::QueueFull(msg) => (409, "queue_full", msg.clone()),
+            AppError::ContentPolicy(_) => (
+                422,
+                "content_policy",
+                "Your prompt was blocked by our content policy. Please rephrase it and try again.".to_string(),
             ),
             AppError::RateLimitExceeded(msg) => (429, "rate_limit_exceeded", msg.clone()),
             AppError::ExternalApiError(msg) => (502, "external_api_error", msg.clone()),
+            AppError::OpenAIError(kind, msg) => (kind.status(), kind.code(), msg.clone()),
             AppError::DatabaseError(msg) => (500, "database_error", msg.clone()),
             AppError::InternalError(msg) => (500, "internal_error", msg.clone()),
         };
 
-        let body = json!({
+        let mut body = json!({
             "error": {
                 "code": error_code,
                 "message": message
             }
         });
 
+        if let AppError::ContentPolicy(categories) = self {
+            body["error"]["categories"] = json!(categories);
+        }
+
         Response::from_json(&body).map(|r| {
             r.with_status(status)
         })
@@ -0,0 +1,407 @@
+use crate::db::get_db;
+use crate::error::AppError;
+use crate::models::{CreateVideoRequest, GalleryItem};
+use crate::shares;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use worker::{wasm_bindgen::JsValue, Env};
+
+const MAX_REASON_CHARS: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GallerySort {
+    Recent,
+    Top,
+}
+
+impl GallerySort {
+    pub fn parse(value: Option<&str>) -> Result<Self, AppError> {
+        match value {
+            None | Some("recent") => Ok(GallerySort::Recent),
+            Some("top") => Ok(GallerySort::Top),
+            Some(other) => Err(AppError::BadRequest(format!(
+                "Unknown sort: {}. Use recent or top",
+                other
+            ))),
+        }
+    }
+
+    fn order_sql(self) -> &'static str {
+        match self {
+            GallerySort::Recent => "g.published_at DESC, g.video_id DESC",
+            GallerySort::Top => "g.like_count DESC, g.published_at DESC, g.video_id DESC",
+        }
+    }
+}
+
+/// Where the previous page stopped. Opaque to clients; it carries every
+/// sort key so pages stay stable while new items are published.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    #[serde(rename = "l")]
+    pub like_count: i64,
+    #[serde(rename = "p")]
+    pub published_at: String,
+    #[serde(rename = "v")]
+    pub video_id: String,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(value: &str) -> Result<Self, AppError> {
+        URL_SAFE_NO_PAD
+            .decode(value)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or_else(|| AppError::BadRequest("Invalid cursor".into()))
+    }
+
+    /// Rows strictly after this cursor in `sort` order.
+    fn condition_sql(sort: GallerySort) -> &'static str {
+        match sort {
+            GallerySort::Recent => "(g.published_at, g.video_id) < (?, ?)",
+            GallerySort::Top => "(g.like_count, g.published_at, g.video_id) < (?, ?, ?)",
+        }
+    }
+
+    fn bindings(&self, sort: GallerySort) -> Vec<JsValue> {
+        let mut values = Vec::with_capacity(3);
+        if sort == GallerySort::Top {
+            values.push(JsValue::from_f64(self.like_count as f64));
+        }
+        values.push(self.published_at.as_str().into());
+        values.push(self.video_id.as_str().into());
+        values
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GalleryRow {
+    video_id: String,
+    user_id: String,
+    openai_video_id: String,
+    prompt: String,
+    model: String,
+    size: String,
+    seconds: i32,
+    like_count: i64,
+    liked: i64,
+    published_at: String,
+}
+
+const ITEM_SELECT: &str = "SELECT g.video_id, g.user_id, v.openai_video_id, v.prompt, v.model, v.size, v.seconds, g.like_count, \
+EXISTS (SELECT 1 FROM gallery_likes l WHERE l.video_id = g.video_id AND l.user_id = ?) AS liked, g.published_at \
+FROM gallery_items g JOIN videos v ON v.id = g.video_id WHERE g.taken_down_at IS NULL";
+
+/// A proxy URL authorized by the item being published, so any signed-in
+/// viewer can load it.
+fn media_url(service_url: &str, openai_video_id: &str, variant: &str) -> String {
+    format!("{}/v1/videos/{}/proxy?variant={}&gallery=1", service_url, openai_video_id, variant)
+}
+
+fn to_item(row: GalleryRow, viewer_id: &str, service_url: &str) -> Result<(GalleryItem, Cursor), AppError> {
+    let published_at = DateTime::parse_from_rfc3339(&row.published_at)
+        .map(|d| d.with_timezone(&Utc))
+        .map_err(|e| AppError::DatabaseError(format!("Invalid published_at: {}", e)))?;
+
+    let cursor = Cursor {
+        like_count: row.like_count,
+        published_at: row.published_at,
+        video_id: row.video_id.clone(),
+    };
+
+    let item = GalleryItem {
+        thumbnail_url: media_url(service_url, &row.openai_video_id, "thumbnail"),
+        video_url: media_url(service_url, &row.openai_video_id, "video"),
+        remix: CreateVideoRequest {
+            model: row.model.clone(),
+            prompt: row.prompt.clone(),
+            size: row.size.clone(),
+            seconds: row.seconds,
+            org_id: None,
+            reference_image_id: None,
+            template_id: None,
+            variables: BTreeMap::new(),
+            enhance: false,
+        },
+        video_id: row.video_id,
+        prompt: row.prompt,
+        model: row.model,
+        size: row.size,
+        seconds: row.seconds,
+        like_count: row.like_count,
+        liked: row.liked != 0,
+        owned: row.user_id == viewer_id,
+        published_at,
+    };
+
+    Ok((item, cursor))
+}
+
+/// One page of the feed and the cursor for the next, if there is one.
+pub async fn list_items(
+    env: &Env,
+    viewer_id: &str,
+    sort: GallerySort,
+    cursor: Option<&Cursor>,
+    limit: u32,
+) -> Result<(Vec<GalleryItem>, Option<String>), AppError> {
+    let db = get_db(env)?;
+    let service_url = shares::service_url(env)?;
+
+    let mut sql = ITEM_SELECT.to_string();
+    let mut bindings: Vec<JsValue> = vec![viewer_id.into()];
+    if let Some(cursor) = cursor {
+        sql.push_str(" AND ");
+        sql.push_str(Cursor::condition_sql(sort));
+        bindings.extend(cursor.bindings(sort));
+    }
+    sql.push_str(&format!(" ORDER BY {} LIMIT ?", sort.order_sql()));
+    // One extra row tells us whether there is another page.
+    bindings.push((limit + 1).into());
+
+    let rows: Vec<GalleryRow> = db
+        .prepare(sql)
+        .bind(&bindings)?
+        .all()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .results()
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    let has_more = rows.len() > limit as usize;
+    let mut items = Vec::with_capacity(limit as usize);
+    let mut last_cursor = None;
+
+    for row in rows.into_iter().take(limit as usize) {
+        let (item, cursor) = to_item(row, viewer_id, &service_url)?;
+        items.push(item);
+        last_cursor = Some(cursor);
+    }
+
+    let next_cursor = if has_more { last_cursor.map(|c| c.encode()) } else { None };
+
+    Ok((items, next_cursor))
+}
+
+pub async fn get_item(env: &Env, video_id: &str, viewer_id: &str) -> Result<GalleryItem, AppError> {
+    let db = get_db(env)?;
+
+    let row: Option<GalleryRow> = db
+        .prepare(format!("{} AND g.video_id = ?", ITEM_SELECT))
+        .bind(&[viewer_id.into(), video_id.into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    let row = row.ok_or_else(|| AppError::NotFound("Gallery item not found".into()))?;
+    Ok(to_item(row, viewer_id, &shares::service_url(env)?)?.0)
+}
+
+#[derive(Debug, Deserialize)]
+struct PublicationRow {
+    taken_down_at: Option<String>,
+}
+
+/// Whether the video has been taken down by an admin. Taken-down videos
+/// can't be published again.
+pub async fn is_taken_down(env: &Env, video_id: &str) -> Result<bool, AppError> {
+    let db = get_db(env)?;
+
+    let row: Option<PublicationRow> = db
+        .prepare("SELECT taken_down_at FROM gallery_items WHERE video_id = ?")
+        .bind(&[video_id.into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(row.is_some_and(|r| r.taken_down_at.is_some()))
+}
+
+/// Publishes a video. Publishing again keeps the original date and likes.
+pub async fn publish(env: &Env, video_id: &str, user_id: &str, now: DateTime<Utc>) -> Result<(), AppError> {
+    let db = get_db(env)?;
+
+    db.prepare("INSERT INTO gallery_items (video_id, user_id, published_at) VALUES (?, ?, ?) ON CONFLICT(video_id) DO NOTHING")
+        .bind(&[video_id.into(), user_id.into(), now.to_rfc3339().into()])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Removes the owner's item and its likes. Taken-down items stay so they
+/// can't be republished. Returns false if nothing was published.
+pub async fn unpublish(env: &Env, video_id: &str, user_id: &str) -> Result<bool, AppError> {
+    let db = get_db(env)?;
+
+    let result = db
+        .prepare("DELETE FROM gallery_items WHERE video_id = ? AND user_id = ? AND taken_down_at IS NULL")
+        .bind(&[video_id.into(), user_id.into()])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) > 0)
+}
+
+/// Hides an item from the feed for good. Returns false if there was no
+/// visible item.
+pub async fn take_down(
+    env: &Env,
+    video_id: &str,
+    admin_id: &str,
+    reason: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<bool, AppError> {
+    if reason.is_some_and(|r| r.chars().count() > MAX_REASON_CHARS) {
+        return Err(AppError::BadRequest(format!(
+            "Reason must be at most {} characters",
+            MAX_REASON_CHARS
+        )));
+    }
+
+    let db = get_db(env)?;
+
+    let result = db
+        .prepare("UPDATE gallery_items SET taken_down_at = ?, taken_down_by = ?, takedown_reason = ? WHERE video_id = ? AND taken_down_at IS NULL")
+        .bind(&[
+            now.to_rfc3339().into(),
+            admin_id.into(),
+            reason.map(JsValue::from_str).unwrap_or(JsValue::NULL),
+            video_id.into(),
+        ])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) > 0)
+}
+
+/// Likes or unlikes a visible item and returns its like count. Repeating
+/// either is harmless: the count is recomputed from `gallery_likes`.
+pub async fn set_liked(
+    env: &Env,
+    video_id: &str,
+    user_id: &str,
+    liked: bool,
+    now: DateTime<Utc>,
+) -> Result<i64, AppError> {
+    let db = get_db(env)?;
+
+    let change = if liked {
+        db.prepare("INSERT OR IGNORE INTO gallery_likes (video_id, user_id, created_at) SELECT video_id, ?, ? FROM gallery_items WHERE video_id = ? AND taken_down_at IS NULL")
+            .bind(&[user_id.into(), now.to_rfc3339().into(), video_id.into()])?
+    } else {
+        db.prepare("DELETE FROM gallery_likes WHERE video_id = ? AND user_id = ?")
+            .bind(&[video_id.into(), user_id.into()])?
+    };
+
+    let recount = db
+        .prepare("UPDATE gallery_items SET like_count = (SELECT COUNT(*) FROM gallery_likes WHERE video_id = ?) WHERE video_id = ? AND taken_down_at IS NULL RETURNING like_count")
+        .bind(&[video_id.into(), video_id.into()])?;
+
+    let results = db
+        .batch(vec![change, recount])
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    #[derive(Deserialize)]
+    struct LikeCount {
+        like_count: i64,
+    }
+
+    results
+        .get(1)
+        .map(|r| r.results::<LikeCount>())
+        .transpose()
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .and_then(|rows| rows.into_iter().next())
+        .map(|row| row.like_count)
+        .ok_or_else(|| AppError::NotFound("Gallery item not found".into()))
+}
+
+/// Checks that a video is in the gallery, for the media requests the feed
+/// makes on behalf of signed-in viewers who don't own it.
+pub async fn authorize_media(env: &Env, video_id: &str) -> Result<(), AppError> {
+    let db = get_db(env)?;
+
+    let row: Option<PublicationRow> = db
+        .prepare("SELECT taken_down_at FROM gallery_items WHERE video_id = ? AND taken_down_at IS NULL")
+        .bind(&[video_id.into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    row.map(|_| ())
+        .ok_or_else(|| AppError::Unauthorized("Not authorized to access this video".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursors_round_trip() {
+        let cursor = Cursor {
+            like_count: 12,
+            published_at: "2025-01-01T00:00:00+00:00".into(),
+            video_id: "vid_1".into(),
+        };
+
+        let encoded = cursor.encode();
+        assert!(encoded.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'));
+        assert_eq!(Cursor::decode(&encoded).unwrap(), cursor);
+        assert!(Cursor::decode("not a cursor").is_err());
+        assert!(Cursor::decode(&URL_SAFE_NO_PAD.encode("{}")).is_err());
+    }
+
+    #[test]
+    fn cursor_conditions_match_the_sort_keys() {
+        assert_eq!(Cursor::condition_sql(GallerySort::Recent).matches('?').count(), 2);
+        assert_eq!(Cursor::condition_sql(GallerySort::Top).matches('?').count(), 3);
+        assert!(GallerySort::Top.order_sql().starts_with("g.like_count DESC"));
+    }
+
+    #[test]
+    fn parses_sort() {
+        assert_eq!(GallerySort::parse(None).unwrap(), GallerySort::Recent);
+        assert_eq!(GallerySort::parse(Some("top")).unwrap(), GallerySort::Top);
+        assert!(GallerySort::parse(Some("random")).is_err());
+    }
+
+    #[test]
+    fn items_prefill_a_remix_request() {
+        let row = GalleryRow {
+            video_id: "vid_1".into(),
+            user_id: "user_1".into(),
+            openai_video_id: "video_abc".into(),
+            prompt: "A fox at dawn".into(),
+            model: "sora-2".into(),
+            size: "1280x720".into(),
+            seconds: 8,
+            like_count: 4,
+            liked: 1,
+            published_at: "2025-01-01T00:00:00+00:00".into(),
+        };
+
+        let (item, cursor) = to_item(row, "user_2", "https://api.example.com").unwrap();
+
+        assert!(item.liked);
+        assert!(!item.owned);
+        assert_eq!(item.remix.prompt, "A fox at dawn");
+        assert_eq!(item.remix.seconds, 8);
+        assert_eq!(
+            item.thumbnail_url,
+            "https://api.example.com/v1/videos/video_abc/proxy?variant=thumbnail&gallery=1"
+        );
+        assert_eq!(cursor.like_count, 4);
+        assert_eq!(cursor.video_id, "vid_1");
+    }
+}
@@ -0,0 +1,241 @@
+use crate::db::{self, get_db, now_rfc3339, VIDEO_COLUMNS};
+use crate::error::AppError;
+use crate::images;
+use crate::models::Video;
+use crate::video_lifecycle;
+use crate::video_provider::{InputReference, VideoProvider};
+use serde::Deserialize;
+use worker::{console_log, wasm_bindgen::JsValue, Env};
+
+const DEFAULT_MAX_ACTIVE_GENERATIONS: i64 = 1;
+const DEFAULT_MAX_QUEUED_GENERATIONS: i64 = 5;
+
+#[derive(Deserialize)]
+struct JobCounts {
+    active: Option<f64>,
+    pending: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct CountResult {
+    count: f64,
+}
+
+pub struct QueueLimits {
+    pub max_active: i64,
+    pub max_queued: i64,
+}
+
+impl QueueLimits {
+    pub fn from_env(env: &Env) -> Self {
+        let read = |name: &str, default: i64| {
+            env.var(name)
+                .map(|v| v.to_string().parse::<i64>().unwrap_or(default))
+                .unwrap_or(default)
+                .max(0)
+        };
+
+        Self {
+            max_active: read("MAX_ACTIVE_GENERATIONS", DEFAULT_MAX_ACTIVE_GENERATIONS).max(1),
+            max_queued: read("MAX_QUEUED_GENERATIONS", DEFAULT_MAX_QUEUED_GENERATIONS),
+        }
+    }
+
+    /// SQL condition that holds while `requested` more of the user's jobs fit
+    /// in their free active slots plus the room left in their queue, with the
+    /// values for its placeholders.
+    pub fn capacity_condition(&self, user_id: &str, requested: i64) -> (&'static str, Vec<JsValue>) {
+        (
+            "MAX(? - (SELECT COUNT(*) FROM videos WHERE user_id = ? AND status IN ('queued', 'in_progress')), 0) \
++ MAX(? - (SELECT COUNT(*) FROM videos WHERE user_id = ? AND status = 'pending_submission'), 0) >= ?",
+            vec![
+                JsValue::from_f64(self.max_active as f64),
+                user_id.into(),
+                JsValue::from_f64(self.max_queued as f64),
+                user_id.into(),
+                JsValue::from_f64(requested as f64),
+            ],
+        )
+    }
+}
+
+async fn count_jobs(env: &Env, user_id: &str) -> Result<(i64, i64), AppError> {
+    let db = get_db(env)?;
+
+    let counts: Option<JobCounts> = db
+        .prepare("SELECT SUM(CASE WHEN status IN ('queued', 'in_progress') THEN 1 ELSE 0 END) as active, SUM(CASE WHEN status = 'pending_submission' THEN 1 ELSE 0 END) as pending FROM videos WHERE user_id = ?")
+        .bind(&[user_id.into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(counts
+        .map(|c| (c.active.unwrap_or(0.0) as i64, c.pending.unwrap_or(0.0) as i64))
+        .unwrap_or((0, 0)))
+}
+
+/// Rejects `requested` new jobs unless they fit in the user's free active
+/// slots plus the room left in their queue.
+pub async fn check_capacity(
+    env: &Env,
+    user_id: &str,
+    limits: &QueueLimits,
+    requested: i64,
+) -> Result<(), AppError> {
+    let (active, pending) = count_jobs(env, user_id).await?;
+    let free = (limits.max_active - active).max(0) + (limits.max_queued - pending).max(0);
+
+    if requested > free {
+        return Err(AppError::QueueFull(format!(
+            "You have {} video(s) generating and {} waiting, so only {} more can be queued. Wait for some to finish before adding more.",
+            active, pending, free
+        )));
+    }
+
+    Ok(())
+}
+
+/// Adds `video` to its owner's queue. The capacity check is part of the
+/// insert, so parallel requests can't queue more than `limits` allow.
+pub async fn insert_pending(env: &Env, video: &Video, limits: &QueueLimits) -> Result<(), AppError> {
+    let db = get_db(env)?;
+    let (condition, bindings) = limits.capacity_condition(&video.user_id, 1);
+
+    let inserted = db::insert_video_statement(&db, video, condition, bindings)?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .meta()?
+        .and_then(|meta| meta.changes)
+        .unwrap_or(0);
+
+    if inserted == 0 {
+        check_capacity(env, &video.user_id, limits, 1).await?;
+        return Err(AppError::QueueFull(
+            "Your queue filled up while this video was being created. Wait for a video to finish and try again.".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+pub async fn queue_position(env: &Env, video: &Video) -> Result<i64, AppError> {
+    let db = get_db(env)?;
+
+    let result: Option<CountResult> = db
+        .prepare("SELECT COUNT(*) as count FROM videos WHERE user_id = ? AND status = 'pending_submission' AND (created_at < ? OR (created_at = ? AND id <= ?))")
+        .bind(&[
+            video.user_id.clone().into(),
+            video.created_at.to_rfc3339().into(),
+            video.created_at.to_rfc3339().into(),
+            video.id.clone().into(),
+        ])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(result.map(|r| r.count as i64).unwrap_or(0))
+}
+
+/// Atomically moves the user's oldest pending job to `queued`, stamping
+/// `started_at`, if they are below `max_active`. The check and the update are one statement, so two
+/// triggers racing can't both start a job.
+async fn claim_next(env: &Env, user_id: &str, max_active: i64) -> Result<Option<Video>, AppError> {
+    let db = get_db(env)?;
+
+    db.prepare(format!(
+        "UPDATE videos SET status = 'queued', started_at = ? WHERE id = (SELECT id FROM videos WHERE user_id = ? AND status = 'pending_submission' ORDER BY created_at ASC, id ASC LIMIT 1) AND (SELECT COUNT(*) FROM videos WHERE user_id = ? AND status IN ('queued', 'in_progress')) < ? RETURNING {}",
+        VIDEO_COLUMNS
+    ))
+    .bind(&[now_rfc3339().into(), user_id.into(), user_id.into(), (max_active as f64).into()])?
+    .first(None)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))
+}
+
+async fn mark_submitted(env: &Env, video_id: &str, openai_video_id: &str) -> Result<bool, AppError> {
+    let db = get_db(env)?;
+
+    let result = db
+        .prepare("UPDATE videos SET openai_video_id = ? WHERE id = ? AND status = 'queued'")
+        .bind(&[openai_video_id.into(), video_id.into()])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) > 0)
+}
+
+async fn submit(env: &Env, provider: &dyn VideoProvider, video: &Video) -> Result<String, AppError> {
+    if let Some(parent_id) = video.parent_video_id.as_deref() {
+        let parent = db::get_video_by_id(env, parent_id).await?;
+        let response = provider.remix_video(&parent.openai_video_id, &video.prompt).await?;
+        return Ok(response.id);
+    }
+
+    let reference = match video.reference_image_id.as_deref() {
+        Some(image_id) => {
+            let image = images::get_reference_image(env, image_id, &video.user_id).await?;
+            let bytes = images::load_reference_image_bytes(env, &image).await?;
+            Some((image, bytes))
+        }
+        None => None,
+    };
+
+    let input_reference = reference.as_ref().map(|(image, bytes)| InputReference {
+        filename: &image.id,
+        mime_type: &image.mime_type,
+        bytes,
+    });
+
+    let response = provider
+        .create_video(&video.id, &video.model, &video.prompt, &video.size, video.seconds, input_reference)
+        .await?;
+
+    Ok(response.id)
+}
+
+/// Submits the user's pending jobs to the provider, oldest first, while they
+/// have free slots. Jobs the provider rejects are failed and refunded; their
+/// errors are returned so the request that queued them can report them.
+pub async fn submit_pending(
+    env: &Env,
+    provider: &dyn VideoProvider,
+    user_id: &str,
+) -> Result<Vec<(String, AppError)>, AppError> {
+    let limits = QueueLimits::from_env(env);
+    let mut failures = Vec::new();
+
+    while let Some(video) = claim_next(env, user_id, limits.max_active).await? {
+        console_log!("Submitting queued video {} for user {}", video.id, user_id);
+
+        match submit(env, provider, &video).await {
+            Ok(openai_video_id) => {
+                if !mark_submitted(env, &video.id, &openai_video_id).await? {
+                    console_log!("Video {} was cancelled during submission, deleting {}", video.id, openai_video_id);
+                    if let Err(e) = provider.delete_video(&openai_video_id).await {
+                        console_log!("Failed to delete orphaned video {}: {:?}", openai_video_id, e);
+                    }
+                }
+            }
+            Err(e) => {
+                console_log!("Submission failed for video {}: {:?}", video.id, e);
+                video_lifecycle::apply(env, &video, video_lifecycle::Event::failed(e.to_string())).await?;
+                failures.push((video.id, e));
+            }
+        }
+    }
+
+    Ok(failures)
+}
+
+/// Like `submit_pending`, for callers that only need to log failures.
+pub async fn advance(env: &Env, provider: &dyn VideoProvider, user_id: &str) {
+    match submit_pending(env, provider, user_id).await {
+        Ok(failures) if !failures.is_empty() => {
+            console_log!("{} queued video(s) failed to submit for user {}", failures.len(), user_id);
+        }
+        Ok(_) => {}
+        Err(e) => console_log!("Failed to advance queue for user {}: {:?}", user_id, e),
+    }
+}
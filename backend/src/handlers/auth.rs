@@ -1,12 +1,13 @@
 use crate::auth;
 use crate::db;
 use crate::error::AppError;
+use crate::AppState;
 use crate::models::{AppleTokenRequest, AuthResponse};
 use worker::{Request, Response, RouteContext};
 
 async fn apple_sign_in_inner(
     mut req: Request,
-    ctx: RouteContext<()>,
+    ctx: RouteContext<AppState>,
 ) -> Result<Response, AppError> {
     let body: AppleTokenRequest = req.json().await.map_err(|_| {
         AppError::BadRequest("Invalid request body".into())
@@ -35,17 +36,18 @@ async fn apple_sign_in_inner(
     Ok(response)
 }
 
-pub async fn apple_sign_in(req: Request, ctx: RouteContext<()>) -> worker::Result<Response> {
+pub async fn apple_sign_in(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
     apple_sign_in_inner(req, ctx).await.or_else(|e| e.to_response())
 }
 
-async fn get_me_inner(req: Request, ctx: RouteContext<()>) -> Result<Response, AppError> {
+async fn get_me_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
     let user_id = auth::extract_user_from_request(&req)?;
     let user = db::get_user_by_id(&ctx.env, &user_id).await?;
 
     let response = serde_json::json!({
         "id": user.id,
         "email": user.email,
+        "referral_code": user.referral_code,
         "credits_balance": user.credits_balance,
         "total_videos_generated": user.total_videos_generated,
         "created_at": user.created_at.to_rfc3339(),
@@ -54,6 +56,6 @@ async fn get_me_inner(req: Request, ctx: RouteContext<()>) -> Result<Response, A
     Response::from_json(&response).map_err(|e| e.into())
 }
 
-pub async fn get_me(req: Request, ctx: RouteContext<()>) -> worker::Result<Response> {
+pub async fn get_me(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
     get_me_inner(req, ctx).await.or_else(|e| e.to_response())
 }
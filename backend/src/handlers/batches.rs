@@ -0,0 +1,192 @@
+use crate::auth;
+use crate::batches;
+use crate::budgets;
+use crate::credits::{self, Charge};
+use crate::db::now_datetime;
+use crate::error::AppError;
+use crate::AppState;
+use crate::generation_queue::{self, QueueLimits};
+use crate::images;
+use crate::moderation;
+use crate::models::{CreateBatchRequest, CreateBatchResponse, CreateVideoRequest, Video, VideoBatch};
+use crate::pricing;
+use crate::prompt_templates;
+use crate::rate_limit;
+use worker::{console_log, Request, Response, RouteContext};
+
+fn expand_request(
+    body: CreateBatchRequest,
+    max_size: usize,
+) -> Result<(Vec<CreateVideoRequest>, Option<String>), AppError> {
+    let (items, org_id) = match body {
+        CreateBatchRequest::Videos { videos, org_id } => {
+            let org_id = org_id.or_else(|| videos.first().and_then(|v| v.org_id.clone()));
+            (videos, org_id)
+        }
+        CreateBatchRequest::Variants { video, variants } => {
+            if variants > max_size {
+                return Err(AppError::BadRequest(format!(
+                    "A batch can have at most {} videos",
+                    max_size
+                )));
+            }
+            let org_id = video.org_id.clone();
+            (vec![video; variants], org_id)
+        }
+    };
+
+    if items.is_empty() {
+        return Err(AppError::BadRequest("A batch needs at least one video".into()));
+    }
+
+    if items.len() > max_size {
+        return Err(AppError::BadRequest(format!(
+            "A batch can have at most {} videos",
+            max_size
+        )));
+    }
+
+    if items.iter().any(|item| item.enhance) {
+        return Err(AppError::BadRequest(
+            "Batches don't support enhance. Enhance the prompt with /v1/prompts/enhance first".into(),
+        ));
+    }
+
+    if items.iter().any(|item| item.org_id.is_some() && item.org_id != org_id) {
+        return Err(AppError::BadRequest("All videos in a batch must use the same org_id".into()));
+    }
+
+    Ok((items, org_id))
+}
+
+async fn create_batch_inner(mut req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let body: CreateBatchRequest = req.json().await.map_err(|e| {
+        console_log!("Failed to parse batch request body: {:?}", e);
+        AppError::BadRequest("Invalid request body".into())
+    })?;
+
+    let (items, org_id) = expand_request(body, batches::max_batch_size(&ctx.env))?;
+    let count = items.len() as i64;
+
+    rate_limit::check_video_quota(&ctx.env, &user_id, count).await?;
+
+    let mut resolved = Vec::with_capacity(items.len());
+    for item in &items {
+        resolved.push(prompt_templates::resolve_prompt(&ctx.env, &user_id, item).await?);
+    }
+
+    let mut prompts: Vec<&str> = resolved.iter().map(|r| r.prompt.as_str()).collect();
+    prompts.sort_unstable();
+    prompts.dedup();
+    for prompt in prompts {
+        moderation::check_prompt(&ctx.env, ctx.data.moderator.as_deref(), &user_id, prompt).await?;
+    }
+
+    let batch_id = uuid::Uuid::new_v4().to_string();
+    let now = now_datetime();
+    let mut videos = Vec::with_capacity(items.len());
+
+    for (index, (item, resolved)) in items.into_iter().zip(resolved).enumerate() {
+        console_log!("Validating batch item {}", index);
+        pricing::validate_video_params(&item.model, &item.size, item.seconds)?;
+
+        if let Some(image_id) = item.reference_image_id.as_deref() {
+            let image = images::get_reference_image(&ctx.env, image_id, &user_id).await?;
+            images::check_matches_size(image.width, image.height, &item.size)?;
+        }
+
+        let credits_cost = pricing::calculate_credits(&item.model, item.seconds)?;
+
+        let mut video = Video::new_pending(
+            user_id.clone(),
+            item.model,
+            resolved.prompt,
+            item.size,
+            item.seconds,
+            credits_cost,
+            now,
+        );
+        video.org_id = org_id.clone();
+        video.reference_image_id = item.reference_image_id;
+        video.batch_id = Some(batch_id.clone());
+        video.template_id = resolved.template_id;
+        video.template_variables = resolved.variables;
+        videos.push(video);
+    }
+
+    let total_credits: i64 = videos.iter().map(|v| v.credits_cost).sum();
+    console_log!("Batch {} of {} videos costs {} credits", batch_id, count, total_credits);
+
+    if org_id.is_none() {
+        budgets::check_budget(&ctx.env, &user_id, total_credits).await?;
+    }
+
+    let limits = QueueLimits::from_env(&ctx.env);
+    generation_queue::check_capacity(&ctx.env, &user_id, &limits, count).await?;
+
+    let charges: Vec<Charge> = videos.iter().map(|v| Charge::video(&v.id, v.credits_cost)).collect();
+    let new_balance = credits::reserve_credits(&ctx.env, &user_id, org_id.as_deref(), &charges).await?;
+
+    let batch = VideoBatch {
+        id: batch_id,
+        user_id: user_id.clone(),
+        org_id,
+        total_credits,
+        created_at: now,
+    };
+
+    if let Err(e) = batches::insert_batch(&ctx.env, &batch, &videos, &limits).await {
+        console_log!("Failed to store batch {}: {:?}", batch.id, e);
+        for video in &videos {
+            credits::refund_credits(&ctx.env, &user_id, &video.id, video.credits_cost).await?;
+        }
+        return Err(e);
+    }
+
+    let failures = generation_queue::submit_pending(&ctx.env, ctx.data.provider.as_ref(), &user_id).await?;
+    if !failures.is_empty() {
+        console_log!("{} video(s) in batch {} failed to submit", failures.len(), batch.id);
+    }
+
+    if batch.org_id.is_none() {
+        if let Err(e) = budgets::record_budget_alerts(&ctx.env, &user_id).await {
+            console_log!("Failed to record budget alerts: {:?}", e);
+        }
+    }
+
+    let videos = batches::list_batch_videos(&ctx.env, &batch.id).await?;
+    let refunded = batches::get_refunded_credits(&ctx.env, &batch.id).await?;
+
+    let response = CreateBatchResponse {
+        batch: batches::summarize(batch, videos, refunded),
+        new_balance: new_balance + refunded,
+    };
+
+    Response::from_json(&response).map_err(|e| e.into())
+}
+
+pub async fn create_batch(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    create_batch_inner(req, ctx).await.or_else(|e| {
+        console_log!("Batch creation error: {:?}", e);
+        e.to_response()
+    })
+}
+
+async fn get_batch_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let batch_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing batch ID".into()))?;
+
+    let batch = batches::get_batch(&ctx.env, batch_id, &user_id).await?;
+    let videos = batches::list_batch_videos(&ctx.env, &batch.id).await?;
+    let refunded = batches::get_refunded_credits(&ctx.env, &batch.id).await?;
+
+    Response::from_json(&batches::summarize(batch, videos, refunded)).map_err(|e| e.into())
+}
+
+pub async fn get_batch(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    get_batch_inner(req, ctx).await.or_else(|e| e.to_response())
+}
@@ -1,31 +1,38 @@
 use crate::auth;
+use crate::budgets;
 use crate::credits as credits_mod;
-use crate::db;
+use crate::currency::PriceContext;
+use crate::db::{self, now_datetime};
 use crate::error::AppError;
-use crate::models::{BalanceResponse, AppleIAPValidateRequest, AppleIAPValidateResponse};
+use crate::AppState;
+use crate::models::{OrgRole, BalanceResponse, AppleIAPValidateRequest, AppleIAPValidateResponse, GiftCreditsRequest, GiftCreditsResponse, UpdateBudgetRequest, UserBudget};
+use crate::organizations;
 use crate::pricing;
+use crate::rate_limit;
 use worker::{Request, Response, RouteContext};
 use serde::Deserialize;
 
-async fn get_balance_inner(req: Request, ctx: RouteContext<()>) -> Result<Response, AppError> {
+async fn get_balance_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
     let user_id = auth::extract_user_from_request(&req)?;
     let user = db::get_user_by_id(&ctx.env, &user_id).await?;
+    let price_context = PriceContext::from_request(&req);
 
     let response = BalanceResponse {
         credits_balance: user.credits_balance,
         usd_equivalent: pricing::credits_to_usd(user.credits_balance),
+        equivalent: price_context.credits_equivalent(user.credits_balance),
     };
 
     Response::from_json(&response).map_err(|e| e.into())
 }
 
-pub async fn get_balance(req: Request, ctx: RouteContext<()>) -> worker::Result<Response> {
+pub async fn get_balance(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
     get_balance_inner(req, ctx).await.or_else(|e| e.to_response())
 }
 
 async fn get_transactions_inner(
     req: Request,
-    ctx: RouteContext<()>,
+    ctx: RouteContext<AppState>,
 ) -> Result<Response, AppError> {
     let user_id = auth::extract_user_from_request(&req)?;
 
@@ -34,7 +41,7 @@ async fn get_transactions_inner(
     Response::from_json(&transactions).map_err(|e| e.into())
 }
 
-pub async fn get_transactions(req: Request, ctx: RouteContext<()>) -> worker::Result<Response> {
+pub async fn get_transactions(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
     get_transactions_inner(req, ctx).await.or_else(|e| e.to_response())
 }
 
@@ -53,7 +60,7 @@ struct AppleTransactionPayload {
 
 async fn validate_apple_iap_inner(
     mut req: Request,
-    ctx: RouteContext<()>,
+    ctx: RouteContext<AppState>,
 ) -> Result<Response, AppError> {
     let user_id = auth::extract_user_from_request(&req)?;
 
@@ -100,14 +107,31 @@ async fn validate_apple_iap_inner(
         return Err(AppError::BadRequest("Transaction already processed".into()));
     }
 
-    let new_balance = credits_mod::add_credits(
-        &ctx.env,
-        &user_id,
-        pricing::STARTER_PACK_CREDITS,
-        "Purchased Starter Pack",
-        Some(&transaction.transaction_id),
-    )
-    .await?;
+    let new_balance = match body.org_id.as_deref() {
+        Some(org_id) => {
+            organizations::require_role(&ctx.env, org_id, &user_id, OrgRole::Admin).await?;
+
+            credits_mod::add_org_credits(
+                &ctx.env,
+                org_id,
+                &user_id,
+                pricing::STARTER_PACK_CREDITS,
+                "Purchased Starter Pack for organization",
+                Some(&transaction.transaction_id),
+            )
+            .await?
+        }
+        None => {
+            credits_mod::add_credits(
+                &ctx.env,
+                &user_id,
+                pricing::STARTER_PACK_CREDITS,
+                "Purchased Starter Pack",
+                Some(&transaction.transaction_id),
+            )
+            .await?
+        }
+    };
 
     let response = AppleIAPValidateResponse {
         success: true,
@@ -119,17 +143,142 @@ async fn validate_apple_iap_inner(
     Response::from_json(&response).map_err(|e| e.into())
 }
 
-pub async fn validate_apple_iap(req: Request, ctx: RouteContext<()>) -> worker::Result<Response> {
+pub async fn validate_apple_iap(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
     validate_apple_iap_inner(req, ctx).await.or_else(|e| e.to_response())
 }
 
-pub async fn get_credit_packs(_req: Request, _ctx: RouteContext<()>) -> worker::Result<Response> {
+async fn get_budget_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let status = budgets::get_budget_status(&ctx.env, &user_id).await?;
+
+    Response::from_json(&status).map_err(|e| e.into())
+}
+
+pub async fn get_budget(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    get_budget_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn update_budget_inner(
+    mut req: Request,
+    ctx: RouteContext<AppState>,
+) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let body: UpdateBudgetRequest = req.json().await.map_err(|_| {
+        AppError::BadRequest("Invalid request body".into())
+    })?;
+
+    let mut budget = budgets::get_budget(&ctx.env, &user_id).await?.unwrap_or(UserBudget {
+        user_id: user_id.clone(),
+        daily_limit: None,
+        weekly_limit: None,
+        monthly_limit: None,
+        updated_at: now_datetime(),
+    });
+
+    for (update, current) in [
+        (body.daily_limit, &mut budget.daily_limit),
+        (body.weekly_limit, &mut budget.weekly_limit),
+        (body.monthly_limit, &mut budget.monthly_limit),
+    ] {
+        if let Some(limit) = update {
+            if limit.is_some_and(|l| l <= 0) {
+                return Err(AppError::BadRequest("Budget limits must be positive".into()));
+            }
+            *current = limit;
+        }
+    }
+
+    budget.updated_at = now_datetime();
+    budgets::save_budget(&ctx.env, &budget).await?;
+
+    let status = budgets::get_budget_status(&ctx.env, &user_id).await?;
+
+    Response::from_json(&status).map_err(|e| e.into())
+}
+
+pub async fn update_budget(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    update_budget_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+const MAX_GIFT_MESSAGE_LENGTH: usize = 200;
+
+async fn gift_credits_inner(
+    mut req: Request,
+    ctx: RouteContext<AppState>,
+) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let body: GiftCreditsRequest = req.json().await.map_err(|_| {
+        AppError::BadRequest("Invalid request body".into())
+    })?;
+
+    if body.amount <= 0 {
+        return Err(AppError::BadRequest("Gift amount must be positive".into()));
+    }
+
+    let message = body
+        .message
+        .as_deref()
+        .map(str::trim)
+        .filter(|m| !m.is_empty());
+
+    if message.is_some_and(|m| m.chars().count() > MAX_GIFT_MESSAGE_LENGTH) {
+        return Err(AppError::BadRequest(format!(
+            "Gift message must be at most {} characters",
+            MAX_GIFT_MESSAGE_LENGTH
+        )));
+    }
+
+    let recipient = match (body.recipient_referral_code.as_deref(), body.recipient_email.as_deref()) {
+        (Some(code), _) => db::get_user_by_referral_code(&ctx.env, code).await?,
+        (None, Some(email)) => db::get_user_by_email(&ctx.env, email).await?,
+        (None, None) => {
+            return Err(AppError::BadRequest(
+                "recipient_referral_code or recipient_email is required".into(),
+            ))
+        }
+    }
+    .ok_or_else(|| AppError::NotFound("Recipient not found".into()))?;
+
+    if recipient.id == user_id {
+        return Err(AppError::BadRequest("You can't gift credits to yourself".into()));
+    }
+
+    rate_limit::check_gift_limit(&ctx.env, &user_id, body.amount).await?;
+    credits_mod::ensure_giftable(&ctx.env, &user_id, body.amount).await?;
+
+    let sender = db::get_user_by_id(&ctx.env, &user_id).await?;
+    let limits = rate_limit::GiftLimits::from_env(&ctx.env);
+    let new_balance =
+        credits_mod::gift_credits(&ctx.env, &sender, &recipient, body.amount, message, &limits).await?;
+
+    let response = GiftCreditsResponse {
+        success: true,
+        amount: body.amount,
+        new_balance,
+        recipient_referral_code: recipient.referral_code,
+    };
+
+    Response::from_json(&response).map_err(|e| e.into())
+}
+
+pub async fn gift_credits(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    gift_credits_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+pub async fn get_credit_packs(req: Request, _ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    let price_context = PriceContext::from_request(&req);
+    let price = price_context.money(pricing::starter_pack_price_minor(price_context.currency));
+
     let packs = serde_json::json!([
         {
             "id": "sora_starter_pack",
             "name": "Starter Pack",
             "credits": pricing::STARTER_PACK_CREDITS,
             "price_usd": pricing::STARTER_PACK_PRICE_USD,
+            "price": price,
             "popular": true,
             "estimated_videos": {
                 "sora_2_5s": 10,
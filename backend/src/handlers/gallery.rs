@@ -0,0 +1,145 @@
+use crate::auth;
+use crate::db::{self, now_datetime};
+use crate::error::AppError;
+use crate::gallery::{self, Cursor, GallerySort};
+use crate::models::{GalleryListResponse, TakedownRequest, VideoStatus};
+use crate::moderation;
+use crate::AppState;
+use worker::{console_log, Request, Response, RouteContext, Url};
+
+const DEFAULT_PAGE_SIZE: u32 = 20;
+const MAX_PAGE_SIZE: u32 = 50;
+
+fn get_query_param(url: &Url, key: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.to_string())
+}
+
+async fn publish_video_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let video_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing video ID".into()))?;
+
+    let video = db::get_video_by_id(&ctx.env, video_id).await?;
+
+    if video.user_id != user_id {
+        return Err(AppError::NotFound("Video not found".into()));
+    }
+
+    if video.status != VideoStatus::Completed {
+        return Err(AppError::BadRequest(format!(
+            "Only completed videos can be published (status: {})",
+            video.status
+        )));
+    }
+
+    if gallery::is_taken_down(&ctx.env, &video.id).await? {
+        return Err(AppError::Forbidden("This video was removed from the gallery by a moderator".into()));
+    }
+
+    // The prompt is shown publicly, so it is screened again under today's
+    // rules even though it passed when the video was created.
+    moderation::check_prompt(&ctx.env, ctx.data.moderator.as_deref(), &user_id, &video.prompt).await?;
+
+    gallery::publish(&ctx.env, &video.id, &user_id, now_datetime()).await?;
+    console_log!("Video {} published to the gallery", video.id);
+
+    let item = gallery::get_item(&ctx.env, &video.id, &user_id).await?;
+    Response::from_json(&item).map_err(|e| e.into())
+}
+
+pub async fn publish_video(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    publish_video_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn unpublish_video_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let video_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing video ID".into()))?;
+
+    if !gallery::unpublish(&ctx.env, video_id, &user_id).await? {
+        return Err(AppError::NotFound("Gallery item not found".into()));
+    }
+
+    console_log!("Video {} unpublished", video_id);
+
+    Response::from_json(&serde_json::json!({ "success": true })).map_err(|e| e.into())
+}
+
+pub async fn unpublish_video(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    unpublish_video_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn list_gallery_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let url = req.url()?;
+    let sort = GallerySort::parse(get_query_param(&url, "sort").as_deref())?;
+    let cursor = get_query_param(&url, "cursor")
+        .map(|c| Cursor::decode(&c))
+        .transpose()?;
+    let limit = get_query_param(&url, "limit")
+        .and_then(|s| s.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let (items, next_cursor) = gallery::list_items(&ctx.env, &user_id, sort, cursor.as_ref(), limit).await?;
+
+    Response::from_json(&GalleryListResponse { items, next_cursor }).map_err(|e| e.into())
+}
+
+pub async fn list_gallery(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    list_gallery_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn set_liked(req: Request, ctx: RouteContext<AppState>, liked: bool) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let video_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing video ID".into()))?;
+
+    let like_count = gallery::set_liked(&ctx.env, video_id, &user_id, liked, now_datetime()).await?;
+
+    Response::from_json(&serde_json::json!({ "liked": liked, "like_count": like_count })).map_err(|e| e.into())
+}
+
+pub async fn like_item(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    set_liked(req, ctx, true).await.or_else(|e| e.to_response())
+}
+
+pub async fn unlike_item(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    set_liked(req, ctx, false).await.or_else(|e| e.to_response())
+}
+
+async fn take_down_item_inner(mut req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    auth::require_admin(&ctx.env, &user_id)?;
+
+    let video_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing video ID".into()))?
+        .to_string();
+
+    let text = req.text().await.map_err(|_| AppError::BadRequest("Invalid request body".into()))?;
+    let body: TakedownRequest = if text.trim().is_empty() {
+        TakedownRequest::default()
+    } else {
+        serde_json::from_str(&text).map_err(|_| AppError::BadRequest("Invalid request body".into()))?
+    };
+    let reason = body.reason.as_deref().map(str::trim).filter(|r| !r.is_empty());
+
+    if !gallery::take_down(&ctx.env, &video_id, &user_id, reason, now_datetime()).await? {
+        return Err(AppError::NotFound("Gallery item not found".into()));
+    }
+
+    console_log!("Gallery item {} taken down by {}: {:?}", video_id, user_id, reason);
+
+    Response::from_json(&serde_json::json!({ "success": true })).map_err(|e| e.into())
+}
+
+pub async fn take_down_item(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    take_down_item_inner(req, ctx).await.or_else(|e| e.to_response())
+}
@@ -0,0 +1,52 @@
+use crate::auth;
+use crate::error::AppError;
+use crate::AppState;
+use crate::images;
+use worker::{FormEntry, Request, Response, RouteContext};
+
+async fn upload_image_inner(mut req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let form = req.form_data().await.map_err(|_| {
+        AppError::BadRequest("Expected a multipart/form-data body".into())
+    })?;
+
+    let file = match form.get("file") {
+        Some(FormEntry::File(file)) => file,
+        _ => return Err(AppError::BadRequest("Missing image file field \"file\"".into())),
+    };
+
+    if file.size() > images::max_reference_image_bytes(&ctx.env) {
+        return Err(AppError::BadRequest("Image is too large".into()));
+    }
+
+    let bytes = file.bytes().await?;
+    let info = images::validate_reference_image(&ctx.env, &bytes, Some(&file.type_()))?;
+    let image = images::store_reference_image(&ctx.env, &user_id, bytes, info).await?;
+
+    Response::from_json(&image).map_err(|e| e.into())
+}
+
+pub async fn upload_image(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    upload_image_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn get_image_content_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let image_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing image ID".into()))?;
+
+    let image = images::get_reference_image(&ctx.env, image_id, &user_id).await?;
+    let bytes = images::load_reference_image_bytes(&ctx.env, &image).await?;
+
+    let mut resp = Response::from_bytes(bytes)?;
+    resp.headers_mut().set("Content-Type", &image.mime_type)?;
+    resp.headers_mut().set("Cache-Control", "private, max-age=86400")?;
+
+    Ok(resp)
+}
+
+pub async fn get_image_content(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    get_image_content_inner(req, ctx).await.or_else(|e| e.to_response())
+}
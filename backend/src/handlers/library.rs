@@ -0,0 +1,171 @@
+use crate::auth;
+use crate::db::now_datetime;
+use crate::error::AppError;
+use crate::library;
+use crate::models::{
+    Collection, CollectionListResponse, CollectionVideosRequest, CreateCollectionRequest, TagListResponse,
+    UpdateCollectionRequest,
+};
+use crate::AppState;
+use worker::{Request, Response, RouteContext};
+
+fn collection_id(ctx: &RouteContext<AppState>) -> Result<String, AppError> {
+    ctx.param("id")
+        .map(String::to_string)
+        .ok_or_else(|| AppError::BadRequest("Missing collection ID".into()))
+}
+
+async fn list_collections_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let collections = library::list_collections(&ctx.env, &user_id).await?;
+
+    Response::from_json(&CollectionListResponse { collections }).map_err(|e| e.into())
+}
+
+pub async fn list_collections(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    list_collections_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn get_collection_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let collection = library::get_collection(&ctx.env, &collection_id(&ctx)?, &user_id).await?;
+
+    Response::from_json(&collection).map_err(|e| e.into())
+}
+
+pub async fn get_collection(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    get_collection_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn create_collection_inner(mut req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let body: CreateCollectionRequest = req.json().await.map_err(|_| {
+        AppError::BadRequest("Invalid request body".into())
+    })?;
+
+    let now = now_datetime();
+    let collection = Collection {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id,
+        name: library::normalize_name(&body.name)?,
+        description: library::normalize_description(body.description.as_deref())?,
+        video_count: 0,
+        created_at: now,
+        updated_at: now,
+    };
+
+    library::save_collection(&ctx.env, &collection).await?;
+
+    Response::from_json(&collection).map_err(|e| e.into())
+}
+
+pub async fn create_collection(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    create_collection_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn update_collection_inner(mut req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let mut collection = library::get_collection(&ctx.env, &collection_id(&ctx)?, &user_id).await?;
+
+    let body: UpdateCollectionRequest = req.json().await.map_err(|_| {
+        AppError::BadRequest("Invalid request body".into())
+    })?;
+
+    if let Some(name) = body.name {
+        collection.name = library::normalize_name(&name)?;
+    }
+    if let Some(description) = body.description {
+        collection.description = library::normalize_description(description.as_deref())?;
+    }
+    collection.updated_at = now_datetime();
+
+    library::save_collection(&ctx.env, &collection).await?;
+
+    Response::from_json(&collection).map_err(|e| e.into())
+}
+
+pub async fn update_collection(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    update_collection_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn delete_collection_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let collection = library::get_collection(&ctx.env, &collection_id(&ctx)?, &user_id).await?;
+    library::delete_collection(&ctx.env, &collection.id).await?;
+
+    Response::from_json(&serde_json::json!({ "success": true })).map_err(|e| e.into())
+}
+
+pub async fn delete_collection(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    delete_collection_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn add_collection_videos_inner(mut req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let collection = library::get_collection(&ctx.env, &collection_id(&ctx)?, &user_id).await?;
+
+    let body: CollectionVideosRequest = req.json().await.map_err(|_| {
+        AppError::BadRequest("Invalid request body".into())
+    })?;
+
+    if body.video_ids.len() > library::MAX_VIDEOS_PER_REQUEST {
+        return Err(AppError::BadRequest(format!(
+            "Add at most {} videos per request",
+            library::MAX_VIDEOS_PER_REQUEST
+        )));
+    }
+
+    let added = library::add_videos(
+        &ctx.env,
+        &collection.id,
+        &user_id,
+        &body.video_ids,
+        &now_datetime().to_rfc3339(),
+    )
+    .await?;
+
+    let collection = library::get_collection(&ctx.env, &collection.id, &user_id).await?;
+
+    Response::from_json(&serde_json::json!({ "added": added, "collection": collection })).map_err(|e| e.into())
+}
+
+pub async fn add_collection_videos(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    add_collection_videos_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn remove_collection_video_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let video_id = ctx
+        .param("video_id")
+        .ok_or_else(|| AppError::BadRequest("Missing video ID".into()))?;
+
+    let collection = library::get_collection(&ctx.env, &collection_id(&ctx)?, &user_id).await?;
+
+    if !library::remove_video(&ctx.env, &collection.id, video_id).await? {
+        return Err(AppError::NotFound("Video is not in this collection".into()));
+    }
+
+    Response::from_json(&serde_json::json!({ "success": true })).map_err(|e| e.into())
+}
+
+pub async fn remove_collection_video(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    remove_collection_video_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn list_tags_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let tags = library::list_tags(&ctx.env, &user_id).await?;
+
+    Response::from_json(&TagListResponse { tags }).map_err(|e| e.into())
+}
+
+pub async fn list_tags(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    list_tags_inner(req, ctx).await.or_else(|e| e.to_response())
+}
@@ -3,3 +3,12 @@ pub mod videos;
 pub mod credits;
 pub mod webhooks;
 pub mod video_proxy;
+pub mod organizations;
+pub mod notifications;
+pub mod images;
+pub mod batches;
+pub mod templates;
+pub mod prompts;
+pub mod shares;
+pub mod gallery;
+pub mod library;
@@ -0,0 +1,62 @@
+use crate::auth;
+use crate::error::AppError;
+use crate::AppState;
+use crate::models::NotificationListResponse;
+use crate::notifications;
+use worker::{Request, Response, RouteContext};
+
+async fn list_notifications_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let url = req.url()?;
+    let unread_only = url
+        .query_pairs()
+        .any(|(k, v)| k == "unread_only" && (v == "true" || v == "1"));
+    let limit = url
+        .query_pairs()
+        .find(|(k, _)| k == "limit")
+        .and_then(|(_, v)| v.parse::<i32>().ok())
+        .unwrap_or(50)
+        .min(100);
+
+    let notifications = notifications::list_notifications(&ctx.env, &user_id, unread_only, limit).await?;
+    let unread_count = notifications::count_unread(&ctx.env, &user_id).await?;
+
+    let response = NotificationListResponse {
+        notifications,
+        unread_count,
+    };
+
+    Response::from_json(&response).map_err(|e| e.into())
+}
+
+pub async fn list_notifications(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    list_notifications_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn mark_notification_read_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let notification_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing notification ID".into()))?;
+
+    notifications::mark_read(&ctx.env, &user_id, Some(notification_id)).await?;
+
+    Response::from_json(&serde_json::json!({ "success": true })).map_err(|e| e.into())
+}
+
+pub async fn mark_notification_read(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    mark_notification_read_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn mark_all_notifications_read_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    notifications::mark_read(&ctx.env, &user_id, None).await?;
+
+    Response::from_json(&serde_json::json!({ "success": true })).map_err(|e| e.into())
+}
+
+pub async fn mark_all_notifications_read(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    mark_all_notifications_read_inner(req, ctx).await.or_else(|e| e.to_response())
+}
@@ -0,0 +1,270 @@
+use crate::auth;
+use crate::db;
+use crate::error::AppError;
+use crate::AppState;
+use crate::models::{
+    CreateInvitationRequest, CreateOrganizationRequest, OrgRole, OrganizationDetailResponse,
+    UpdateMemberRequest, VideoListResponse,
+};
+use crate::organizations;
+use worker::{Request, Response, RouteContext, Url};
+
+const MAX_ORGANIZATION_NAME_LENGTH: usize = 100;
+
+async fn create_organization_inner(
+    mut req: Request,
+    ctx: RouteContext<AppState>,
+) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let body: CreateOrganizationRequest = req.json().await.map_err(|_| {
+        AppError::BadRequest("Invalid request body".into())
+    })?;
+
+    let name = body.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_ORGANIZATION_NAME_LENGTH {
+        return Err(AppError::BadRequest(format!(
+            "Organization name must be between 1 and {} characters",
+            MAX_ORGANIZATION_NAME_LENGTH
+        )));
+    }
+
+    let organization = organizations::create_organization(&ctx.env, name, &user_id).await?;
+
+    Response::from_json(&organization).map_err(|e| e.into())
+}
+
+pub async fn create_organization(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    create_organization_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn list_organizations_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let organizations = organizations::list_user_organizations(&ctx.env, &user_id).await?;
+
+    Response::from_json(&organizations).map_err(|e| e.into())
+}
+
+pub async fn list_organizations(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    list_organizations_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn get_organization_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let org_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing organization ID".into()))?;
+
+    let membership = organizations::require_role(&ctx.env, org_id, &user_id, OrgRole::Member).await?;
+    let organization = organizations::get_organization(&ctx.env, org_id).await?;
+    let members = organizations::list_members(&ctx.env, org_id).await?;
+
+    let response = OrganizationDetailResponse {
+        organization,
+        role: membership.role,
+        members,
+    };
+
+    Response::from_json(&response).map_err(|e| e.into())
+}
+
+pub async fn get_organization(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    get_organization_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn create_invitation_inner(
+    mut req: Request,
+    ctx: RouteContext<AppState>,
+) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let org_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing organization ID".into()))?;
+
+    let inviter = organizations::require_role(&ctx.env, org_id, &user_id, OrgRole::Admin).await?;
+
+    let body: CreateInvitationRequest = req.json().await.map_err(|_| {
+        AppError::BadRequest("Invalid request body".into())
+    })?;
+
+    let role = body.role.unwrap_or(OrgRole::Member);
+    if role == OrgRole::Owner {
+        return Err(AppError::BadRequest("Organizations can only have one owner".into()));
+    }
+
+    if role == OrgRole::Admin && inviter.role != OrgRole::Owner {
+        return Err(AppError::Forbidden("Only the owner can invite admins".into()));
+    }
+
+    let email = body
+        .email
+        .map(|e| e.trim().to_string())
+        .filter(|e| !e.is_empty());
+
+    let invitation =
+        organizations::create_invitation(&ctx.env, org_id, &user_id, email, role).await?;
+
+    Response::from_json(&invitation).map_err(|e| e.into())
+}
+
+pub async fn create_invitation(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    create_invitation_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn accept_invitation_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let token = ctx
+        .param("token")
+        .ok_or_else(|| AppError::BadRequest("Missing invitation token".into()))?;
+
+    let user = db::get_user_by_id(&ctx.env, &user_id).await?;
+    let member = organizations::accept_invitation(&ctx.env, token, &user).await?;
+
+    Response::from_json(&member).map_err(|e| e.into())
+}
+
+pub async fn accept_invitation(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    accept_invitation_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn update_member_inner(
+    mut req: Request,
+    ctx: RouteContext<AppState>,
+) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let org_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing organization ID".into()))?;
+    let member_id = ctx
+        .param("user_id")
+        .ok_or_else(|| AppError::BadRequest("Missing member ID".into()))?;
+
+    let actor = organizations::require_role(&ctx.env, org_id, &user_id, OrgRole::Admin).await?;
+    let mut member = organizations::get_membership(&ctx.env, org_id, member_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Member not found".into()))?;
+
+    let body: UpdateMemberRequest = req.json().await.map_err(|_| {
+        AppError::BadRequest("Invalid request body".into())
+    })?;
+
+    if let Some(role) = body.role {
+        if role == OrgRole::Owner || member.role == OrgRole::Owner {
+            return Err(AppError::BadRequest("The owner role can't be transferred or changed".into()));
+        }
+
+        if (role == OrgRole::Admin || member.role == OrgRole::Admin) && actor.role != OrgRole::Owner {
+            return Err(AppError::Forbidden("Only the owner can change admin roles".into()));
+        }
+
+        member.role = role;
+    }
+
+    if let Some(cap) = body.monthly_credit_cap {
+        if cap.is_some_and(|c| c < 0) {
+            return Err(AppError::BadRequest("Monthly credit cap can't be negative".into()));
+        }
+
+        member.monthly_credit_cap = cap;
+    }
+
+    organizations::update_member(&ctx.env, org_id, member_id, member.role, member.monthly_credit_cap).await?;
+
+    Response::from_json(&member).map_err(|e| e.into())
+}
+
+pub async fn update_member(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    update_member_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn remove_member_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let org_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing organization ID".into()))?;
+    let member_id = ctx
+        .param("user_id")
+        .ok_or_else(|| AppError::BadRequest("Missing member ID".into()))?;
+
+    let minimum_role = if member_id == &user_id { OrgRole::Member } else { OrgRole::Admin };
+    let actor = organizations::require_role(&ctx.env, org_id, &user_id, minimum_role).await?;
+
+    let member = organizations::get_membership(&ctx.env, org_id, member_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Member not found".into()))?;
+
+    if member.role == OrgRole::Owner {
+        return Err(AppError::BadRequest("The owner can't be removed from the organization".into()));
+    }
+
+    if member.role == OrgRole::Admin && member_id != &user_id && actor.role != OrgRole::Owner {
+        return Err(AppError::Forbidden("Only the owner can remove admins".into()));
+    }
+
+    organizations::remove_member(&ctx.env, org_id, member_id).await?;
+
+    Response::from_json(&serde_json::json!({ "success": true })).map_err(|e| e.into())
+}
+
+pub async fn remove_member(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    remove_member_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn list_org_videos_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let org_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing organization ID".into()))?;
+
+    organizations::require_role(&ctx.env, org_id, &user_id, OrgRole::Admin).await?;
+
+    let url = req.url()?;
+    let limit = get_query_param(&url, "limit")
+        .and_then(|s| s.parse::<i32>().ok())
+        .unwrap_or(20)
+        .min(100);
+
+    let offset = get_query_param(&url, "offset")
+        .and_then(|s| s.parse::<i32>().ok())
+        .unwrap_or(0);
+
+    let (videos, total_count) = organizations::list_org_videos(&ctx.env, org_id, limit, offset).await?;
+
+    let has_more = (offset + limit) < total_count as i32;
+
+    let response = VideoListResponse {
+        videos,
+        has_more,
+        total_count,
+    };
+
+    Response::from_json(&response).map_err(|e| e.into())
+}
+
+pub async fn list_org_videos(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    list_org_videos_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn get_org_transactions_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let org_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing organization ID".into()))?;
+
+    organizations::require_role(&ctx.env, org_id, &user_id, OrgRole::Admin).await?;
+
+    let transactions = organizations::get_org_transactions(&ctx.env, org_id, 100).await?;
+
+    Response::from_json(&transactions).map_err(|e| e.into())
+}
+
+pub async fn get_org_transactions(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    get_org_transactions_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+fn get_query_param(url: &Url, key: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.to_string())
+}
@@ -0,0 +1,52 @@
+use crate::auth;
+use crate::error::AppError;
+use crate::AppState;
+use crate::models::{EnhancePromptRequest, EnhancePromptResponse};
+use crate::moderation;
+use crate::prompt_enhancer;
+use crate::prompt_templates;
+use worker::{console_log, Request, Response, RouteContext};
+
+async fn enhance_prompt_inner(mut req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let body: EnhancePromptRequest = req.json().await.map_err(|_| {
+        AppError::BadRequest("Invalid request body".into())
+    })?;
+
+    let prompt = body.prompt.trim().to_string();
+    if prompt.is_empty() {
+        return Err(AppError::BadRequest("Prompt can't be empty".into()));
+    }
+
+    let max_chars = prompt_templates::max_prompt_chars(&ctx.env);
+    if prompt.chars().count() > max_chars {
+        return Err(AppError::BadRequest(format!("Prompt is longer than {} characters", max_chars)));
+    }
+
+    moderation::check_prompt(&ctx.env, ctx.data.moderator.as_deref(), &user_id, &prompt).await?;
+
+    let (enhancement, new_balance) = prompt_enhancer::enhance_and_charge(
+        &ctx.env,
+        ctx.data.enhancer.as_ref(),
+        &user_id,
+        body.org_id.as_deref(),
+        &prompt,
+    )
+    .await?;
+    console_log!("Enhanced prompt for user {} ({} changes)", user_id, enhancement.changes.len());
+
+    let response = EnhancePromptResponse {
+        original_prompt: prompt,
+        enhanced_prompt: enhancement.prompt,
+        changes: enhancement.changes,
+        credits_cost: prompt_enhancer::enhance_credits(&ctx.env),
+        new_balance,
+    };
+
+    Response::from_json(&response).map_err(|e| e.into())
+}
+
+pub async fn enhance_prompt(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    enhance_prompt_inner(req, ctx).await.or_else(|e| e.to_response())
+}
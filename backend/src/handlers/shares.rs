@@ -0,0 +1,110 @@
+use crate::auth;
+use crate::db::{self, now_datetime};
+use crate::error::AppError;
+use crate::models::{CreateShareRequest, ShareResponse, VideoShare, VideoStatus};
+use crate::shares;
+use crate::AppState;
+use chrono::Duration;
+use worker::{console_log, Headers, Request, Response, RouteContext};
+
+async fn create_share_inner(mut req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let video_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing video ID".into()))?;
+
+    // The body is optional: an empty one creates a link without limits.
+    let text = req.text().await.map_err(|_| AppError::BadRequest("Invalid request body".into()))?;
+    let body: CreateShareRequest = if text.trim().is_empty() {
+        CreateShareRequest::default()
+    } else {
+        serde_json::from_str(&text).map_err(|_| AppError::BadRequest("Invalid request body".into()))?
+    };
+
+    shares::validate_limits(body.expires_in_hours, body.max_views)?;
+
+    let video = db::get_video_by_id(&ctx.env, video_id).await?;
+
+    if video.user_id != user_id {
+        return Err(AppError::NotFound("Video not found".into()));
+    }
+
+    if video.status != VideoStatus::Completed {
+        return Err(AppError::BadRequest(format!(
+            "Only completed videos can be shared (status: {})",
+            video.status
+        )));
+    }
+
+    let now = now_datetime();
+    let share = VideoShare {
+        slug: shares::generate_slug(),
+        video_id: video.id.clone(),
+        user_id,
+        expires_at: body.expires_in_hours.map(|hours| now + Duration::hours(hours)),
+        max_views: body.max_views,
+        view_count: 0,
+        revoked_at: None,
+        created_at: now,
+    };
+
+    shares::create_share(&ctx.env, &share).await?;
+    console_log!("Share {} created for video {}", share.slug, video.id);
+
+    let url = shares::page_url(&ctx.env, &share.slug)?;
+    Response::from_json(&ShareResponse { share, url }).map_err(|e| e.into())
+}
+
+pub async fn create_share(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    create_share_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn revoke_share_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let slug = ctx
+        .param("slug")
+        .ok_or_else(|| AppError::BadRequest("Missing share slug".into()))?;
+
+    if !shares::is_valid_slug(slug) || !shares::revoke_share(&ctx.env, slug, &user_id, now_datetime()).await? {
+        return Err(AppError::NotFound("Share not found".into()));
+    }
+
+    console_log!("Share {} revoked", slug);
+
+    Response::from_json(&serde_json::json!({ "success": true })).map_err(|e| e.into())
+}
+
+pub async fn revoke_share(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    revoke_share_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+fn html_response(html: String, status: u16) -> Result<Response, AppError> {
+    let headers = Headers::new();
+    headers.set("Content-Type", "text/html; charset=utf-8")?;
+    // Every load counts as a view, so nothing may serve the page from cache.
+    headers.set("Cache-Control", "no-store")?;
+    headers.set("X-Robots-Tag", "noindex")?;
+
+    Ok(Response::ok(html)?.with_headers(headers).with_status(status))
+}
+
+async fn share_page_inner(ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let slug = ctx.param("slug").map(String::as_str).unwrap_or_default();
+
+    if !shares::is_valid_slug(slug) {
+        return html_response(shares::render_unavailable_page(), 404);
+    }
+
+    let Some(share) = shares::record_view(&ctx.env, slug, now_datetime()).await? else {
+        return html_response(shares::render_unavailable_page(), 404);
+    };
+
+    let video = db::get_video_by_id(&ctx.env, &share.video_id).await?;
+    let service_url = shares::service_url(&ctx.env)?;
+
+    html_response(shares::render_page(&service_url, &video, &share.slug), 200)
+}
+
+pub async fn share_page(_req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    share_page_inner(ctx).await.or_else(|e| e.to_response())
+}
@@ -0,0 +1,137 @@
+use crate::auth;
+use crate::db::now_datetime;
+use crate::error::AppError;
+use crate::AppState;
+use crate::models::{CreatePromptTemplateRequest, PromptTemplate, PromptTemplateListResponse, UpdatePromptTemplateRequest};
+use crate::prompt_templates;
+use worker::{Request, Response, RouteContext};
+
+/// Loads a template the user may change: their own, or a global one if they
+/// are an admin.
+async fn get_editable_template(
+    ctx: &RouteContext<AppState>,
+    template_id: &str,
+    user_id: &str,
+) -> Result<PromptTemplate, AppError> {
+    let template = prompt_templates::get_template(&ctx.env, template_id, user_id).await?;
+
+    if template.user_id.is_none() {
+        auth::require_admin(&ctx.env, user_id)?;
+    }
+
+    Ok(template)
+}
+
+async fn list_templates_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let templates = prompt_templates::list_templates(&ctx.env, &user_id).await?;
+
+    Response::from_json(&PromptTemplateListResponse { templates }).map_err(|e| e.into())
+}
+
+pub async fn list_templates(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    list_templates_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn get_template_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let template_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing template ID".into()))?;
+
+    let template = prompt_templates::get_template(&ctx.env, template_id, &user_id).await?;
+
+    Response::from_json(&template).map_err(|e| e.into())
+}
+
+pub async fn get_template(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    get_template_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn create_template_inner(mut req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let body: CreatePromptTemplateRequest = req.json().await.map_err(|_| {
+        AppError::BadRequest("Invalid request body".into())
+    })?;
+
+    if body.global {
+        auth::require_admin(&ctx.env, &user_id)?;
+    }
+
+    let name = body.name.trim().to_string();
+    let description = body.description.map(|d| d.trim().to_string()).filter(|d| !d.is_empty());
+    prompt_templates::validate_template(&ctx.env, &name, description.as_deref(), &body.body)?;
+
+    let now = now_datetime();
+    let template = PromptTemplate {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id: (!body.global).then_some(user_id),
+        name,
+        description,
+        variables: prompt_templates::placeholders(&body.body)?,
+        body: body.body,
+        created_at: now,
+        updated_at: now,
+    };
+
+    prompt_templates::save_template(&ctx.env, &template).await?;
+
+    Response::from_json(&template).map_err(|e| e.into())
+}
+
+pub async fn create_template(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    create_template_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn update_template_inner(mut req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let template_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing template ID".into()))?;
+
+    let mut template = get_editable_template(&ctx, template_id, &user_id).await?;
+
+    let body: UpdatePromptTemplateRequest = req.json().await.map_err(|_| {
+        AppError::BadRequest("Invalid request body".into())
+    })?;
+
+    if let Some(name) = body.name {
+        template.name = name.trim().to_string();
+    }
+    if let Some(description) = body.description {
+        template.description = description.map(|d| d.trim().to_string()).filter(|d| !d.is_empty());
+    }
+    if let Some(template_body) = body.body {
+        template.body = template_body;
+    }
+
+    prompt_templates::validate_template(&ctx.env, &template.name, template.description.as_deref(), &template.body)?;
+    template.variables = prompt_templates::placeholders(&template.body)?;
+    template.updated_at = now_datetime();
+
+    prompt_templates::save_template(&ctx.env, &template).await?;
+
+    Response::from_json(&template).map_err(|e| e.into())
+}
+
+pub async fn update_template(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    update_template_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn delete_template_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let template_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing template ID".into()))?;
+
+    let template = get_editable_template(&ctx, template_id, &user_id).await?;
+    prompt_templates::delete_template(&ctx.env, &template.id).await?;
+
+    Response::from_json(&serde_json::json!({ "success": true })).map_err(|e| e.into())
+}
+
+pub async fn delete_template(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    delete_template_inner(req, ctx).await.or_else(|e| e.to_response())
+}
@@ -1,24 +1,203 @@
 use crate::auth;
-use crate::db;
+use crate::db::{self, now_datetime};
 use crate::error::AppError;
-use worker::{Request, Response, RouteContext};
+use crate::gallery;
+use crate::http_range::{self, ByteRange, ByteRanges, ByteStream, RangeOutcome};
+use crate::media_cache;
+use crate::media_storage;
+use crate::models::Video;
+use crate::shares;
+use crate::AppState;
+use chrono::DateTime;
+use worker::{console_log, Env, FixedLengthStream, Headers, Object, ObjectBody, Range, Request, Response, ResponseBuilder, RouteContext};
+
+/// Upstream headers the client needs to seek and revalidate.
+const PASSTHROUGH_HEADERS: [&str; 6] = [
+    "Content-Type",
+    "Content-Length",
+    "Content-Range",
+    "ETag",
+    "Last-Modified",
+    "Accept-Ranges",
+];
+
+/// What clients may do with proxied media. Responses depend on who asked,
+/// so shared caches must not keep them; the edge copy is managed by
+/// `media_cache`.
+const CLIENT_CACHE_CONTROL: &str = "private, max-age=86400";
+
+/// The request headers that decide which bytes, if any, are sent.
+struct Preconditions {
+    range: Option<String>,
+    if_range: Option<String>,
+    if_none_match: Option<String>,
+    if_modified_since: Option<String>,
+}
+
+impl Preconditions {
+    fn from_request(req: &Request) -> Result<Self, AppError> {
+        let headers = req.headers();
+
+        Ok(Self {
+            range: headers.get("Range")?,
+            if_range: headers.get("If-Range")?,
+            if_none_match: headers.get("If-None-Match")?,
+            if_modified_since: headers.get("If-Modified-Since")?,
+        })
+    }
+
+    fn not_modified(&self, headers: &Headers) -> Result<bool, AppError> {
+        Ok(media_cache::not_modified(
+            self.if_none_match.as_deref(),
+            self.if_modified_since.as_deref(),
+            headers.get("ETag")?.as_deref(),
+            headers.get("Last-Modified")?.as_deref(),
+        ))
+    }
+}
+
+fn set_client_caching(headers: &Headers) -> Result<(), AppError> {
+    headers.set("Cache-Control", CLIENT_CACHE_CONTROL)?;
+    headers.set("Vary", "Authorization")?;
+    Ok(())
+}
+
+/// Answers a conditional GET with 304 when the client's copy is current.
+fn revalidate(response: Response, preconditions: &Preconditions) -> Result<Response, AppError> {
+    if response.status_code() == 200 && preconditions.not_modified(response.headers())? {
+        return media_cache::not_modified_response(response.headers());
+    }
+
+    Ok(response)
+}
+
+fn http_date(millis: u64) -> Option<String> {
+    DateTime::from_timestamp_millis(millis as i64).map(|d| d.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+}
+
+/// Headers describing a stored object, shared by GET and HEAD.
+fn object_headers(object: &Object) -> Result<Headers, AppError> {
+    let headers = Headers::new();
+    let content_type = object.http_metadata().content_type.unwrap_or_else(|| "video/mp4".to_string());
+
+    headers.set("Content-Type", &content_type)?;
+    headers.set("Accept-Ranges", "bytes")?;
+    headers.set("ETag", &object.http_etag())?;
+    if let Some(last_modified) = http_date(object.uploaded().as_millis()) {
+        headers.set("Last-Modified", &last_modified)?;
+    }
+    set_client_caching(&headers)?;
+
+    Ok(headers)
+}
+
+async fn read_stored(env: &Env, video: &Video, variant: &str, range: Option<ByteRange>) -> Result<Object, AppError> {
+    media_storage::get_asset(env, video, variant, range.map(Range::from))
+        .await?
+        .ok_or_else(|| AppError::NotFound("Stored video content not found".into()))
+}
+
+fn stored_body(object: &Object) -> Result<ObjectBody<'_>, AppError> {
+    object
+        .body()
+        .ok_or_else(|| AppError::NotFound("Stored video content not found".into()))
+}
+
+/// Streams a stored variant. `head` is the object's metadata, used to
+/// answer conditional requests and resolve `Range` and `If-Range` before
+/// any bytes are read.
+async fn stored_response(
+    env: &Env,
+    video: &Video,
+    variant: &str,
+    head: Object,
+    preconditions: &Preconditions,
+) -> Result<Response, AppError> {
+    let headers = object_headers(&head)?;
+    let size = head.size();
+    let etag = head.http_etag();
+    let last_modified = headers.get("Last-Modified")?;
+
+    if preconditions.not_modified(&headers)? {
+        return media_cache::not_modified_response(&headers);
+    }
+
+    match http_range::evaluate(
+        preconditions.range.as_deref(),
+        preconditions.if_range.as_deref(),
+        Some(&etag),
+        last_modified.as_deref(),
+        size,
+    ) {
+        RangeOutcome::Full => {
+            let object = read_stored(env, video, variant, None).await?;
+            headers.set("Content-Length", &size.to_string())?;
+            let body = stored_body(&object)?.response_body()?;
+            Ok(ResponseBuilder::new().with_headers(headers).body(body))
+        }
+        RangeOutcome::Unsatisfiable => {
+            headers.set("Content-Range", &format!("bytes */{}", size))?;
+            Ok(ResponseBuilder::new().with_status(416).with_headers(headers).empty())
+        }
+        RangeOutcome::Partial(ranges) if ranges.len() == 1 => {
+            let range = ranges[0];
+            let object = read_stored(env, video, variant, Some(range)).await?;
+            headers.set("Content-Range", &range.content_range(size))?;
+            headers.set("Content-Length", &range.len().to_string())?;
+            let body = stored_body(&object)?.response_body()?;
+            Ok(ResponseBuilder::new().with_status(206).with_headers(headers).body(body))
+        }
+        RangeOutcome::Partial(ranges) => {
+            let content_type = headers.get("Content-Type")?.unwrap_or_else(|| "video/mp4".to_string());
+            let multipart = ByteRanges::new(&content_type, size);
+
+            let mut parts: Vec<(ByteRange, ByteStream)> = Vec::with_capacity(ranges.len());
+            for range in &ranges {
+                let object = read_stored(env, video, variant, Some(*range)).await?;
+                parts.push((*range, Box::pin(stored_body(&object)?.stream()?)));
+            }
+
+            let length = multipart.content_length(&ranges);
+            headers.set("Content-Type", &multipart.content_type())?;
+            headers.set("Content-Length", &length.to_string())?;
+
+            let body: worker::worker_sys::FixedLengthStream =
+                FixedLengthStream::wrap(multipart.into_stream(parts), length).into();
+            Ok(ResponseBuilder::new()
+                .with_status(206)
+                .with_headers(headers)
+                .stream(body.readable()))
+        }
+    }
+}
+
+/// Passes an upstream or edge-cached response through without buffering
+/// it.
+fn passthrough_response(upstream: Response) -> Result<Response, AppError> {
+    let headers = Headers::new();
+
+    for name in PASSTHROUGH_HEADERS {
+        if let Some(value) = upstream.headers().get(name)? {
+            headers.set(name, &value)?;
+        }
+    }
+    if !headers.has("Accept-Ranges")? {
+        headers.set("Accept-Ranges", "bytes")?;
+    }
+    set_client_caching(&headers)?;
+
+    let status = upstream.status_code();
+    let (_, body) = upstream.into_parts();
+
+    Ok(ResponseBuilder::new().with_status(status).with_headers(headers).body(body))
+}
 
 async fn proxy_video_content_inner(
     req: Request,
-    ctx: RouteContext<()>,
+    ctx: RouteContext<AppState>,
 ) -> Result<Response, AppError> {
     let url = req.url()?;
 
-    let user_id = match auth::extract_user_from_request(&req) {
-        Ok(uid) => uid,
-        Err(_) => {
-            url.query_pairs()
-                .find(|(k, _)| k == "user_id")
-                .map(|(_, v)| v.to_string())
-                .ok_or_else(|| AppError::Unauthorized("Missing authentication".into()))?
-        }
-    };
-
     let path_segments: Vec<&str> = url.path_segments().ok_or_else(|| {
         AppError::BadRequest("Invalid URL".into())
     })?.collect();
@@ -29,8 +208,31 @@ async fn proxy_video_content_inner(
 
     let video = db::get_video_by_openai_id(&ctx.env, openai_video_id).await?;
 
-    if video.user_id != user_id {
-        return Err(AppError::Unauthorized("Not authorized to access this video".into()));
+    let share = url.query_pairs().find(|(k, _)| k == "share").map(|(_, v)| v.to_string());
+    let from_gallery = url.query_pairs().any(|(k, v)| k == "gallery" && v == "1");
+    match share {
+        // Share links are the only way to reach media without signing in.
+        Some(slug) => shares::authorize_media(&ctx.env, &slug, &video.id, now_datetime()).await?,
+        // Like the feed itself, gallery media is for signed-in users only.
+        None if from_gallery => {
+            auth::extract_user_from_request(&req)?;
+            gallery::authorize_media(&ctx.env, &video.id).await?;
+        }
+        None => {
+            let user_id = match auth::extract_user_from_request(&req) {
+                Ok(uid) => uid,
+                Err(_) => {
+                    url.query_pairs()
+                        .find(|(k, _)| k == "user_id")
+                        .map(|(_, v)| v.to_string())
+                        .ok_or_else(|| AppError::Unauthorized("Missing authentication".into()))?
+                }
+            };
+
+            if video.user_id != user_id {
+                return Err(AppError::Unauthorized("Not authorized to access this video".into()));
+            }
+        }
     }
 
     let url_string = req.url()?.to_string();
@@ -42,105 +244,83 @@ async fn proxy_video_content_inner(
         "video"
     };
 
-    let api_key = ctx.env
-        .secret("OPENAI_API_KEY")
-        .map_err(|_| AppError::InternalError("OPENAI_API_KEY not configured".into()))?
-        .to_string();
+    if req.method() == worker::Method::Head {
+        if let Some(object) = media_storage::head_asset(&ctx.env, &video, variant).await? {
+            let headers = object_headers(&object)?;
+            headers.set("Content-Length", &object.size().to_string())?;
 
-    let download_url = format!(
-        "https://api.openai.com/v1/videos/{}/content?variant={}",
-        openai_video_id, variant
-    );
+            return Ok(ResponseBuilder::new().with_headers(headers).empty());
+        }
 
-    if req.method() == worker::Method::Head {
-        let headers = worker::Headers::new();
-        headers.set("Authorization", &format!("Bearer {}", api_key))?;
-        headers.set("Range", "bytes=0-0")?;
-
-        let range_req = Request::new_with_init(
-            &download_url,
-            &worker::RequestInit::new()
-                .with_method(worker::Method::Get)
-                .with_headers(headers),
-        )?;
-
-        let openai_response = worker::Fetch::Request(range_req).send().await?;
-
-        let mut resp = Response::ok("")?;
-        resp.headers_mut().set("Content-Type", "video/mp4")?;
-        resp.headers_mut().set("Accept-Ranges", "bytes")?;
-
-        if let Some(content_length) = openai_response.headers().get("Content-Length")? {
-            resp.headers_mut().set("Content-Length", &content_length)?;
-        } else if let Some(range) = openai_response.headers().get("Content-Range")? {
-            if let Some(total) = range.split('/').last() {
-                resp.headers_mut().set("Content-Length", total)?;
+        // OpenAI has no HEAD for content, so until the video is in R2 the
+        // size comes from a one-byte ranged GET.
+        let openai_response = ctx
+            .data
+            .provider
+            .download_content(openai_video_id, variant, Some("bytes=0-0"))
+            .await?;
+
+        let headers = Headers::new();
+        headers.set("Content-Type", "video/mp4")?;
+        headers.set("Accept-Ranges", "bytes")?;
+
+        if let Some(range) = openai_response.headers().get("Content-Range")? {
+            if let Some(total) = range.split('/').next_back() {
+                headers.set("Content-Length", total)?;
+            }
+        } else if let Some(content_length) = openai_response.headers().get("Content-Length")? {
+            headers.set("Content-Length", &content_length)?;
+        }
+        for name in ["Content-Type", "ETag", "Last-Modified"] {
+            if let Some(value) = openai_response.headers().get(name)? {
+                headers.set(name, &value)?;
             }
         }
 
-        resp.headers_mut().set("Cache-Control", "public, max-age=86400")?;
-        return Ok(resp);
-    }
-
-    let headers = worker::Headers::new();
-    headers.set("Authorization", &format!("Bearer {}", api_key))?;
-
-    let download_req = Request::new_with_init(
-        &download_url,
-        &worker::RequestInit::new()
-            .with_method(worker::Method::Get)
-            .with_headers(headers),
-    )?;
-
-    let mut openai_response = worker::Fetch::Request(download_req).send().await?;
-
-    if openai_response.status_code() < 200 || openai_response.status_code() >= 300 {
-        let error_text = openai_response.text().await.unwrap_or_else(|_| "Unknown error".into());
-        return Err(AppError::ExternalApiError(format!(
-            "Failed to download from OpenAI ({}): {}",
-            openai_response.status_code(),
-            error_text
-        )));
+        set_client_caching(&headers)?;
+        return Ok(ResponseBuilder::new().with_headers(headers).empty());
     }
 
-    let body = openai_response.bytes().await?;
-    let total_length = body.len();
+    let preconditions = Preconditions::from_request(&req)?;
 
-    let range_header = req.headers().get("Range")?;
+    // Only after the ownership check above: the cache entry is shared by
+    // every viewer of the video.
+    let cached = media_cache::is_cached_variant(variant) && preconditions.range.is_none();
+    if cached {
+        if let Some(hit) = media_cache::lookup(&ctx.env, &video.id, variant).await? {
+            return revalidate(passthrough_response(hit)?, &preconditions);
+        }
+    }
 
-    if let Some(range_str) = range_header {
-        if let Some(range_value) = range_str.strip_prefix("bytes=") {
-            if let Some((start_str, end_str)) = range_value.split_once('-') {
-                let start: usize = start_str.parse().unwrap_or(0);
-                let end: usize = if end_str.is_empty() {
-                    total_length - 1
-                } else {
-                    end_str.parse().unwrap_or(total_length - 1).min(total_length - 1)
-                };
+    let mut response = match media_storage::head_asset(&ctx.env, &video, variant).await? {
+        Some(head) => stored_response(&ctx.env, &video, variant, head, &preconditions).await?,
+        None => {
+            // Until the video is in R2 we can't check If-Range or split
+            // multiple ranges ourselves, so only a plain single range is
+            // forwarded and anything else gets the whole file.
+            let forwarded_range = preconditions.range.as_deref().filter(|header| {
+                preconditions.if_range.is_none() && http_range::parse(header).is_some_and(|specs| specs.len() == 1)
+            });
 
-                let range_body = &body[start..=end];
-                let mut resp = Response::from_bytes(range_body.to_vec())?.with_status(206);
+            let openai_response = ctx
+                .data
+                .provider
+                .download_content(openai_video_id, variant, forwarded_range)
+                .await?;
 
-                resp.headers_mut().set("Content-Type", "video/mp4")?;
-                resp.headers_mut().set("Content-Length", &(end - start + 1).to_string())?;
-                resp.headers_mut().set("Content-Range", &format!("bytes {}-{}/{}", start, end, total_length))?;
-                resp.headers_mut().set("Accept-Ranges", "bytes")?;
-                resp.headers_mut().set("Cache-Control", "public, max-age=86400")?;
+            passthrough_response(openai_response)?
+        }
+    };
 
-                return Ok(resp);
-            }
+    if cached {
+        if let Err(e) = media_cache::store(&ctx.env, &video.id, variant, &mut response).await {
+            console_log!("Failed to cache {} for video {}: {:?}", variant, video.id, e);
         }
     }
 
-    let mut resp = Response::from_bytes(body)?;
-    resp.headers_mut().set("Content-Type", "video/mp4")?;
-    resp.headers_mut().set("Content-Length", &total_length.to_string())?;
-    resp.headers_mut().set("Accept-Ranges", "bytes")?;
-    resp.headers_mut().set("Cache-Control", "public, max-age=86400")?;
-
-    Ok(resp)
+    revalidate(response, &preconditions)
 }
 
-pub async fn proxy_video_content(req: Request, ctx: RouteContext<()>) -> worker::Result<Response> {
+pub async fn proxy_video_content(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
     proxy_video_content_inner(req, ctx).await.or_else(|e| e.to_response())
 }
@@ -1,25 +1,29 @@
 use crate::auth;
-use crate::credits;
-use crate::db;
+use crate::budgets;
+use crate::credits::{self, Charge};
+use crate::currency::PriceContext;
+use crate::db::{self, now_datetime};
 use crate::error::AppError;
-use crate::models::{CreateVideoRequest, CreateVideoResponse, EstimateRequest, EstimateResponse, VideoListResponse, Video};
-use crate::openai_client;
+use crate::AppState;
+use crate::generation_queue::{self, QueueLimits};
+use crate::images;
+use crate::library::{self, VideoFilter};
+use crate::media_cache;
+use crate::media_storage;
+use crate::moderation;
+use crate::models::{CancelVideoResponse, CreateVideoRequest, VideoStatus, CreateVideoResponse, EstimateRequest, EstimateResponse, RemixVideoRequest, UpdateVideoRequest, VideoDetailResponse, VideoListResponse, VideoSearchResponse, Video};
+use crate::organizations;
 use crate::pricing;
+use crate::prompt_enhancer;
+use crate::prompt_templates;
 use crate::rate_limit;
-use worker::{console_log, Request, Response, RouteContext, Url};
-use chrono::{DateTime, Utc};
-
-fn now_datetime() -> DateTime<Utc> {
-    let ms = worker::Date::now().as_millis();
-    let secs = (ms / 1000) as i64;
-    let nsecs = ((ms % 1000) * 1_000_000) as u32;
-    DateTime::from_timestamp(secs, nsecs)
-        .unwrap_or_else(|| DateTime::from_timestamp(0, 0).unwrap())
-}
+use crate::search;
+use crate::video_lifecycle;
+use worker::{console_log, FormEntry, Request, Response, RouteContext, Url};
 
 async fn create_video_inner(
     mut req: Request,
-    ctx: RouteContext<()>,
+    ctx: RouteContext<AppState>,
 ) -> Result<Response, AppError> {
     console_log!("Starting video creation");
 
@@ -29,93 +33,223 @@ async fn create_video_inner(
     rate_limit::check_rate_limit(&ctx.env, &user_id).await?;
     console_log!("Rate limit check passed");
 
-    let body: CreateVideoRequest = req.json().await.map_err(|e| {
-        console_log!("Failed to parse request body: {:?}", e);
-        AppError::BadRequest("Invalid request body".into())
-    })?;
+    let is_multipart = req
+        .headers()
+        .get("Content-Type")?
+        .is_some_and(|ct| ct.starts_with("multipart/form-data"));
+
+    let (body, uploaded_image) = if is_multipart {
+        parse_multipart_create_request(&mut req).await?
+    } else {
+        let body: CreateVideoRequest = req.json().await.map_err(|e| {
+            console_log!("Failed to parse request body: {:?}", e);
+            AppError::BadRequest("Invalid request body".into())
+        })?;
+        (body, None)
+    };
     console_log!("Request body parsed: model={}, size={}, seconds={}", body.model, body.size, body.seconds);
 
     pricing::validate_video_params(&body.model, &body.size, body.seconds)?;
     console_log!("Video params validated");
 
+    let resolved = prompt_templates::resolve_prompt(&ctx.env, &user_id, &body).await?;
+    if let Some(template_id) = &resolved.template_id {
+        console_log!("Rendered prompt from template {}", template_id);
+    }
+
+    moderation::check_prompt(&ctx.env, ctx.data.moderator.as_deref(), &user_id, &resolved.prompt).await?;
+    console_log!("Prompt moderation passed");
+
+    // Uploaded images are only validated here. They're stored once the video
+    // has been paid for, so rejected requests don't leave objects in R2.
+    let (reference_image, upload) = match (uploaded_image, body.reference_image_id.as_deref()) {
+        (Some(file), _) => {
+            let bytes = file.bytes().await?;
+            let info = images::validate_reference_image(&ctx.env, &bytes, Some(&file.type_()))?;
+            images::check_matches_size(info.width as i64, info.height as i64, &body.size)?;
+            (None, Some((bytes, info)))
+        }
+        (None, Some(image_id)) => {
+            let image = images::get_reference_image(&ctx.env, image_id, &user_id).await?;
+            images::check_matches_size(image.width, image.height, &body.size)?;
+            console_log!("Using reference image {} ({}x{})", image.id, image.width, image.height);
+            (Some(image), None)
+        }
+        (None, None) => (None, None),
+    };
+
     let credits_cost = pricing::calculate_credits(&body.model, body.seconds)?;
     console_log!("Credits cost calculated: {}", credits_cost);
 
-    let video_id = uuid::Uuid::new_v4().to_string();
-    console_log!("Generated video ID: {}", video_id);
-
-    console_log!("Deducting {} credits from user {}", credits_cost, user_id);
-    let new_balance = credits::deduct_credits_with_lock(
-        &ctx.env,
-        &user_id,
-        &video_id,
+    let mut video = Video::new_pending(
+        user_id,
+        body.model,
+        resolved.prompt,
+        body.size,
+        body.seconds,
         credits_cost,
-    )
-    .await?;
+        now_datetime(),
+    );
+    video.org_id = body.org_id;
+    video.reference_image_id = reference_image.map(|image| image.id);
+    video.template_id = resolved.template_id;
+    video.template_variables = resolved.variables;
+
+    let enhancement_fee = if body.enhance { prompt_enhancer::enhance_credits(&ctx.env) } else { 0 };
+    check_can_enqueue(&ctx, &video, enhancement_fee).await?;
+
+    if body.enhance {
+        let enhancement = prompt_enhancer::enhance(&ctx.env, ctx.data.enhancer.as_ref(), &video.prompt).await?;
+        console_log!("Prompt enhanced ({} changes)", enhancement.changes.len());
+
+        video.original_prompt = Some(std::mem::replace(&mut video.prompt, enhancement.prompt));
+        moderation::check_prompt(&ctx.env, ctx.data.moderator.as_deref(), &video.user_id, &video.prompt).await?;
+    }
+
+    let response = enqueue_video(&ctx, video, upload, enhancement_fee).await?;
+    console_log!("Video creation completed successfully");
+
+    Response::from_json(&response).map_err(|e| e.into())
+}
+
+/// Fails fast, before any paid work such as prompt enhancement, when the
+/// video plus `extra_credits` wouldn't fit the budget, the queue or the
+/// balance. `enqueue_video` enforces the same limits atomically.
+async fn check_can_enqueue(ctx: &RouteContext<AppState>, video: &Video, extra_credits: i64) -> Result<(), AppError> {
+    let total = video.credits_cost + extra_credits;
+
+    if video.org_id.is_none() {
+        budgets::check_budget(&ctx.env, &video.user_id, total).await?;
+        console_log!("Budget check passed");
+    }
+
+    generation_queue::check_capacity(&ctx.env, &video.user_id, &QueueLimits::from_env(&ctx.env), 1).await?;
+    credits::ensure_balance(&ctx.env, &video.user_id, video.org_id.as_deref(), total).await?;
+
+    Ok(())
+}
+
+/// Charges the video (and its prompt enhancement fee, if any), stores its
+/// uploaded reference image, adds it to the user's queue and submits it
+/// straight away if they have a free slot. If the video fails, here or later
+/// in the queue, both charges are refunded together.
+async fn enqueue_video(
+    ctx: &RouteContext<AppState>,
+    mut video: Video,
+    upload: Option<(Vec<u8>, images::ImageInfo)>,
+    enhancement_fee: i64,
+) -> Result<CreateVideoResponse, AppError> {
+    let user_id = video.user_id.clone();
+    let limits = QueueLimits::from_env(&ctx.env);
+
+    let mut charges = vec![Charge::video(&video.id, video.credits_cost)];
+    if enhancement_fee > 0 {
+        charges.push(Charge::prompt_enhancement(Some(&video.id), enhancement_fee));
+    }
+
+    console_log!("Deducting {} credits from user {}", video.credits_cost + enhancement_fee, user_id);
+    let new_balance = credits::reserve_credits(&ctx.env, &user_id, video.org_id.as_deref(), &charges).await?;
     console_log!("Credits deducted. New balance: {}", new_balance);
 
-    let result = async {
-        console_log!("Calling OpenAI API to create video");
-        let openai_response = openai_client::create_video(
-            &ctx.env,
-            &body.model,
-            &body.prompt,
-            &body.size,
-            body.seconds,
-        )
-        .await?;
-        console_log!("OpenAI video created successfully: {}", openai_response.id);
-
-        let video = Video::new(
-            user_id.clone(),
-            openai_response.id.clone(),
-            body.model,
-            body.prompt,
-            body.size,
-            body.seconds,
-            credits_cost,
-            now_datetime(),
-        );
-
-        console_log!("Inserting video into database");
-        db::insert_video(&ctx.env, &video).await?;
-        console_log!("Video inserted successfully");
-
-        let response = CreateVideoResponse {
-            id: video.id,
-            status: video.status,
-            credits_cost,
-            new_balance,
-            estimated_wait_seconds: 120,
-        };
-
-        Ok::<_, AppError>(response)
-    }
-    .await;
-
-    match result {
-        Ok(response) => {
-            credits::release_lock(&ctx.env, &user_id).await?;
-            console_log!("Video creation completed successfully");
-            Response::from_json(&response).map_err(|e| e.into())
-        }
-        Err(e) => {
-            console_log!("Video creation failed: {:?}", e);
-            credits::refund_credits(&ctx.env, &user_id, &video_id, credits_cost).await?;
-            credits::release_lock(&ctx.env, &user_id).await?;
-            Err(e)
+    if let Err(e) = add_to_queue(ctx, &mut video, upload, &limits).await {
+        credits::refund_video_charges(&ctx.env, &user_id, &video.id, "Video generation failed - credits refunded").await?;
+        return Err(e);
+    }
+
+    let failures = generation_queue::submit_pending(&ctx.env, ctx.data.provider.as_ref(), &user_id).await?;
+    if let Some((_, e)) = failures.into_iter().find(|(id, _)| *id == video.id) {
+        // Failing the video refunded both of its charges.
+        return Err(e);
+    }
+
+    if video.org_id.is_none() {
+        if let Err(e) = budgets::record_budget_alerts(&ctx.env, &user_id).await {
+            console_log!("Failed to record budget alerts: {:?}", e);
         }
     }
+
+    let video = db::get_video_by_id(&ctx.env, &video.id).await?;
+    let queue_position = match video.status {
+        VideoStatus::PendingSubmission => Some(generation_queue::queue_position(&ctx.env, &video).await?),
+        _ => None,
+    };
+
+    Ok(CreateVideoResponse {
+        enhanced_prompt: video.original_prompt.is_some().then(|| video.prompt.clone()),
+        id: video.id,
+        status: video.status,
+        credits_cost: video.credits_cost,
+        new_balance,
+        estimated_wait_seconds: 120 * (1 + queue_position.unwrap_or(0) as i32),
+        queue_position,
+    })
+}
+
+async fn add_to_queue(
+    ctx: &RouteContext<AppState>,
+    video: &mut Video,
+    upload: Option<(Vec<u8>, images::ImageInfo)>,
+    limits: &QueueLimits,
+) -> Result<(), AppError> {
+    if let Some((bytes, info)) = upload {
+        let image = images::store_reference_image(&ctx.env, &video.user_id, bytes, info).await?;
+        console_log!("Stored reference image {} ({}x{})", image.id, image.width, image.height);
+        video.reference_image_id = Some(image.id);
+    }
+
+    generation_queue::insert_pending(&ctx.env, video, limits).await
+}
+
+async fn parse_multipart_create_request(
+    req: &mut Request,
+) -> Result<(CreateVideoRequest, Option<worker::File>), AppError> {
+    let form = req.form_data().await.map_err(|_| {
+        AppError::BadRequest("Invalid multipart body".into())
+    })?;
+
+    let field = |name: &str| form.get_field(name).filter(|v| !v.is_empty());
+    let required = |name: &str| {
+        field(name).ok_or_else(|| AppError::BadRequest(format!("Missing field: {}", name)))
+    };
+
+    let body = CreateVideoRequest {
+        model: required("model")?,
+        prompt: field("prompt").unwrap_or_default(),
+        size: required("size")?,
+        seconds: required("seconds")?
+            .parse()
+            .map_err(|_| AppError::BadRequest("Invalid seconds".into()))?,
+        org_id: field("org_id"),
+        reference_image_id: field("reference_image_id"),
+        template_id: field("template_id"),
+        enhance: field("enhance").is_some_and(|v| v == "true"),
+        variables: match field("variables") {
+            Some(json) => serde_json::from_str(&json).map_err(|_| {
+                AppError::BadRequest("variables must be a JSON object of strings".into())
+            })?,
+            None => Default::default(),
+        },
+    };
+
+    let file = match form.get("input_reference") {
+        Some(FormEntry::File(file)) => Some(file),
+        Some(FormEntry::Field(_)) => {
+            return Err(AppError::BadRequest("input_reference must be a file".into()))
+        }
+        None => None,
+    };
+
+    Ok((body, file))
 }
 
-pub async fn create_video(req: Request, ctx: RouteContext<()>) -> worker::Result<Response> {
+pub async fn create_video(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
     create_video_inner(req, ctx).await.or_else(|e| {
         console_log!("Video creation error: {:?}", e);
         e.to_response()
     })
 }
 
-async fn get_video_inner(req: Request, ctx: RouteContext<()>) -> Result<Response, AppError> {
+async fn get_video_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
     let user_id = auth::extract_user_from_request(&req)?;
     let video_id = ctx
         .param("id")
@@ -127,53 +261,20 @@ async fn get_video_inner(req: Request, ctx: RouteContext<()>) -> Result<Response
         return Err(AppError::NotFound("Video not found".into()));
     }
 
-    if video.status == crate::models::VideoStatus::Queued || video.status == crate::models::VideoStatus::InProgress {
+    if video.is_submitted() && (video.status == VideoStatus::Queued || video.status == VideoStatus::InProgress) {
         console_log!("Polling OpenAI for video status: {}", video.openai_video_id);
 
-        match openai_client::get_video_status(&ctx.env, &video.openai_video_id).await {
+        match ctx.data.provider.retrieve_video(&video.openai_video_id).await {
             Ok(openai_response) => {
                 console_log!("OpenAI status: {}, progress: {:?}", openai_response.status, openai_response.progress);
 
-                match openai_response.status.as_str() {
-                    "in_progress" => {
-                        let progress = openai_response.progress.unwrap_or(0);
-                        db::update_video_progress(&ctx.env, &video.openai_video_id, "in_progress", progress).await?;
-                        console_log!("Updated video progress to {}%", progress);
-                    }
-                    "completed" => {
-                        let service_url = ctx.env
-                            .var("SERVICE_URL")
-                            .map_err(|_| AppError::InternalError("SERVICE_URL not configured".into()))?
-                            .to_string();
-
-                        let video_url = format!("{}/v1/videos/{}/proxy?variant=video&user_id={}", service_url, video.openai_video_id, video.user_id);
-                        let thumbnail_url = format!("{}/v1/videos/{}/proxy?variant=thumbnail&user_id={}", service_url, video.openai_video_id, video.user_id);
-                        let spritesheet_url = format!("{}/v1/videos/{}/proxy?variant=spritesheet&user_id={}", service_url, video.openai_video_id, video.user_id);
-
-                        db::update_video_completed(
-                            &ctx.env,
-                            &video.openai_video_id,
-                            &video_url,
-                            &thumbnail_url,
-                            &spritesheet_url,
-                        ).await?;
-                        console_log!("Video completed: {}", video.openai_video_id);
-                    }
-                    "failed" => {
-                        let error_msg = openai_response
-                            .error
-                            .map(|e| e.message)
-                            .unwrap_or_else(|| "Unknown error".to_string());
-
-                        db::update_video_failed(&ctx.env, &video.openai_video_id, &error_msg).await?;
-                        console_log!("Video failed: {}", error_msg);
-                    }
-                    _ => {
-                        console_log!("Status unchanged: {}", openai_response.status);
+                match video_lifecycle::Event::from_response(&openai_response) {
+                    Some(event) => {
+                        video_lifecycle::apply_and_advance(&ctx.env, ctx.data.provider.as_ref(), &video, event).await?;
+                        video = db::get_video_by_id(&ctx.env, video_id).await?;
                     }
+                    None => console_log!("Status unchanged: {}", openai_response.status),
                 }
-
-                video = db::get_video_by_id(&ctx.env, video_id).await?;
             }
             Err(e) => {
                 console_log!("Failed to poll OpenAI status: {:?}", e);
@@ -181,14 +282,231 @@ async fn get_video_inner(req: Request, ctx: RouteContext<()>) -> Result<Response
         }
     }
 
-    Response::from_json(&video).map_err(|e| e.into())
+    library::attach_tags(&ctx.env, std::slice::from_mut(&mut video)).await?;
+    let remixes = db::list_video_remixes(&ctx.env, &video.id, &user_id).await?;
+
+    let response = VideoDetailResponse { video, remixes };
+
+    Response::from_json(&response).map_err(|e| e.into())
 }
 
-pub async fn get_video(req: Request, ctx: RouteContext<()>) -> worker::Result<Response> {
+pub async fn get_video(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
     get_video_inner(req, ctx).await.or_else(|e| e.to_response())
 }
 
-async fn list_videos_inner(req: Request, ctx: RouteContext<()>) -> Result<Response, AppError> {
+async fn remix_video_inner(
+    mut req: Request,
+    ctx: RouteContext<AppState>,
+) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let parent_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing video ID".into()))?;
+
+    let parent = db::get_video_by_id(&ctx.env, parent_id).await?;
+
+    if parent.user_id != user_id {
+        return Err(AppError::NotFound("Video not found".into()));
+    }
+
+    if parent.status != VideoStatus::Completed {
+        return Err(AppError::BadRequest(format!(
+            "Only completed videos can be remixed (status: {})",
+            parent.status
+        )));
+    }
+
+    rate_limit::check_rate_limit(&ctx.env, &user_id).await?;
+
+    let body: RemixVideoRequest = req.json().await.map_err(|_| {
+        AppError::BadRequest("Invalid request body".into())
+    })?;
+
+    let prompt = body.prompt.trim().to_string();
+    if prompt.is_empty() {
+        return Err(AppError::BadRequest("Remix prompt can't be empty".into()));
+    }
+
+    let max_chars = prompt_templates::max_prompt_chars(&ctx.env);
+    if prompt.chars().count() > max_chars {
+        return Err(AppError::BadRequest(format!("Remix prompt is longer than {} characters", max_chars)));
+    }
+
+    moderation::check_prompt(&ctx.env, ctx.data.moderator.as_deref(), &user_id, &prompt).await?;
+
+    let credits_cost = pricing::calculate_remix_credits(&parent.model, parent.seconds)?;
+
+    let mut video = Video::new_pending(
+        user_id,
+        parent.model.clone(),
+        prompt,
+        parent.size.clone(),
+        parent.seconds,
+        credits_cost,
+        now_datetime(),
+    );
+    video.org_id = body.org_id;
+    video.parent_video_id = Some(parent.id.clone());
+
+    console_log!("Remixing video {} as {} for {} credits", parent.id, video.id, credits_cost);
+    check_can_enqueue(&ctx, &video, 0).await?;
+    let response = enqueue_video(&ctx, video, None, 0).await?;
+
+    Response::from_json(&response).map_err(|e| e.into())
+}
+
+pub async fn remix_video(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    remix_video_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn cancel_video_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let video_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing video ID".into()))?;
+
+    let video = db::get_video_by_id(&ctx.env, video_id).await?;
+
+    if video.user_id != user_id {
+        return Err(AppError::NotFound("Video not found".into()));
+    }
+
+    if !matches!(
+        video.status,
+        VideoStatus::PendingSubmission | VideoStatus::Queued | VideoStatus::InProgress
+    ) {
+        return Err(AppError::BadRequest(format!(
+            "Only pending, queued or in-progress videos can be cancelled (status: {})",
+            video.status
+        )));
+    }
+
+    let outcome = video_lifecycle::apply_and_advance(
+        &ctx.env,
+        ctx.data.provider.as_ref(),
+        &video,
+        video_lifecycle::Event::Cancelled,
+    )
+    .await?;
+
+    if !outcome.is_applied() {
+        return Err(AppError::BadRequest("Video finished before it could be cancelled".into()));
+    }
+
+    // Only the request that won the cancellation talks to the provider, so a
+    // video that completed in the meantime is never deleted upstream.
+    if video.is_submitted() {
+        console_log!("Cancelling OpenAI video: {}", video.openai_video_id);
+        if let Err(e) = ctx.data.provider.delete_video(&video.openai_video_id).await {
+            console_log!("Failed to delete cancelled video {}: {:?}", video.openai_video_id, e);
+        }
+    }
+
+    let refund = credits::CancellationRefundPolicy::from_env(&ctx.env).refund_for(&video);
+    console_log!("Video cancelled: {} (progress {}%, refunding {} of {} credits)", video.id, video.progress, refund, video.credits_cost);
+
+    let new_balance = if refund > 0 {
+        credits::refund_credits_with_description(
+            &ctx.env,
+            &video.user_id,
+            &video.id,
+            refund,
+            "Video generation cancelled - credits refunded",
+        )
+        .await?
+    } else if let Some(org_id) = video.org_id.as_deref() {
+        organizations::get_organization(&ctx.env, org_id).await?.credits_balance
+    } else {
+        db::get_user_by_id(&ctx.env, &user_id).await?.credits_balance
+    };
+
+    let response = CancelVideoResponse {
+        id: video.id,
+        status: VideoStatus::Cancelled,
+        refunded_credits: refund,
+        new_balance,
+    };
+
+    Response::from_json(&response).map_err(|e| e.into())
+}
+
+pub async fn cancel_video(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    cancel_video_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn update_video_inner(mut req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let video_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing video ID".into()))?
+        .to_string();
+
+    let body: UpdateVideoRequest = req.json().await.map_err(|_| {
+        AppError::BadRequest("Invalid request body".into())
+    })?;
+    let tags = body.tags.as_deref().map(library::normalize_tags).transpose()?;
+
+    let video = db::get_video_by_id(&ctx.env, &video_id).await?;
+
+    if video.user_id != user_id {
+        return Err(AppError::NotFound("Video not found".into()));
+    }
+
+    if let Some(favorite) = body.favorite {
+        library::set_favorite(&ctx.env, &video.id, favorite).await?;
+    }
+    if let Some(tags) = tags {
+        library::set_tags(&ctx.env, &video.id, &user_id, &tags, &now_datetime().to_rfc3339()).await?;
+    }
+
+    let mut video = db::get_video_by_id(&ctx.env, &video_id).await?;
+    library::attach_tags(&ctx.env, std::slice::from_mut(&mut video)).await?;
+
+    Response::from_json(&video).map_err(|e| e.into())
+}
+
+pub async fn update_video(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    update_video_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn delete_video_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+    let video_id = ctx
+        .param("id")
+        .ok_or_else(|| AppError::BadRequest("Missing video ID".into()))?;
+
+    let video = db::get_video_by_id(&ctx.env, video_id).await?;
+
+    if video.user_id != user_id {
+        return Err(AppError::NotFound("Video not found".into()));
+    }
+
+    if !db::delete_finished_video(&ctx.env, &video.id).await? {
+        return Err(AppError::BadRequest(format!(
+            "Only completed, failed or cancelled videos can be deleted (status: {}); cancel it first",
+            video.status
+        )));
+    }
+
+    media_cache::purge(&ctx.env, &video.id).await;
+    media_storage::delete_assets(&ctx.env, &video).await?;
+
+    if video.status == VideoStatus::Completed {
+        if let Err(e) = ctx.data.provider.delete_video(&video.openai_video_id).await {
+            console_log!("Failed to delete OpenAI video {}: {:?}", video.openai_video_id, e);
+        }
+    }
+
+    console_log!("Video deleted: {}", video.id);
+
+    Response::from_json(&serde_json::json!({ "success": true })).map_err(|e| e.into())
+}
+
+pub async fn delete_video(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    delete_video_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
+async fn list_videos_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
     let user_id = auth::extract_user_from_request(&req)?;
 
     let url = req.url()?;
@@ -201,7 +519,24 @@ async fn list_videos_inner(req: Request, ctx: RouteContext<()>) -> Result<Respon
         .and_then(|s| s.parse::<i32>().ok())
         .unwrap_or(0);
 
-    let (videos, total_count) = db::list_user_videos(&ctx.env, &user_id, limit, offset).await?;
+    let filter = VideoFilter {
+        collection_id: get_query_param(&url, "collection_id"),
+        tag: get_query_param(&url, "tag").map(|t| library::normalize_tag(&t)).transpose()?,
+        favorite: get_query_param(&url, "favorite")
+            .map(|f| match f.as_str() {
+                "true" | "1" => Ok(true),
+                "false" | "0" => Ok(false),
+                _ => Err(AppError::BadRequest("favorite must be true or false".into())),
+            })
+            .transpose()?,
+    };
+
+    if let Some(collection_id) = &filter.collection_id {
+        library::get_collection(&ctx.env, collection_id, &user_id).await?;
+    }
+
+    let (mut videos, total_count) = db::list_user_videos(&ctx.env, &user_id, &filter, limit, offset).await?;
+    library::attach_tags(&ctx.env, &mut videos).await?;
 
     let has_more = (offset + limit) < total_count as i32;
 
@@ -214,15 +549,45 @@ async fn list_videos_inner(req: Request, ctx: RouteContext<()>) -> Result<Respon
     Response::from_json(&response).map_err(|e| e.into())
 }
 
-pub async fn list_videos(req: Request, ctx: RouteContext<()>) -> worker::Result<Response> {
+pub async fn list_videos(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
     list_videos_inner(req, ctx).await.or_else(|e| e.to_response())
 }
 
+async fn search_videos_inner(req: Request, ctx: RouteContext<AppState>) -> Result<Response, AppError> {
+    let user_id = auth::extract_user_from_request(&req)?;
+
+    let url = req.url()?;
+    let q = get_query_param(&url, "q")
+        .ok_or_else(|| AppError::BadRequest("q is required".into()))?;
+    let match_query = search::build_match_query(&q)?;
+
+    let limit = get_query_param(&url, "limit")
+        .and_then(|s| s.parse::<i32>().ok())
+        .unwrap_or(20)
+        .clamp(1, 100);
+
+    let offset = get_query_param(&url, "offset")
+        .and_then(|s| s.parse::<i32>().ok())
+        .unwrap_or(0)
+        .max(0);
+
+    let (results, has_more) = search::search_videos(&ctx.env, &user_id, &match_query, limit, offset).await?;
+
+    let response = VideoSearchResponse { results, has_more };
+
+    Response::from_json(&response).map_err(|e| e.into())
+}
+
+pub async fn search_videos(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
+    search_videos_inner(req, ctx).await.or_else(|e| e.to_response())
+}
+
 async fn estimate_cost_inner(
     mut req: Request,
-    _ctx: RouteContext<()>,
+    _ctx: RouteContext<AppState>,
 ) -> Result<Response, AppError> {
     let user_id = auth::extract_user_from_request(&req)?;
+    let price_context = PriceContext::from_request(&req);
 
     let body: EstimateRequest = req.json().await.map_err(|_| {
         AppError::BadRequest("Invalid request body".into())
@@ -237,6 +602,7 @@ async fn estimate_cost_inner(
     let response = EstimateResponse {
         credits_cost,
         usd_equivalent: pricing::credits_to_usd(credits_cost),
+        equivalent: price_context.credits_equivalent(credits_cost),
         current_balance: user.credits_balance,
         sufficient_credits: user.credits_balance >= credits_cost,
     };
@@ -244,7 +610,7 @@ async fn estimate_cost_inner(
     Response::from_json(&response).map_err(|e| e.into())
 }
 
-pub async fn estimate_cost(req: Request, ctx: RouteContext<()>) -> worker::Result<Response> {
+pub async fn estimate_cost(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
     estimate_cost_inner(req, ctx).await.or_else(|e| e.to_response())
 }
 
@@ -1,14 +1,14 @@
-use crate::credits;
 use crate::db;
 use crate::error::AppError;
+use crate::AppState;
 use crate::models::OpenAIWebhookEvent;
-use crate::openai_client;
+use crate::video_lifecycle;
 use uuid::Uuid;
 use worker::{console_log, Request, Response, RouteContext};
 
 async fn openai_webhook_inner(
     mut req: Request,
-    ctx: RouteContext<()>,
+    ctx: RouteContext<AppState>,
 ) -> Result<Response, AppError> {
     verify_webhook_auth(&req, &ctx.env)?;
 
@@ -30,8 +30,13 @@ async fn openai_webhook_inner(
     console_log!("Received OpenAI webhook: type={}, video_id={}", event.event_type, event.data.id);
 
     match event.event_type.as_str() {
-        "video.completed" => handle_video_completed(&ctx, &event.data.id).await?,
-        "video.failed" => handle_video_failed(&ctx, &event.data.id).await?,
+        "video.completed" => {
+            handle_video_event(&ctx, &event.data.id, video_lifecycle::Event::Completed).await?
+        }
+        "video.failed" => {
+            let failed = video_lifecycle::Event::failed("Video generation failed on OpenAI side");
+            handle_video_event(&ctx, &event.data.id, failed).await?
+        }
         _ => {
             console_log!("Unknown webhook event type: {}", event.event_type);
         }
@@ -110,54 +115,19 @@ async fn mark_webhook_processed(env: &worker::Env, openai_video_id: &str, event_
     Ok(())
 }
 
-pub async fn openai_webhook(req: Request, ctx: RouteContext<()>) -> worker::Result<Response> {
+pub async fn openai_webhook(req: Request, ctx: RouteContext<AppState>) -> worker::Result<Response> {
     openai_webhook_inner(req, ctx).await.or_else(|e| e.to_response())
 }
 
-async fn handle_video_completed(ctx: &RouteContext<()>, openai_video_id: &str) -> Result<(), AppError> {
-    let _video_status = openai_client::get_video_status(&ctx.env, openai_video_id).await?;
-
-    let video = db::get_video_by_openai_id(&ctx.env, openai_video_id).await?;
-
-    let service_url = ctx.env
-        .var("SERVICE_URL")
-        .map_err(|_| AppError::InternalError("SERVICE_URL not configured".into()))?
-        .to_string();
-
-    let video_url = format!("{}/v1/videos/{}/proxy?variant=video&user_id={}", service_url, openai_video_id, video.user_id);
-    let thumbnail_url = format!("{}/v1/videos/{}/proxy?variant=thumbnail&user_id={}", service_url, openai_video_id, video.user_id);
-    let spritesheet_url = format!("{}/v1/videos/{}/proxy?variant=spritesheet&user_id={}", service_url, openai_video_id, video.user_id);
-
-    db::update_video_completed(
-        &ctx.env,
-        openai_video_id,
-        &video_url,
-        &thumbnail_url,
-        &spritesheet_url,
-    )
-    .await?;
-
-    console_log!("Video completed: {}", openai_video_id);
-
-    Ok(())
-}
-
-async fn handle_video_failed(ctx: &RouteContext<()>, openai_video_id: &str) -> Result<(), AppError> {
+async fn handle_video_event(
+    ctx: &RouteContext<AppState>,
+    openai_video_id: &str,
+    event: video_lifecycle::Event,
+) -> Result<(), AppError> {
     let video = db::get_video_by_openai_id(&ctx.env, openai_video_id).await?;
 
-    let error_message = "Video generation failed on OpenAI side".to_string();
-
-    db::update_video_failed(&ctx.env, openai_video_id, &error_message).await?;
-
-    credits::refund_credits(
-        &ctx.env,
-        &video.user_id,
-        &video.id,
-        video.credits_cost,
-    )
-    .await?;
-
-    console_log!("Video failed and credits refunded: {}", openai_video_id);
+    let outcome = video_lifecycle::apply_and_advance(&ctx.env, ctx.data.provider.as_ref(), &video, event).await?;
+    console_log!("Webhook for {} ({}): {:?}", openai_video_id, video.status, outcome);
 
     Ok(())
 }
@@ -0,0 +1,352 @@
+use chrono::DateTime;
+use futures_util::stream::{self, Stream, StreamExt};
+use std::pin::Pin;
+
+/// A body read in chunks, such as an R2 object or an upstream response.
+pub type ByteStream = Pin<Box<dyn Stream<Item = worker::Result<Vec<u8>>>>>;
+
+/// More ranges than this in one request are treated as abuse and the whole
+/// representation is served instead.
+const MAX_RANGES: usize = 16;
+
+/// A `byte-range-spec` or `suffix-byte-range-spec` before it is resolved
+/// against the representation's length.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RangeSpec {
+    /// `first-last`
+    Bounded(u64, u64),
+    /// `first-`
+    From(u64),
+    /// `-length`
+    Suffix(u64),
+}
+
+/// An inclusive, satisfiable span of bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+impl From<ByteRange> for worker::Range {
+    fn from(range: ByteRange) -> Self {
+        worker::Range::OffsetWithLength {
+            offset: range.start,
+            length: range.len(),
+        }
+    }
+}
+
+/// How to answer a request for a representation of known length.
+#[derive(Debug, PartialEq)]
+pub enum RangeOutcome {
+    /// No usable `Range`: send everything with 200.
+    Full,
+    /// Send these ranges with 206, as `multipart/byteranges` if there is
+    /// more than one.
+    Partial(Vec<ByteRange>),
+    /// Nothing requested overlaps the representation: 416 with
+    /// `Content-Range: bytes */len`.
+    Unsatisfiable,
+}
+
+fn parse_position(value: &str) -> Option<u64> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+/// Parses a `Range` header (RFC 7233 §2.1). Returns `None` for anything
+/// that isn't a valid `bytes` range set, which the caller must ignore.
+pub fn parse(header: &str) -> Option<Vec<RangeSpec>> {
+    let (unit, set) = header.trim().split_once('=')?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+
+    let mut specs = Vec::new();
+
+    // Empty list elements are allowed and skipped (RFC 7230 §7).
+    for spec in set.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (first, last) = spec.split_once('-')?;
+
+        let spec = match (first, last) {
+            ("", suffix) => RangeSpec::Suffix(parse_position(suffix)?),
+            (first, "") => RangeSpec::From(parse_position(first)?),
+            (first, last) => {
+                let (first, last) = (parse_position(first)?, parse_position(last)?);
+                if last < first {
+                    return None;
+                }
+                RangeSpec::Bounded(first, last)
+            }
+        };
+        specs.push(spec);
+    }
+
+    if specs.is_empty() || specs.len() > MAX_RANGES {
+        return None;
+    }
+
+    Some(specs)
+}
+
+/// Resolves one spec against a representation of `length` bytes, or `None`
+/// if it can't be satisfied.
+fn resolve_spec(spec: RangeSpec, length: u64) -> Option<ByteRange> {
+    if length == 0 {
+        return None;
+    }
+    let last = length - 1;
+
+    match spec {
+        RangeSpec::Bounded(first, end) if first <= last => Some(ByteRange { start: first, end: end.min(last) }),
+        RangeSpec::From(first) if first <= last => Some(ByteRange { start: first, end: last }),
+        RangeSpec::Suffix(suffix) if suffix > 0 => Some(ByteRange {
+            start: length - suffix.min(length),
+            end: last,
+        }),
+        _ => None,
+    }
+}
+
+/// Sorts ranges and merges overlapping or adjacent ones, so a client can't
+/// make us send the same bytes many times.
+fn coalesce(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|r| r.start);
+
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(previous) if range.start <= previous.end.saturating_add(1) => {
+                previous.end = previous.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+
+    merged
+}
+
+/// Whether an `If-Range` validator still matches the representation
+/// (RFC 7233 §3.2). Entity tags use strong comparison, so weak tags never
+/// match; dates must equal `Last-Modified` exactly.
+pub fn if_range_matches(if_range: &str, etag: Option<&str>, last_modified: Option<&str>) -> bool {
+    let if_range = if_range.trim();
+
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        return !if_range.starts_with("W/")
+            && etag.is_some_and(|etag| !etag.starts_with("W/") && etag == if_range);
+    }
+
+    match (DateTime::parse_from_rfc2822(if_range), last_modified.map(DateTime::parse_from_rfc2822)) {
+        (Ok(date), Some(Ok(modified))) => date == modified,
+        _ => false,
+    }
+}
+
+/// Decides how to answer a request with these `Range` and `If-Range`
+/// headers for a representation of `length` bytes.
+pub fn evaluate(
+    range: Option<&str>,
+    if_range: Option<&str>,
+    etag: Option<&str>,
+    last_modified: Option<&str>,
+    length: u64,
+) -> RangeOutcome {
+    let Some(specs) = range.and_then(parse) else {
+        return RangeOutcome::Full;
+    };
+
+    if if_range.is_some_and(|v| !if_range_matches(v, etag, last_modified)) {
+        return RangeOutcome::Full;
+    }
+
+    let ranges: Vec<ByteRange> = specs.into_iter().filter_map(|spec| resolve_spec(spec, length)).collect();
+    if ranges.is_empty() {
+        return RangeOutcome::Unsatisfiable;
+    }
+
+    RangeOutcome::Partial(coalesce(ranges))
+}
+
+/// A `multipart/byteranges` body (RFC 7233 Appendix A) whose parts are
+/// streamed in order.
+pub struct ByteRanges {
+    boundary: String,
+    content_type: String,
+    total: u64,
+}
+
+impl ByteRanges {
+    pub fn new(content_type: &str, total: u64) -> Self {
+        Self::with_boundary(&format!("sora-engine-{}", uuid::Uuid::new_v4().simple()), content_type, total)
+    }
+
+    fn with_boundary(boundary: &str, content_type: &str, total: u64) -> Self {
+        Self {
+            boundary: boundary.to_string(),
+            content_type: content_type.to_string(),
+            total,
+        }
+    }
+
+    pub fn content_type(&self) -> String {
+        format!("multipart/byteranges; boundary={}", self.boundary)
+    }
+
+    fn part_header(&self, range: &ByteRange) -> String {
+        format!(
+            "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+            self.boundary,
+            self.content_type,
+            range.content_range(self.total)
+        )
+    }
+
+    fn closing_delimiter(&self) -> String {
+        format!("\r\n--{}--\r\n", self.boundary)
+    }
+
+    pub fn content_length(&self, ranges: &[ByteRange]) -> u64 {
+        let parts: u64 = ranges.iter().map(|r| self.part_header(r).len() as u64 + r.len()).sum();
+        parts + self.closing_delimiter().len() as u64
+    }
+
+    /// Interleaves each range's body with its part headers.
+    pub fn into_stream(self, parts: Vec<(ByteRange, ByteStream)>) -> ByteStream {
+        let mut chunks: Vec<ByteStream> = Vec::with_capacity(parts.len() * 2 + 1);
+
+        for (range, body) in parts {
+            chunks.push(Box::pin(stream::iter([Ok(self.part_header(&range).into_bytes())])));
+            chunks.push(body);
+        }
+        chunks.push(Box::pin(stream::iter([Ok(self.closing_delimiter().into_bytes())])));
+
+        Box::pin(stream::iter(chunks).flatten())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::FutureExt;
+
+    fn range(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    fn partial(header: &str, length: u64) -> RangeOutcome {
+        evaluate(Some(header), None, None, None, length)
+    }
+
+    #[test]
+    fn parses_every_spec_form() {
+        assert_eq!(parse("bytes=0-499"), Some(vec![RangeSpec::Bounded(0, 499)]));
+        assert_eq!(parse("bytes=9500-"), Some(vec![RangeSpec::From(9500)]));
+        assert_eq!(parse("bytes=-500"), Some(vec![RangeSpec::Suffix(500)]));
+        assert_eq!(
+            parse("Bytes = 0-0 , -1,"),
+            Some(vec![RangeSpec::Bounded(0, 0), RangeSpec::Suffix(1)])
+        );
+    }
+
+    #[test]
+    fn malformed_headers_are_ignored() {
+        for header in ["bytes=", "bytes=5-1", "bytes=a-b", "bytes=1-2-3", "bytes=+1-2", "items=0-1", "bytes=-", "0-1"] {
+            assert_eq!(parse(header), None, "{}", header);
+            assert_eq!(partial(header, 100), RangeOutcome::Full, "{}", header);
+        }
+
+        let too_many = format!("bytes={}", vec!["0-0"; MAX_RANGES + 1].join(","));
+        assert_eq!(parse(&too_many), None);
+    }
+
+    #[test]
+    fn resolves_single_ranges() {
+        assert_eq!(partial("bytes=0-499", 1000), RangeOutcome::Partial(vec![range(0, 499)]));
+        assert_eq!(partial("bytes=900-5000", 1000), RangeOutcome::Partial(vec![range(900, 999)]));
+        assert_eq!(partial("bytes=990-", 1000), RangeOutcome::Partial(vec![range(990, 999)]));
+        assert_eq!(partial("bytes=-500", 1000), RangeOutcome::Partial(vec![range(500, 999)]));
+        assert_eq!(partial("bytes=-5000", 1000), RangeOutcome::Partial(vec![range(0, 999)]));
+        assert_eq!(range(500, 999).content_range(1000), "bytes 500-999/1000");
+    }
+
+    #[test]
+    fn unsatisfiable_ranges_get_416() {
+        assert_eq!(partial("bytes=1000-", 1000), RangeOutcome::Unsatisfiable);
+        assert_eq!(partial("bytes=2000-3000", 1000), RangeOutcome::Unsatisfiable);
+        assert_eq!(partial("bytes=-0", 1000), RangeOutcome::Unsatisfiable);
+        assert_eq!(partial("bytes=0-0", 0), RangeOutcome::Unsatisfiable);
+        assert_eq!(partial("bytes=2000-,0-9", 1000), RangeOutcome::Partial(vec![range(0, 9)]));
+    }
+
+    #[test]
+    fn multiple_ranges_are_sorted_and_merged() {
+        assert_eq!(
+            partial("bytes=500-599,0-99", 1000),
+            RangeOutcome::Partial(vec![range(0, 99), range(500, 599)])
+        );
+        assert_eq!(
+            partial("bytes=0-99,50-149,150-199,-100", 1000),
+            RangeOutcome::Partial(vec![range(0, 199), range(900, 999)])
+        );
+    }
+
+    #[test]
+    fn if_range_uses_strong_etags_and_exact_dates() {
+        let etag = Some("\"abc\"");
+        let modified = Some("Wed, 21 Oct 2015 07:28:00 GMT");
+
+        assert!(if_range_matches("\"abc\"", etag, modified));
+        assert!(!if_range_matches("\"xyz\"", etag, modified));
+        assert!(!if_range_matches("W/\"abc\"", etag, modified));
+        assert!(if_range_matches("Wed, 21 Oct 2015 07:28:00 GMT", etag, modified));
+        assert!(!if_range_matches("Wed, 21 Oct 2015 07:29:00 GMT", etag, modified));
+        assert!(!if_range_matches("garbage", etag, modified));
+
+        assert_eq!(evaluate(Some("bytes=0-9"), Some("\"old\""), etag, modified, 100), RangeOutcome::Full);
+        assert_eq!(
+            evaluate(Some("bytes=0-9"), Some("\"abc\""), etag, modified, 100),
+            RangeOutcome::Partial(vec![range(0, 9)])
+        );
+    }
+
+    #[test]
+    fn multipart_body_matches_its_length() {
+        let body = ByteRanges::with_boundary("b", "video/mp4", 20);
+        let ranges = [range(0, 3), range(10, 11)];
+
+        let parts: Vec<(ByteRange, ByteStream)> = vec![
+            (ranges[0], Box::pin(stream::iter([Ok(b"abcd".to_vec())]))),
+            (ranges[1], Box::pin(stream::iter([Ok(b"kl".to_vec())]))),
+        ];
+
+        let length = body.content_length(&ranges);
+        let bytes: Vec<u8> = body
+            .into_stream(parts)
+            .map(|chunk| chunk.unwrap())
+            .concat()
+            .now_or_never()
+            .unwrap();
+
+        assert_eq!(bytes.len() as u64, length);
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "\r\n--b\r\nContent-Type: video/mp4\r\nContent-Range: bytes 0-3/20\r\n\r\nabcd\
+             \r\n--b\r\nContent-Type: video/mp4\r\nContent-Range: bytes 10-11/20\r\n\r\nkl\
+             \r\n--b--\r\n"
+        );
+    }
+}
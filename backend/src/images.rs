@@ -0,0 +1,368 @@
+use crate::db::{get_db, now_datetime};
+use crate::error::AppError;
+use crate::models::ReferenceImage;
+use worker::{Env, HttpMetadata};
+
+const DEFAULT_MAX_REFERENCE_IMAGE_BYTES: usize = 10 * 1024 * 1024;
+
+const REFERENCE_IMAGE_COLUMNS: &str = "id, user_id, storage_key, mime_type, width, height, size_bytes, created_at";
+
+pub struct ImageInfo {
+    pub mime_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub fn inspect_image(bytes: &[u8]) -> Result<ImageInfo, AppError> {
+    let unsupported = || AppError::BadRequest("Unsupported image. Use JPEG, PNG or WebP".into());
+    let corrupt = || AppError::BadRequest("Image file is corrupt or truncated".into());
+
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let (width, height) = png_dimensions(bytes).ok_or_else(corrupt)?;
+        return Ok(ImageInfo { mime_type: "image/png", width, height });
+    }
+
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        let (width, height) = jpeg_dimensions(bytes).ok_or_else(corrupt)?;
+        return Ok(ImageInfo { mime_type: "image/jpeg", width, height });
+    }
+
+    if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        let (width, height) = webp_dimensions(bytes).ok_or_else(corrupt)?;
+        return Ok(ImageInfo { mime_type: "image/webp", width, height });
+    }
+
+    Err(unsupported())
+}
+
+fn png_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if bytes.get(12..16)? != b"IHDR" {
+        return None;
+    }
+
+    let width = u32::from_be_bytes(bytes.get(16..20)?.try_into().ok()?);
+    let height = u32::from_be_bytes(bytes.get(20..24)?.try_into().ok()?);
+    Some((width, height))
+}
+
+fn jpeg_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let mut i = 2;
+
+    loop {
+        while *bytes.get(i)? == 0xFF {
+            i += 1;
+        }
+        let marker = *bytes.get(i)?;
+        i += 1;
+
+        if matches!(marker, 0x01 | 0xD0..=0xD7) {
+            continue;
+        }
+
+        if marker == 0xD9 || marker == 0xDA {
+            return None;
+        }
+
+        let length = u16::from_be_bytes([*bytes.get(i)?, *bytes.get(i + 1)?]) as usize;
+
+        if matches!(marker, 0xC0..=0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF) {
+            let height = u16::from_be_bytes([*bytes.get(i + 3)?, *bytes.get(i + 4)?]) as u32;
+            let width = u16::from_be_bytes([*bytes.get(i + 5)?, *bytes.get(i + 6)?]) as u32;
+            return Some((width, height));
+        }
+
+        i += length;
+    }
+}
+
+fn webp_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let le24 = |b: &[u8]| (b[0] as u32) | ((b[1] as u32) << 8) | ((b[2] as u32) << 16);
+
+    match bytes.get(12..16)? {
+        b"VP8 " => {
+            let frame = bytes.get(26..30)?;
+            let width = u16::from_le_bytes([frame[0], frame[1]]) as u32 & 0x3FFF;
+            let height = u16::from_le_bytes([frame[2], frame[3]]) as u32 & 0x3FFF;
+            Some((width, height))
+        }
+        b"VP8L" => {
+            let b = bytes.get(21..25)?;
+            let width = 1 + ((((b[1] & 0x3F) as u32) << 8) | b[0] as u32);
+            let height = 1 + ((((b[3] & 0x0F) as u32) << 10) | ((b[2] as u32) << 2) | (((b[1] & 0xC0) as u32) >> 6));
+            Some((width, height))
+        }
+        b"VP8X" => {
+            let width = 1 + le24(bytes.get(24..27)?);
+            let height = 1 + le24(bytes.get(27..30)?);
+            Some((width, height))
+        }
+        _ => None,
+    }
+}
+
+pub fn max_reference_image_bytes(env: &Env) -> usize {
+    env.var("MAX_REFERENCE_IMAGE_BYTES")
+        .map(|v| v.to_string().parse::<usize>().unwrap_or(DEFAULT_MAX_REFERENCE_IMAGE_BYTES))
+        .unwrap_or(DEFAULT_MAX_REFERENCE_IMAGE_BYTES)
+}
+
+pub fn validate_reference_image(
+    env: &Env,
+    bytes: &[u8],
+    declared_mime_type: Option<&str>,
+) -> Result<ImageInfo, AppError> {
+    check_reference_image(bytes, declared_mime_type, max_reference_image_bytes(env))
+}
+
+fn check_reference_image(bytes: &[u8], declared_mime_type: Option<&str>, max_bytes: usize) -> Result<ImageInfo, AppError> {
+    if bytes.is_empty() {
+        return Err(AppError::BadRequest("Image file is empty".into()));
+    }
+
+    if bytes.len() > max_bytes {
+        return Err(AppError::BadRequest(format!(
+            "Image is too large ({} bytes). Maximum is {} bytes",
+            bytes.len(),
+            max_bytes
+        )));
+    }
+
+    let info = inspect_image(bytes)?;
+
+    if let Some(declared) = declared_mime_type.filter(|m| !m.is_empty() && *m != "application/octet-stream") {
+        let declared = if declared == "image/jpg" { "image/jpeg" } else { declared };
+        if declared != info.mime_type {
+            return Err(AppError::BadRequest(format!(
+                "Image content ({}) doesn't match its declared type ({})",
+                info.mime_type, declared
+            )));
+        }
+    }
+
+    Ok(info)
+}
+
+pub fn check_matches_size(width: i64, height: i64, size: &str) -> Result<(), AppError> {
+    let expected = format!("{}x{}", width, height);
+    if expected != size {
+        return Err(AppError::BadRequest(format!(
+            "Reference image is {} but the video size is {}. They must match",
+            expected, size
+        )));
+    }
+
+    Ok(())
+}
+
+pub async fn store_reference_image(
+    env: &Env,
+    user_id: &str,
+    bytes: Vec<u8>,
+    info: ImageInfo,
+) -> Result<ReferenceImage, AppError> {
+    let bucket = env
+        .bucket("MEDIA_BUCKET")
+        .map_err(|e| AppError::InternalError(format!("Failed to get media bucket: {}", e)))?;
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let storage_key = format!("reference-images/{}/{}", user_id, id);
+
+    let image = ReferenceImage {
+        id,
+        user_id: user_id.to_string(),
+        storage_key,
+        mime_type: info.mime_type.to_string(),
+        width: info.width as i64,
+        height: info.height as i64,
+        size_bytes: bytes.len() as i64,
+        created_at: now_datetime(),
+    };
+
+    bucket
+        .put(image.storage_key.clone(), bytes)
+        .http_metadata(HttpMetadata {
+            content_type: Some(image.mime_type.clone()),
+            ..Default::default()
+        })
+        .execute()
+        .await
+        .map_err(|e| AppError::InternalError(format!("Failed to store image: {}", e)))?;
+
+    let db = get_db(env)?;
+
+    db.prepare("INSERT INTO reference_images (id, user_id, storage_key, mime_type, width, height, size_bytes, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
+        .bind(&[
+            image.id.clone().into(),
+            image.user_id.clone().into(),
+            image.storage_key.clone().into(),
+            image.mime_type.clone().into(),
+            (image.width as f64).into(),
+            (image.height as f64).into(),
+            (image.size_bytes as f64).into(),
+            image.created_at.to_rfc3339().into(),
+        ])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(image)
+}
+
+pub async fn get_reference_image(env: &Env, image_id: &str, user_id: &str) -> Result<ReferenceImage, AppError> {
+    let db = get_db(env)?;
+
+    db.prepare(format!("SELECT {} FROM reference_images WHERE id = ? AND user_id = ?", REFERENCE_IMAGE_COLUMNS))
+        .bind(&[image_id.into(), user_id.into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("Reference image not found".into()))
+}
+
+pub async fn load_reference_image_bytes(env: &Env, image: &ReferenceImage) -> Result<Vec<u8>, AppError> {
+    let bucket = env
+        .bucket("MEDIA_BUCKET")
+        .map_err(|e| AppError::InternalError(format!("Failed to get media bucket: {}", e)))?;
+
+    let object = bucket
+        .get(image.storage_key.clone())
+        .execute()
+        .await
+        .map_err(|e| AppError::InternalError(format!("Failed to read image: {}", e)))?
+        .ok_or_else(|| AppError::NotFound("Reference image content not found".into()))?;
+
+    object
+        .body()
+        .ok_or_else(|| AppError::NotFound("Reference image content not found".into()))?
+        .bytes()
+        .await
+        .map_err(|e| AppError::InternalError(format!("Failed to read image: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_BYTES: usize = 1024;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = b"\x89PNG\r\n\x1a\n".to_vec();
+        bytes.extend_from_slice(&13u32.to_be_bytes());
+        bytes.extend_from_slice(b"IHDR");
+        bytes.extend_from_slice(&width.to_be_bytes());
+        bytes.extend_from_slice(&height.to_be_bytes());
+        bytes.extend_from_slice(&[8, 6, 0, 0, 0]);
+        bytes
+    }
+
+    fn jpeg(width: u16, height: u16) -> Vec<u8> {
+        let mut bytes = vec![0xFF, 0xD8];
+        // An APP0 segment and some fill bytes before the frame header.
+        bytes.extend_from_slice(&[0xFF, 0xE0, 0x00, 0x04, 0x4A, 0x46]);
+        bytes.extend_from_slice(&[0xFF, 0xFF, 0xC0, 0x00, 0x11, 0x08]);
+        bytes.extend_from_slice(&height.to_be_bytes());
+        bytes.extend_from_slice(&width.to_be_bytes());
+        bytes.extend_from_slice(&[0x03, 0x01, 0x22, 0x00]);
+        bytes
+    }
+
+    fn webp(chunk: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&(4 + 8 + payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WEBP");
+        bytes.extend_from_slice(chunk);
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    fn dimensions(bytes: &[u8]) -> (&'static str, u32, u32) {
+        let info = inspect_image(bytes).unwrap_or_else(|e| panic!("{:?}", e));
+        (info.mime_type, info.width, info.height)
+    }
+
+    fn rejection(result: Result<ImageInfo, AppError>) -> String {
+        match result {
+            Err(AppError::BadRequest(message)) => message,
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(info) => panic!("accepted {} {}x{}", info.mime_type, info.width, info.height),
+        }
+    }
+
+    #[test]
+    fn reads_png_dimensions() {
+        assert_eq!(dimensions(&png(1280, 720)), ("image/png", 1280, 720));
+    }
+
+    #[test]
+    fn reads_jpeg_dimensions_after_other_segments() {
+        assert_eq!(dimensions(&jpeg(720, 1280)), ("image/jpeg", 720, 1280));
+    }
+
+    #[test]
+    fn reads_webp_dimensions_for_every_chunk_type() {
+        // VP8: frame tag, start code, then 14-bit width and height with scale bits.
+        let lossy = [
+            &[0x30, 0x01, 0x00][..],
+            &[0x9D, 0x01, 0x2A],
+            &(1280u16 | 0x4000).to_le_bytes(),
+            &720u16.to_le_bytes(),
+        ]
+        .concat();
+        assert_eq!(dimensions(&webp(b"VP8 ", &lossy)), ("image/webp", 1280, 720));
+
+        // VP8L: signature byte, then 14-bit width - 1 and height - 1.
+        let packed = (1279u32) | (719u32 << 14);
+        let lossless = [&[0x2F][..], &packed.to_le_bytes()].concat();
+        assert_eq!(dimensions(&webp(b"VP8L", &lossless)), ("image/webp", 1280, 720));
+
+        // VP8X: flags, reserved bytes, then 24-bit width - 1 and height - 1.
+        let extended = [&[0x10, 0, 0, 0][..], &1279u32.to_le_bytes()[..3], &719u32.to_le_bytes()[..3]].concat();
+        assert_eq!(dimensions(&webp(b"VP8X", &extended)), ("image/webp", 1280, 720));
+    }
+
+    #[test]
+    fn rejects_truncated_images() {
+        let corrupt = "Image file is corrupt or truncated";
+
+        assert_eq!(rejection(inspect_image(&png(1280, 720)[..20])), corrupt);
+        assert_eq!(rejection(inspect_image(&jpeg(720, 1280)[..14])), corrupt);
+        assert_eq!(rejection(inspect_image(&webp(b"VP8X", &[0x10, 0, 0, 0, 0xFF]))), corrupt);
+    }
+
+    #[test]
+    fn rejects_corrupt_and_unknown_images() {
+        let mut bad_png = png(1280, 720);
+        bad_png[12..16].copy_from_slice(b"IDAT");
+        assert_eq!(rejection(inspect_image(&bad_png)), "Image file is corrupt or truncated");
+
+        // A JPEG that reaches its scan data without a frame header.
+        assert_eq!(
+            rejection(inspect_image(&[0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x02])),
+            "Image file is corrupt or truncated"
+        );
+
+        assert_eq!(rejection(inspect_image(&webp(b"ALPH", &[0; 10]))), "Image file is corrupt or truncated");
+        assert_eq!(rejection(inspect_image(b"GIF89a")), "Unsupported image. Use JPEG, PNG or WebP");
+    }
+
+    #[test]
+    fn validates_size_and_declared_type() {
+        let image = png(1280, 720);
+
+        assert!(check_reference_image(&image, Some("image/png"), MAX_BYTES).is_ok());
+        assert!(check_reference_image(&image, Some("application/octet-stream"), MAX_BYTES).is_ok());
+        assert!(check_reference_image(&image, None, MAX_BYTES).is_ok());
+        assert!(check_reference_image(&jpeg(720, 1280), Some("image/jpg"), MAX_BYTES).is_ok());
+
+        assert_eq!(
+            rejection(check_reference_image(&image, Some("image/jpeg"), MAX_BYTES)),
+            "Image content (image/png) doesn't match its declared type (image/jpeg)"
+        );
+        assert_eq!(rejection(check_reference_image(&[], None, MAX_BYTES)), "Image file is empty");
+        assert_eq!(
+            rejection(check_reference_image(&image, None, 16)),
+            format!("Image is too large ({} bytes). Maximum is 16 bytes", image.len())
+        );
+    }
+}
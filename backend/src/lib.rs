@@ -4,13 +4,45 @@ mod models;
 mod error;
 mod auth;
 mod pricing;
+mod currency;
 mod db;
 mod credits;
+mod organizations;
+mod budgets;
+mod notifications;
+mod images;
+mod media_storage;
+mod media_cache;
+mod shares;
+mod gallery;
+mod library;
+mod search;
+mod http_range;
+mod prompt_templates;
+mod batches;
+mod generation_queue;
+mod moderation;
+mod prompt_enhancer;
+mod multipart;
 mod openai_client;
+mod mock_provider;
+mod video_provider;
+mod video_lifecycle;
 mod rate_limit;
+mod reconciler;
 mod handlers;
 
 use error::AppError;
+use moderation::ModerationProvider;
+use prompt_enhancer::PromptEnhancer;
+use std::rc::Rc;
+use video_provider::VideoProvider;
+
+pub struct AppState {
+    pub provider: Rc<dyn VideoProvider>,
+    pub moderator: Option<Rc<dyn ModerationProvider>>,
+    pub enhancer: Rc<dyn PromptEnhancer>,
+}
 
 fn cors_headers_with_env(env: &Env) -> Headers {
     let headers = Headers::new();
@@ -19,8 +51,8 @@ fn cors_headers_with_env(env: &Env) -> Headers {
         let origin = allowed_origin.to_string();
         if !origin.is_empty() {
             let _ = headers.set("Access-Control-Allow-Origin", &origin);
-            let _ = headers.set("Access-Control-Allow-Methods", "GET, POST, OPTIONS");
-            let _ = headers.set("Access-Control-Allow-Headers", "Content-Type, Authorization");
+            let _ = headers.set("Access-Control-Allow-Methods", "GET, POST, PUT, PATCH, DELETE, OPTIONS");
+            let _ = headers.set("Access-Control-Allow-Headers", "Content-Type, Authorization, Accept-Language, X-Storefront");
             let _ = headers.set("Access-Control-Max-Age", "86400");
         }
     }
@@ -28,11 +60,33 @@ fn cors_headers_with_env(env: &Env) -> Headers {
     headers
 }
 
+#[event(scheduled)]
+async fn scheduled(_event: ScheduledEvent, env: Env, _ctx: ScheduleContext) {
+    console_error_panic_hook::set_once();
+
+    reconciler::run(&env).await;
+}
+
 #[event(fetch)]
 async fn main(req: Request, env: Env, _ctx: Context) -> worker::Result<Response> {
     console_error_panic_hook::set_once();
 
-    let router = Router::new();
+    let provider = match video_provider::from_env(&env) {
+        Ok(provider) => provider,
+        Err(e) => return e.to_response(),
+    };
+
+    let moderator = match moderation::from_env(&env) {
+        Ok(moderator) => moderator,
+        Err(e) => return e.to_response(),
+    };
+
+    let enhancer = match prompt_enhancer::from_env(&env) {
+        Ok(enhancer) => enhancer,
+        Err(e) => return e.to_response(),
+    };
+
+    let router = Router::with_data(AppState { provider, moderator, enhancer });
 
     let result = router
         .get("/", |_, _| {
@@ -58,17 +112,73 @@ async fn main(req: Request, env: Env, _ctx: Context) -> worker::Result<Response>
         .post_async("/v1/auth/apple/token", handlers::auth::apple_sign_in)
         .get_async("/v1/auth/me", handlers::auth::get_me)
         .post_async("/v1/videos", handlers::videos::create_video)
+        .post_async("/v1/videos/batch", handlers::batches::create_batch)
+        .get_async("/v1/batches/:id", handlers::batches::get_batch)
+        .post_async("/v1/videos/:id/cancel", handlers::videos::cancel_video)
+        .post_async("/v1/videos/:id/remix", handlers::videos::remix_video)
+        .post_async("/v1/videos/:id/share", handlers::shares::create_share)
+        .delete_async("/v1/shares/:slug", handlers::shares::revoke_share)
+        .get_async("/s/:slug", handlers::shares::share_page)
+        .post_async("/v1/videos/:id/publish", handlers::gallery::publish_video)
+        .delete_async("/v1/videos/:id/publish", handlers::gallery::unpublish_video)
+        .get_async("/v1/gallery", handlers::gallery::list_gallery)
+        .post_async("/v1/gallery/:id/like", handlers::gallery::like_item)
+        .delete_async("/v1/gallery/:id/like", handlers::gallery::unlike_item)
+        .post_async("/v1/admin/gallery/:id/takedown", handlers::gallery::take_down_item)
+        .get_async("/v1/collections", handlers::library::list_collections)
+        .post_async("/v1/collections", handlers::library::create_collection)
+        .get_async("/v1/collections/:id", handlers::library::get_collection)
+        .patch_async("/v1/collections/:id", handlers::library::update_collection)
+        .delete_async("/v1/collections/:id", handlers::library::delete_collection)
+        .post_async("/v1/collections/:id/videos", handlers::library::add_collection_videos)
+        .delete_async("/v1/collections/:id/videos/:video_id", handlers::library::remove_collection_video)
+        .get_async("/v1/tags", handlers::library::list_tags)
         .on_async("/v1/videos/:id/proxy", handlers::video_proxy::proxy_video_content)
+        .get_async("/v1/videos/search", handlers::videos::search_videos)
         .get_async("/v1/videos/:id", handlers::videos::get_video)
+        .patch_async("/v1/videos/:id", handlers::videos::update_video)
+        .delete_async("/v1/videos/:id", handlers::videos::delete_video)
         .get_async("/v1/videos", handlers::videos::list_videos)
         .post_async("/v1/videos/estimate", handlers::videos::estimate_cost)
+        .post_async("/v1/prompts/enhance", handlers::prompts::enhance_prompt)
+        .get_async("/v1/templates", handlers::templates::list_templates)
+        .post_async("/v1/templates", handlers::templates::create_template)
+        .get_async("/v1/templates/:id", handlers::templates::get_template)
+        .patch_async("/v1/templates/:id", handlers::templates::update_template)
+        .delete_async("/v1/templates/:id", handlers::templates::delete_template)
+        .post_async("/v1/images", handlers::images::upload_image)
+        .get_async("/v1/images/:id/content", handlers::images::get_image_content)
         .get_async("/v1/credits/balance", handlers::credits::get_balance)
         .get_async("/v1/credits/transactions", handlers::credits::get_transactions)
         .get_async("/v1/credits/packs", handlers::credits::get_credit_packs)
+        .post_async("/v1/credits/gift", handlers::credits::gift_credits)
+        .get_async("/v1/credits/budget", handlers::credits::get_budget)
+        .put_async("/v1/credits/budget", handlers::credits::update_budget)
+        .get_async("/v1/notifications", handlers::notifications::list_notifications)
+        .post_async(
+            "/v1/notifications/read-all",
+            handlers::notifications::mark_all_notifications_read,
+        )
+        .post_async(
+            "/v1/notifications/:id/read",
+            handlers::notifications::mark_notification_read,
+        )
         .post_async(
             "/v1/credits/purchase/apple/validate",
             handlers::credits::validate_apple_iap,
         )
+        .post_async("/v1/orgs", handlers::organizations::create_organization)
+        .get_async("/v1/orgs", handlers::organizations::list_organizations)
+        .get_async("/v1/orgs/:id", handlers::organizations::get_organization)
+        .post_async("/v1/orgs/:id/invitations", handlers::organizations::create_invitation)
+        .post_async(
+            "/v1/orgs/invitations/:token/accept",
+            handlers::organizations::accept_invitation,
+        )
+        .patch_async("/v1/orgs/:id/members/:user_id", handlers::organizations::update_member)
+        .delete_async("/v1/orgs/:id/members/:user_id", handlers::organizations::remove_member)
+        .get_async("/v1/orgs/:id/videos", handlers::organizations::list_org_videos)
+        .get_async("/v1/orgs/:id/transactions", handlers::organizations::get_org_transactions)
         .post_async("/v1/webhook/openai", handlers::webhooks::openai_webhook)
         .options("/*catchall", |_, ctx| {
             Response::ok("").map(|r| r.with_headers(cors_headers_with_env(&ctx.env)))
@@ -0,0 +1,363 @@
+use crate::db::get_db;
+use crate::error::AppError;
+use crate::models::{Collection, TagCount, Video};
+use std::collections::{BTreeSet, HashMap};
+use worker::{wasm_bindgen::JsValue, Env};
+
+const MAX_NAME_CHARS: usize = 100;
+const MAX_DESCRIPTION_CHARS: usize = 500;
+const MAX_TAG_CHARS: usize = 32;
+const MAX_TAGS_PER_VIDEO: usize = 20;
+pub const MAX_VIDEOS_PER_REQUEST: usize = 100;
+
+const COLLECTION_COLUMNS: &str = "c.id, c.user_id, c.name, c.description, \
+(SELECT COUNT(*) FROM collection_videos cv WHERE cv.collection_id = c.id) AS video_count, c.created_at, c.updated_at";
+
+/// Narrows `GET /v1/videos`. Every set field must match.
+#[derive(Debug, Default, PartialEq)]
+pub struct VideoFilter {
+    pub collection_id: Option<String>,
+    pub tag: Option<String>,
+    pub favorite: Option<bool>,
+}
+
+impl VideoFilter {
+    /// Extra `WHERE` conditions on `videos`, with placeholders in the order
+    /// `bindings` returns their values.
+    pub fn conditions_sql(&self) -> String {
+        let mut sql = String::new();
+
+        if self.collection_id.is_some() {
+            sql.push_str(" AND id IN (SELECT video_id FROM collection_videos WHERE collection_id = ?)");
+        }
+        if self.tag.is_some() {
+            sql.push_str(" AND id IN (SELECT video_id FROM video_tags WHERE tag = ?)");
+        }
+        if self.favorite.is_some() {
+            sql.push_str(" AND favorite = ?");
+        }
+
+        sql
+    }
+
+    pub fn bindings(&self) -> Vec<JsValue> {
+        let mut values = Vec::new();
+
+        if let Some(collection_id) = &self.collection_id {
+            values.push(collection_id.as_str().into());
+        }
+        if let Some(tag) = &self.tag {
+            values.push(tag.as_str().into());
+        }
+        if let Some(favorite) = self.favorite {
+            values.push(JsValue::from_f64(if favorite { 1.0 } else { 0.0 }));
+        }
+
+        values
+    }
+}
+
+/// Lowercases a tag and collapses its whitespace, so `Drone  Shots` and
+/// `drone shots` are the same tag.
+pub fn normalize_tag(tag: &str) -> Result<String, AppError> {
+    let tag = tag.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+
+    if tag.is_empty() || tag.chars().count() > MAX_TAG_CHARS {
+        return Err(AppError::BadRequest(format!(
+            "Tags must be 1 to {} characters",
+            MAX_TAG_CHARS
+        )));
+    }
+    if !tag.chars().all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_') {
+        return Err(AppError::BadRequest(format!(
+            "Invalid tag \"{}\". Use letters, digits, spaces, - and _",
+            tag
+        )));
+    }
+
+    Ok(tag)
+}
+
+/// Normalizes and de-duplicates a video's tags.
+pub fn normalize_tags(tags: &[String]) -> Result<Vec<String>, AppError> {
+    let tags = tags
+        .iter()
+        .map(|tag| normalize_tag(tag))
+        .collect::<Result<BTreeSet<_>, _>>()?;
+
+    if tags.len() > MAX_TAGS_PER_VIDEO {
+        return Err(AppError::BadRequest(format!(
+            "A video can have at most {} tags",
+            MAX_TAGS_PER_VIDEO
+        )));
+    }
+
+    Ok(tags.into_iter().collect())
+}
+
+pub fn normalize_name(name: &str) -> Result<String, AppError> {
+    let name = name.trim();
+
+    if name.is_empty() || name.chars().count() > MAX_NAME_CHARS {
+        return Err(AppError::BadRequest(format!(
+            "Collection names must be 1 to {} characters",
+            MAX_NAME_CHARS
+        )));
+    }
+
+    Ok(name.to_string())
+}
+
+pub fn normalize_description(description: Option<&str>) -> Result<Option<String>, AppError> {
+    let description = description.map(str::trim).filter(|d| !d.is_empty());
+
+    if description.is_some_and(|d| d.chars().count() > MAX_DESCRIPTION_CHARS) {
+        return Err(AppError::BadRequest(format!(
+            "Descriptions must be at most {} characters",
+            MAX_DESCRIPTION_CHARS
+        )));
+    }
+
+    Ok(description.map(str::to_string))
+}
+
+pub async fn set_favorite(env: &Env, video_id: &str, favorite: bool) -> Result<(), AppError> {
+    let db = get_db(env)?;
+
+    db.prepare("UPDATE videos SET favorite = ? WHERE id = ?")
+        .bind(&[JsValue::from_f64(if favorite { 1.0 } else { 0.0 }), video_id.into()])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Replaces a video's tags with `tags`, which must already be normalized.
+pub async fn set_tags(env: &Env, video_id: &str, user_id: &str, tags: &[String], now: &str) -> Result<(), AppError> {
+    let db = get_db(env)?;
+
+    let mut statements = vec![db
+        .prepare("DELETE FROM video_tags WHERE video_id = ?")
+        .bind(&[video_id.into()])?];
+
+    for tag in tags {
+        statements.push(
+            db.prepare("INSERT INTO video_tags (video_id, user_id, tag, created_at) VALUES (?, ?, ?, ?)")
+                .bind(&[video_id.into(), user_id.into(), tag.as_str().into(), now.into()])?,
+        );
+    }
+
+    db.batch(statements)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+#[derive(serde::Deserialize)]
+struct TagRow {
+    video_id: String,
+    tag: String,
+}
+
+/// Fills in `tags` on each video, in one query.
+pub async fn attach_tags(env: &Env, videos: &mut [Video]) -> Result<(), AppError> {
+    if videos.is_empty() {
+        return Ok(());
+    }
+
+    let db = get_db(env)?;
+    let placeholders = vec!["?"; videos.len()].join(", ");
+    let ids: Vec<JsValue> = videos.iter().map(|v| v.id.as_str().into()).collect();
+
+    let rows: Vec<TagRow> = db
+        .prepare(format!(
+            "SELECT video_id, tag FROM video_tags WHERE video_id IN ({}) ORDER BY tag",
+            placeholders
+        ))
+        .bind(&ids)?
+        .all()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .results()
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+    for row in rows {
+        tags.entry(row.video_id).or_default().push(row.tag);
+    }
+    for video in videos {
+        video.tags = tags.remove(&video.id).unwrap_or_default();
+    }
+
+    Ok(())
+}
+
+/// The user's tags, most used first.
+pub async fn list_tags(env: &Env, user_id: &str) -> Result<Vec<TagCount>, AppError> {
+    let db = get_db(env)?;
+
+    db.prepare("SELECT tag, COUNT(*) AS count FROM video_tags WHERE user_id = ? GROUP BY tag ORDER BY count DESC, tag ASC")
+        .bind(&[user_id.into()])?
+        .all()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .results::<TagCount>()
+        .map_err(|e| AppError::DatabaseError(e.to_string()))
+}
+
+pub async fn list_collections(env: &Env, user_id: &str) -> Result<Vec<Collection>, AppError> {
+    let db = get_db(env)?;
+
+    db.prepare(format!("SELECT {} FROM collections c WHERE c.user_id = ? ORDER BY c.name COLLATE NOCASE", COLLECTION_COLUMNS))
+        .bind(&[user_id.into()])?
+        .all()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .results::<Collection>()
+        .map_err(|e| AppError::DatabaseError(e.to_string()))
+}
+
+pub async fn get_collection(env: &Env, collection_id: &str, user_id: &str) -> Result<Collection, AppError> {
+    let db = get_db(env)?;
+
+    db.prepare(format!("SELECT {} FROM collections c WHERE c.id = ? AND c.user_id = ?", COLLECTION_COLUMNS))
+        .bind(&[collection_id.into(), user_id.into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("Collection not found".into()))
+}
+
+pub async fn save_collection(env: &Env, collection: &Collection) -> Result<(), AppError> {
+    let db = get_db(env)?;
+
+    db.prepare("INSERT INTO collections (id, user_id, name, description, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?) ON CONFLICT(id) DO UPDATE SET name = excluded.name, description = excluded.description, updated_at = excluded.updated_at")
+        .bind(&[
+            collection.id.clone().into(),
+            collection.user_id.clone().into(),
+            collection.name.clone().into(),
+            collection.description.as_deref().map(JsValue::from_str).unwrap_or(JsValue::NULL),
+            collection.created_at.to_rfc3339().into(),
+            collection.updated_at.to_rfc3339().into(),
+        ])?
+        .run()
+        .await
+        .map_err(|e| {
+            if e.to_string().contains("UNIQUE constraint failed") {
+                AppError::BadRequest(format!("You already have a collection named \"{}\"", collection.name))
+            } else {
+                AppError::DatabaseError(e.to_string())
+            }
+        })?;
+
+    Ok(())
+}
+
+/// Deletes the collection and its memberships. The videos stay.
+pub async fn delete_collection(env: &Env, collection_id: &str) -> Result<(), AppError> {
+    let db = get_db(env)?;
+
+    db.prepare("DELETE FROM collections WHERE id = ?")
+        .bind(&[collection_id.into()])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Adds the user's own videos to a collection. Unknown, foreign and
+/// already-added IDs are skipped. Returns how many were added.
+pub async fn add_videos(
+    env: &Env,
+    collection_id: &str,
+    user_id: &str,
+    video_ids: &[String],
+    now: &str,
+) -> Result<u64, AppError> {
+    if video_ids.is_empty() {
+        return Ok(0);
+    }
+
+    let db = get_db(env)?;
+
+    let statements = video_ids
+        .iter()
+        .map(|video_id| {
+            db.prepare("INSERT OR IGNORE INTO collection_videos (collection_id, video_id, added_at) SELECT ?, id, ? FROM videos WHERE id = ? AND user_id = ?")
+                .bind(&[collection_id.into(), now.into(), video_id.as_str().into(), user_id.into()])
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let results = db
+        .batch(statements)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    let mut added = 0;
+    for result in results {
+        added += result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) as u64;
+    }
+
+    Ok(added)
+}
+
+/// Returns false if the video wasn't in the collection.
+pub async fn remove_video(env: &Env, collection_id: &str, video_id: &str) -> Result<bool, AppError> {
+    let db = get_db(env)?;
+
+    let result = db
+        .prepare("DELETE FROM collection_videos WHERE collection_id = ? AND video_id = ?")
+        .bind(&[collection_id.into(), video_id.into()])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_add_one_condition_per_field() {
+        assert_eq!(VideoFilter::default().conditions_sql(), "");
+
+        let filter = VideoFilter {
+            collection_id: Some("col_1".into()),
+            tag: Some("drone".into()),
+            favorite: Some(true),
+        };
+        let sql = filter.conditions_sql();
+        assert_eq!(sql.matches('?').count(), 3);
+        assert!(sql.find("collection_videos").unwrap() < sql.find("video_tags").unwrap());
+        assert!(sql.ends_with("AND favorite = ?"));
+    }
+
+    #[test]
+    fn tags_are_normalized() {
+        assert_eq!(normalize_tag("  Drone   Shots ").unwrap(), "drone shots");
+        assert_eq!(normalize_tag("b-roll_2").unwrap(), "b-roll_2");
+        assert!(normalize_tag("   ").is_err());
+        assert!(normalize_tag("#launch").is_err());
+        assert!(normalize_tag(&"a".repeat(MAX_TAG_CHARS + 1)).is_err());
+
+        let tags = normalize_tags(&["Launch".into(), "launch".into(), "b-roll".into()]).unwrap();
+        assert_eq!(tags, vec!["b-roll", "launch"]);
+
+        let too_many: Vec<String> = (0..=MAX_TAGS_PER_VIDEO).map(|i| format!("tag{}", i)).collect();
+        assert!(normalize_tags(&too_many).is_err());
+    }
+
+    #[test]
+    fn collection_names_are_trimmed_and_bounded() {
+        assert_eq!(normalize_name("  Launch clips ").unwrap(), "Launch clips");
+        assert!(normalize_name(" ").is_err());
+        assert!(normalize_name(&"x".repeat(MAX_NAME_CHARS + 1)).is_err());
+        assert_eq!(normalize_description(Some("  ")).unwrap(), None);
+        assert!(normalize_description(Some(&"x".repeat(MAX_DESCRIPTION_CHARS + 1))).is_err());
+    }
+}
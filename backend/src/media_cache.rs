@@ -0,0 +1,171 @@
+use crate::error::AppError;
+use chrono::DateTime;
+use worker::{console_log, Cache, Env, Headers, Response, ResponseBuilder};
+
+const DEFAULT_EDGE_TTL_SECONDS: u64 = 604_800;
+
+/// Variants small enough to cache whole. Videos are read with ranges and
+/// R2 already serves those.
+const CACHED_VARIANTS: [&str; 2] = ["thumbnail", "spritesheet"];
+
+/// Headers a 304 must repeat (RFC 7232 §4.1).
+const REVALIDATION_HEADERS: [&str; 4] = ["ETag", "Last-Modified", "Cache-Control", "Vary"];
+
+pub fn is_cached_variant(variant: &str) -> bool {
+    CACHED_VARIANTS.contains(&variant)
+}
+
+fn edge_ttl_seconds(env: &Env) -> u64 {
+    env.var("MEDIA_CACHE_TTL_SECONDS")
+        .map(|v| v.to_string().parse().unwrap_or(DEFAULT_EDGE_TTL_SECONDS))
+        .unwrap_or(DEFAULT_EDGE_TTL_SECONDS)
+}
+
+/// The cache entry for a variant. It only names the video, so every
+/// authorized viewer shares one entry; authorization happens before
+/// `lookup` is called.
+fn cache_key(service_url: &str, video_id: &str, variant: &str) -> String {
+    format!("{}/media-cache/videos/{}/{}", service_url.trim_end_matches('/'), video_id, variant)
+}
+
+fn key_for(env: &Env, video_id: &str, variant: &str) -> Result<String, AppError> {
+    let service_url = env
+        .var("SERVICE_URL")
+        .map_err(|_| AppError::InternalError("SERVICE_URL not configured".into()))?
+        .to_string();
+
+    Ok(cache_key(&service_url, video_id, variant))
+}
+
+pub async fn lookup(env: &Env, video_id: &str, variant: &str) -> Result<Option<Response>, AppError> {
+    let key = key_for(env, video_id, variant)?;
+    Ok(Cache::default().get(key, true).await?)
+}
+
+/// Stores a copy of a complete (200) response at the edge. The copy is
+/// marked public so the Cache API keeps it; the client's copy stays
+/// private.
+pub async fn store(env: &Env, video_id: &str, variant: &str, response: &mut Response) -> Result<(), AppError> {
+    if response.status_code() != 200 {
+        return Ok(());
+    }
+
+    let key = key_for(env, video_id, variant)?;
+    let copy = response.cloned()?;
+
+    let headers = Headers::new();
+    for (name, value) in copy.headers().entries() {
+        if !name.eq_ignore_ascii_case("cache-control") && !name.eq_ignore_ascii_case("vary") {
+            headers.set(&name, &value)?;
+        }
+    }
+    headers.set("Cache-Control", &format!("public, max-age={}", edge_ttl_seconds(env)))?;
+
+    let (_, body) = copy.into_parts();
+    let copy = ResponseBuilder::new().with_headers(headers).body(body);
+
+    Ok(Cache::default().put(key, copy).await?)
+}
+
+/// Drops every cached variant of a video. Failures are logged: the entries
+/// expire on their own.
+pub async fn purge(env: &Env, video_id: &str) {
+    let cache = Cache::default();
+
+    for variant in CACHED_VARIANTS {
+        let result = match key_for(env, video_id, variant) {
+            Ok(key) => cache.delete(key, true).await.map_err(AppError::from),
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            console_log!("Failed to purge cached {} for video {}: {:?}", variant, video_id, e);
+        }
+    }
+}
+
+/// Weak comparison (RFC 7232 §2.3.2): `W/"a"` matches `"a"`.
+fn opaque_tag(etag: &str) -> &str {
+    etag.trim().trim_start_matches("W/")
+}
+
+/// Whether a GET's `If-None-Match` or `If-Modified-Since` still matches
+/// the representation (RFC 7232 §3.2, §3.3). `If-Modified-Since` is only
+/// consulted when there is no `If-None-Match`.
+pub fn not_modified(
+    if_none_match: Option<&str>,
+    if_modified_since: Option<&str>,
+    etag: Option<&str>,
+    last_modified: Option<&str>,
+) -> bool {
+    if let Some(if_none_match) = if_none_match {
+        if if_none_match.trim() == "*" {
+            return etag.is_some();
+        }
+
+        return etag.is_some_and(|etag| {
+            if_none_match
+                .split(',')
+                .any(|candidate| opaque_tag(candidate) == opaque_tag(etag))
+        });
+    }
+
+    match (
+        if_modified_since.map(DateTime::parse_from_rfc2822),
+        last_modified.map(DateTime::parse_from_rfc2822),
+    ) {
+        (Some(Ok(since)), Some(Ok(modified))) => modified <= since,
+        _ => false,
+    }
+}
+
+/// A body-less 304 carrying the validators and caching headers of
+/// `headers`.
+pub fn not_modified_response(headers: &Headers) -> Result<Response, AppError> {
+    let revalidated = Headers::new();
+
+    for name in REVALIDATION_HEADERS {
+        if let Some(value) = headers.get(name)? {
+            revalidated.set(name, &value)?;
+        }
+    }
+
+    Ok(ResponseBuilder::new().with_status(304).with_headers(revalidated).empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETAG: Option<&str> = Some("\"abc\"");
+    const MODIFIED: Option<&str> = Some("Wed, 21 Oct 2015 07:28:00 GMT");
+
+    #[test]
+    fn keys_name_only_the_video_and_variant() {
+        assert_eq!(
+            cache_key("https://api.example.com/", "vid_1", "thumbnail"),
+            "https://api.example.com/media-cache/videos/vid_1/thumbnail"
+        );
+        assert!(is_cached_variant("spritesheet"));
+        assert!(!is_cached_variant("video"));
+    }
+
+    #[test]
+    fn if_none_match_uses_weak_comparison() {
+        assert!(not_modified(Some("\"abc\""), None, ETAG, MODIFIED));
+        assert!(not_modified(Some("\"x\", W/\"abc\""), None, ETAG, MODIFIED));
+        assert!(not_modified(Some("*"), None, ETAG, MODIFIED));
+        assert!(!not_modified(Some("\"x\""), None, ETAG, MODIFIED));
+        assert!(!not_modified(Some("\"abc\""), None, None, MODIFIED));
+        assert!(!not_modified(Some("\"x\""), Some("Thu, 22 Oct 2015 00:00:00 GMT"), ETAG, MODIFIED));
+    }
+
+    #[test]
+    fn if_modified_since_compares_dates() {
+        assert!(not_modified(None, Some("Wed, 21 Oct 2015 07:28:00 GMT"), ETAG, MODIFIED));
+        assert!(not_modified(None, Some("Thu, 22 Oct 2015 00:00:00 GMT"), ETAG, MODIFIED));
+        assert!(!not_modified(None, Some("Tue, 20 Oct 2015 00:00:00 GMT"), ETAG, MODIFIED));
+        assert!(!not_modified(None, Some("yesterday"), ETAG, MODIFIED));
+        assert!(!not_modified(None, None, ETAG, MODIFIED));
+    }
+}
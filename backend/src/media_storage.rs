@@ -0,0 +1,174 @@
+use crate::db::get_db;
+use crate::error::AppError;
+use crate::models::Video;
+use crate::video_provider::VideoProvider;
+use futures_util::StreamExt;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::rc::Rc;
+use worker::{Bucket, Data, Env, FixedLengthStream, HttpMetadata, Object, Range};
+
+fn get_bucket(env: &Env) -> Result<Bucket, AppError> {
+    env.bucket("MEDIA_BUCKET")
+        .map_err(|e| AppError::InternalError(format!("Failed to get media bucket: {}", e)))
+}
+
+pub fn asset_key(video_id: &str, variant: &str) -> String {
+    format!("videos/{}/{}", video_id, variant)
+}
+
+fn default_content_type(variant: &str) -> &'static str {
+    match variant {
+        "thumbnail" => "image/webp",
+        "spritesheet" => "image/jpeg",
+        _ => "video/mp4",
+    }
+}
+
+/// The stored MP4, as recorded on the video.
+#[derive(Debug)]
+pub struct StoredAsset {
+    pub key: String,
+    pub size: i64,
+    pub checksum: String,
+}
+
+/// Streams one variant from the provider into R2 without holding it in
+/// memory. The SHA-256 is computed from the chunks as they pass through, and
+/// R2 rejects the upload if fewer or more bytes than `Content-Length` arrive.
+async fn copy_variant(
+    bucket: &Bucket,
+    provider: &dyn VideoProvider,
+    video: &Video,
+    variant: &str,
+) -> Result<StoredAsset, AppError> {
+    let mut response = provider.download_content(&video.openai_video_id, variant, None).await?;
+
+    let content_type = response
+        .headers()
+        .get("Content-Type")?
+        .unwrap_or_else(|| default_content_type(variant).to_string());
+    let content_length = response
+        .headers()
+        .get("Content-Length")?
+        .and_then(|v| v.parse::<u64>().ok());
+
+    let hasher = Rc::new(RefCell::new(Sha256::new()));
+
+    let (body, size): (Data, u64) = match response.stream() {
+        Ok(stream) => {
+            let length = content_length.ok_or_else(|| {
+                AppError::ExternalApiError(format!("Download of {} for video {} has no Content-Length", variant, video.id))
+            })?;
+            let chunk_hasher = hasher.clone();
+            let hashed = stream.inspect(move |chunk| {
+                if let Ok(bytes) = chunk {
+                    chunk_hasher.borrow_mut().update(bytes);
+                }
+            });
+            (FixedLengthStream::wrap(hashed, length).into(), length)
+        }
+        // Bodies that were built in memory (the mock provider) aren't streams.
+        Err(_) => {
+            let bytes = response.bytes().await?;
+            hasher.borrow_mut().update(&bytes);
+            let size = bytes.len() as u64;
+            (bytes.into(), size)
+        }
+    };
+
+    bucket
+        .put(asset_key(&video.id, variant), body)
+        .http_metadata(HttpMetadata {
+            content_type: Some(content_type),
+            cache_control: Some("private, max-age=31536000, immutable".to_string()),
+            ..Default::default()
+        })
+        .execute()
+        .await
+        .map_err(|e| AppError::InternalError(format!("Failed to store {} for video {}: {}", variant, video.id, e)))?;
+
+    let checksum = hex::encode(hasher.take().finalize());
+
+    Ok(StoredAsset {
+        key: asset_key(&video.id, variant),
+        size: size as i64,
+        checksum,
+    })
+}
+
+/// Copies a completed video's assets from the provider into R2 and records
+/// the MP4's key, size and SHA-256 on the video. From then on the proxy
+/// serves from R2 and the download never expires. Runs from the reconciler,
+/// off the request path. Safe to retry: objects are overwritten and the row
+/// is only updated once everything is stored.
+pub async fn persist_assets(env: &Env, provider: &dyn VideoProvider, video: &Video) -> Result<StoredAsset, AppError> {
+    let bucket = get_bucket(env)?;
+
+    let stored = copy_variant(&bucket, provider, video, "video").await?;
+    for variant in ["thumbnail", "spritesheet"] {
+        copy_variant(&bucket, provider, video, variant).await?;
+    }
+
+    let db = get_db(env)?;
+    db.prepare("UPDATE videos SET storage_key = ?, storage_size = ?, storage_checksum = ?, download_url_expires_at = NULL WHERE id = ?")
+        .bind(&[
+            stored.key.clone().into(),
+            (stored.size as f64).into(),
+            stored.checksum.clone().into(),
+            video.id.clone().into(),
+        ])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(stored)
+}
+
+/// The stored object for a variant, or `None` if the video hasn't been
+/// copied to R2 yet. With a `range`, only those bytes are read.
+pub async fn get_asset(env: &Env, video: &Video, variant: &str, range: Option<Range>) -> Result<Option<Object>, AppError> {
+    if video.storage_key.is_none() {
+        return Ok(None);
+    }
+
+    let bucket = get_bucket(env)?;
+    let mut request = bucket.get(asset_key(&video.id, variant));
+    if let Some(range) = range {
+        request = request.range(range);
+    }
+
+    request
+        .execute()
+        .await
+        .map_err(|e| AppError::InternalError(format!("Failed to read {} for video {}: {}", variant, video.id, e)))
+}
+
+/// Metadata for a stored variant, without the body.
+pub async fn head_asset(env: &Env, video: &Video, variant: &str) -> Result<Option<Object>, AppError> {
+    if video.storage_key.is_none() {
+        return Ok(None);
+    }
+
+    get_bucket(env)?
+        .head(asset_key(&video.id, variant))
+        .await
+        .map_err(|e| AppError::InternalError(format!("Failed to read {} for video {}: {}", variant, video.id, e)))
+}
+
+/// Removes every stored variant of a video. Missing objects are fine.
+pub async fn delete_assets(env: &Env, video: &Video) -> Result<(), AppError> {
+    if video.storage_key.is_none() {
+        return Ok(());
+    }
+
+    let bucket = get_bucket(env)?;
+    for variant in ["video", "thumbnail", "spritesheet"] {
+        bucket
+            .delete(asset_key(&video.id, variant))
+            .await
+            .map_err(|e| AppError::InternalError(format!("Failed to delete {} for video {}: {}", variant, video.id, e)))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,272 @@
+use crate::error::AppError;
+use crate::models::{OpenAIError, OpenAIVideoResponse};
+use crate::video_provider::{InputReference, VideoProvider};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+use worker::{async_trait::async_trait, Response};
+
+/// Prompts containing this marker end in `failed` instead of `completed`.
+pub const MOCK_FAILURE_MARKER: &str = "[mock:fail]";
+
+const MOCK_EPOCH: i64 = 1_700_000_000;
+
+#[derive(Debug, Clone, PartialEq)]
+enum MockStep {
+    InProgress(i32),
+    Completed,
+    Failed(String),
+}
+
+struct MockVideo {
+    response: OpenAIVideoResponse,
+    script: VecDeque<MockStep>,
+}
+
+impl MockVideo {
+    fn advance(&mut self) {
+        let Some(step) = self.script.pop_front() else {
+            return;
+        };
+
+        match step {
+            MockStep::InProgress(progress) => {
+                self.response.status = "in_progress".into();
+                self.response.progress = Some(progress);
+            }
+            MockStep::Completed => {
+                self.response.status = "completed".into();
+                self.response.progress = Some(100);
+            }
+            MockStep::Failed(message) => {
+                self.response.status = "failed".into();
+                self.response.error = Some(OpenAIError {
+                    code: "mock_failure".into(),
+                    message,
+                });
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    videos: HashMap<String, MockVideo>,
+    next_id: u64,
+}
+
+/// An in-process `VideoProvider` with scripted, deterministic behaviour.
+///
+/// New videos start `queued`. Each retrieve moves them one step along the
+/// script: `in_progress` at 30%, then `completed` (or `failed` when the
+/// prompt contains [`MOCK_FAILURE_MARKER`]). IDs are sequential.
+#[derive(Default)]
+pub struct MockVideoProvider {
+    state: RefCell<MockState>,
+}
+
+thread_local! {
+    static SHARED: Rc<MockVideoProvider> = Rc::new(MockVideoProvider::default());
+}
+
+impl MockVideoProvider {
+    /// The isolate-wide instance, so scripted state survives across requests.
+    pub fn shared() -> Rc<MockVideoProvider> {
+        SHARED.with(Rc::clone)
+    }
+
+    fn insert(&self, model: &str, prompt: &str, size: &str, seconds: i32) -> OpenAIVideoResponse {
+        let mut state = self.state.borrow_mut();
+        state.next_id += 1;
+        let id = format!("video_mock_{:06}", state.next_id);
+
+        let final_step = if prompt.contains(MOCK_FAILURE_MARKER) {
+            MockStep::Failed("Mock generation failed".into())
+        } else {
+            MockStep::Completed
+        };
+
+        let response = OpenAIVideoResponse {
+            id: id.clone(),
+            object: "video".into(),
+            created_at: MOCK_EPOCH + state.next_id as i64,
+            status: "queued".into(),
+            model: model.into(),
+            progress: Some(0),
+            seconds: Some(seconds.to_string()),
+            size: Some(size.into()),
+            error: None,
+        };
+
+        state.videos.insert(
+            id,
+            MockVideo {
+                response: response.clone(),
+                script: VecDeque::from([MockStep::InProgress(30), final_step]),
+            },
+        );
+
+        response
+    }
+
+    fn not_found(video_id: &str) -> AppError {
+        AppError::ExternalApiError(format!("OpenAI API error (404): video {} not found", video_id))
+    }
+}
+
+#[async_trait(?Send)]
+impl VideoProvider for MockVideoProvider {
+    async fn create_video(
+        &self,
+        _request_id: &str,
+        model: &str,
+        prompt: &str,
+        size: &str,
+        seconds: i32,
+        _input_reference: Option<InputReference<'_>>,
+    ) -> Result<OpenAIVideoResponse, AppError> {
+        Ok(self.insert(model, prompt, size, seconds))
+    }
+
+    async fn retrieve_video(&self, video_id: &str) -> Result<OpenAIVideoResponse, AppError> {
+        let mut state = self.state.borrow_mut();
+        let video = state
+            .videos
+            .get_mut(video_id)
+            .ok_or_else(|| Self::not_found(video_id))?;
+
+        video.advance();
+        Ok(video.response.clone())
+    }
+
+    async fn download_content(
+        &self,
+        video_id: &str,
+        variant: &str,
+        _range: Option<&str>,
+    ) -> Result<Response, AppError> {
+        let state = self.state.borrow();
+        let video = state.videos.get(video_id).ok_or_else(|| Self::not_found(video_id))?;
+
+        if video.response.status != "completed" {
+            return Err(AppError::ExternalApiError(format!(
+                "Failed to download from OpenAI (409): video {} is {}",
+                video_id, video.response.status
+            )));
+        }
+
+        let content_type = match variant {
+            "thumbnail" => "image/webp",
+            "spritesheet" => "image/jpeg",
+            _ => "video/mp4",
+        };
+
+        let body = format!("mock {} for {}", variant, video_id).into_bytes();
+        let mut response = Response::from_bytes(body)?;
+        response.headers_mut().set("Content-Type", content_type)?;
+
+        Ok(response)
+    }
+
+    async fn delete_video(&self, video_id: &str) -> Result<(), AppError> {
+        self.state.borrow_mut().videos.remove(video_id);
+        Ok(())
+    }
+
+    async fn remix_video(&self, video_id: &str, prompt: &str) -> Result<OpenAIVideoResponse, AppError> {
+        let source = {
+            let state = self.state.borrow();
+            let video = state.videos.get(video_id).ok_or_else(|| Self::not_found(video_id))?;
+
+            if video.response.status != "completed" {
+                return Err(AppError::ExternalApiError(format!(
+                    "OpenAI API error (400): video {} is not completed",
+                    video_id
+                )));
+            }
+
+            video.response.clone()
+        };
+
+        let seconds = source
+            .seconds
+            .as_deref()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(4);
+
+        Ok(self.insert(&source.model, prompt, source.size.as_deref().unwrap_or_default(), seconds))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::FutureExt;
+
+    fn create(provider: &MockVideoProvider, prompt: &str) -> OpenAIVideoResponse {
+        provider
+            .create_video("local-id", "sora-2", prompt, "1280x720", 8, None)
+            .now_or_never()
+            .unwrap()
+            .unwrap()
+    }
+
+    fn retrieve(provider: &MockVideoProvider, id: &str) -> OpenAIVideoResponse {
+        provider.retrieve_video(id).now_or_never().unwrap().unwrap()
+    }
+
+    #[test]
+    fn scripts_queued_in_progress_completed() {
+        let provider = MockVideoProvider::default();
+        let video = create(&provider, "a lighthouse");
+        assert_eq!(video.id, "video_mock_000001");
+        assert_eq!(video.status, "queued");
+
+        let video = retrieve(&provider, &video.id);
+        assert_eq!(video.status, "in_progress");
+        assert_eq!(video.progress, Some(30));
+
+        let video = retrieve(&provider, &video.id);
+        assert_eq!(video.status, "completed");
+        assert_eq!(video.progress, Some(100));
+
+        assert_eq!(retrieve(&provider, &video.id).status, "completed");
+    }
+
+    #[test]
+    fn failure_marker_ends_in_failed() {
+        let provider = MockVideoProvider::default();
+        let video = create(&provider, "a lighthouse [mock:fail]");
+
+        retrieve(&provider, &video.id);
+        let video = retrieve(&provider, &video.id);
+        assert_eq!(video.status, "failed");
+        assert!(video.error.is_some());
+    }
+
+    #[test]
+    fn remix_requires_a_completed_source() {
+        let provider = MockVideoProvider::default();
+        let source = create(&provider, "a lighthouse");
+
+        assert!(provider.remix_video(&source.id, "at night").now_or_never().unwrap().is_err());
+
+        retrieve(&provider, &source.id);
+        retrieve(&provider, &source.id);
+
+        let remix = provider.remix_video(&source.id, "at night").now_or_never().unwrap().unwrap();
+        assert_eq!(remix.id, "video_mock_000002");
+        assert_eq!(remix.status, "queued");
+        assert_eq!(remix.size.as_deref(), Some("1280x720"));
+        assert_eq!(remix.seconds.as_deref(), Some("8"));
+    }
+
+    #[test]
+    fn deleted_videos_are_gone() {
+        let provider = MockVideoProvider::default();
+        let video = create(&provider, "a lighthouse");
+
+        provider.delete_video(&video.id).now_or_never().unwrap().unwrap();
+        assert!(provider.retrieve_video(&video.id).now_or_never().unwrap().is_err());
+    }
+}
@@ -1,17 +1,22 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use crate::currency::Money;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: String,
     pub apple_user_id: String,
     pub email: Option<String>,
+    pub referral_code: Option<String>,
     pub credits_balance: i64,
     pub total_videos_generated: i64,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+const PENDING_OPENAI_ID_PREFIX: &str = "pending:";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Video {
     pub id: String,
@@ -29,28 +34,51 @@ pub struct Video {
     pub credits_cost: i64,
     pub progress: i32,
     pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
     pub completed_at: Option<DateTime<Utc>>,
     pub failed_at: Option<DateTime<Utc>>,
+    pub cancelled_at: Option<DateTime<Utc>>,
     pub error_message: Option<String>,
+    pub org_id: Option<String>,
+    pub reference_image_id: Option<String>,
+    pub parent_video_id: Option<String>,
+    pub batch_id: Option<String>,
+    pub template_id: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_json_text")]
+    pub template_variables: Option<BTreeMap<String, String>>,
+    pub original_prompt: Option<String>,
+    pub storage_key: Option<String>,
+    pub storage_size: Option<i64>,
+    pub storage_checksum: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_int_bool")]
+    pub favorite: bool,
+    /// Not a column: filled in by `library::attach_tags` where it's shown.
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum VideoStatus {
+    #[serde(rename = "pending_submission")]
+    PendingSubmission,
     Queued,
     #[serde(rename = "in_progress")]
     InProgress,
     Completed,
     Failed,
+    Cancelled,
 }
 
 impl std::fmt::Display for VideoStatus {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            VideoStatus::PendingSubmission => write!(f, "pending_submission"),
             VideoStatus::Queued => write!(f, "queued"),
             VideoStatus::InProgress => write!(f, "in_progress"),
             VideoStatus::Completed => write!(f, "completed"),
             VideoStatus::Failed => write!(f, "failed"),
+            VideoStatus::Cancelled => write!(f, "cancelled"),
         }
     }
 }
@@ -67,7 +95,237 @@ pub struct CreditTransaction {
     #[serde(rename = "revenuecat_transaction_id")]
     pub apple_transaction_id: Option<String>,
     pub metadata: Option<String>,
+    pub org_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReferenceImage {
+    pub id: String,
+    #[serde(skip_serializing)]
+    pub user_id: String,
+    #[serde(skip_serializing)]
+    pub storage_key: String,
+    pub mime_type: String,
+    pub width: i64,
+    pub height: i64,
+    pub size_bytes: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A prompt with `{{placeholders}}`. Templates without a `user_id` are
+/// global and curated by admins.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub id: String,
+    pub user_id: Option<String>,
+    pub name: String,
+    pub description: Option<String>,
+    pub body: String,
+    #[serde(default)]
+    pub variables: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreatePromptTemplateRequest {
+    pub name: String,
+    pub description: Option<String>,
+    pub body: String,
+    #[serde(default)]
+    pub global: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdatePromptTemplateRequest {
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub description: Option<Option<String>>,
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PromptTemplateListResponse {
+    pub templates: Vec<PromptTemplate>,
+}
+
+/// A public link to a finished video. `max_views` limits page views, not
+/// the media requests the page makes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VideoShare {
+    pub slug: String,
+    pub video_id: String,
+    pub user_id: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_views: Option<i64>,
+    pub view_count: i64,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CreateShareRequest {
+    pub expires_in_hours: Option<i64>,
+    pub max_views: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShareResponse {
+    #[serde(flatten)]
+    pub share: VideoShare,
+    pub url: String,
+}
+
+/// A published video as the community feed shows it. `remix` is a
+/// `POST /v1/videos` body pre-filled with the item's prompt and settings.
+#[derive(Debug, Clone, Serialize)]
+pub struct GalleryItem {
+    pub video_id: String,
+    pub prompt: String,
+    pub model: String,
+    pub size: String,
+    pub seconds: i32,
+    pub like_count: i64,
+    pub liked: bool,
+    pub owned: bool,
+    pub published_at: DateTime<Utc>,
+    pub thumbnail_url: String,
+    pub video_url: String,
+    pub remix: CreateVideoRequest,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GalleryListResponse {
+    pub items: Vec<GalleryItem>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TakedownRequest {
+    pub reason: Option<String>,
+}
+
+/// A user's named group of videos. Videos can be in any number of
+/// collections.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Collection {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub video_count: i64,
     pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCollectionRequest {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateCollectionRequest {
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub description: Option<Option<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CollectionListResponse {
+    pub collections: Vec<Collection>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CollectionVideosRequest {
+    pub video_ids: Vec<String>,
+}
+
+/// `PATCH /v1/videos/:id`. `tags` replaces the video's whole tag set.
+#[derive(Debug, Deserialize)]
+pub struct UpdateVideoRequest {
+    pub favorite: Option<bool>,
+    pub tags: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagListResponse {
+    pub tags: Vec<TagCount>,
+}
+
+/// A matching video with an HTML-escaped excerpt whose matched terms are
+/// wrapped in `<mark>`. Lower `rank` is a better match.
+#[derive(Debug, Serialize)]
+pub struct VideoSearchResult {
+    #[serde(flatten)]
+    pub video: Video,
+    pub snippet: String,
+    pub rank: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VideoSearchResponse {
+    pub results: Vec<VideoSearchResult>,
+    pub has_more: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Organization {
+    pub id: String,
+    pub name: String,
+    pub credits_balance: i64,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum OrgRole {
+    Member,
+    Admin,
+    Owner,
+}
+
+impl std::fmt::Display for OrgRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrgRole::Member => write!(f, "member"),
+            OrgRole::Admin => write!(f, "admin"),
+            OrgRole::Owner => write!(f, "owner"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrganizationMember {
+    pub org_id: String,
+    pub user_id: String,
+    pub role: OrgRole,
+    pub monthly_credit_cap: Option<i64>,
+    pub joined_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrganizationInvitation {
+    pub id: String,
+    pub org_id: String,
+    pub token: String,
+    pub email: Option<String>,
+    pub role: OrgRole,
+    pub invited_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub accepted_at: Option<DateTime<Utc>>,
+    pub accepted_by: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -84,12 +342,56 @@ pub struct AuthResponse {
     pub created: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateVideoRequest {
     pub model: String,
+    #[serde(default)]
     pub prompt: String,
     pub size: String,
     pub seconds: i32,
+    pub org_id: Option<String>,
+    pub reference_image_id: Option<String>,
+    pub template_id: Option<String>,
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+    #[serde(default)]
+    pub enhance: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EnhancePromptRequest {
+    pub prompt: String,
+    pub org_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EnhancePromptResponse {
+    pub original_prompt: String,
+    pub enhanced_prompt: String,
+    pub changes: Vec<String>,
+    pub credits_cost: i64,
+    pub new_balance: i64,
+}
+
+/// Either an explicit list of videos, or one request repeated `variants` times.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum CreateBatchRequest {
+    Videos {
+        videos: Vec<CreateVideoRequest>,
+        org_id: Option<String>,
+    },
+    Variants {
+        #[serde(flatten)]
+        video: CreateVideoRequest,
+        variants: usize,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemixVideoRequest {
+    pub prompt: String,
+    pub org_id: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -103,6 +405,7 @@ pub struct EstimateRequest {
 pub struct EstimateResponse {
     pub credits_cost: i64,
     pub usd_equivalent: String,
+    pub equivalent: Money,
     pub current_balance: i64,
     pub sufficient_credits: bool,
 }
@@ -114,12 +417,91 @@ pub struct CreateVideoResponse {
     pub credits_cost: i64,
     pub new_balance: i64,
     pub estimated_wait_seconds: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_position: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enhanced_prompt: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VideoDetailResponse {
+    #[serde(flatten)]
+    pub video: Video,
+    pub remixes: Vec<RemixSummary>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemixSummary {
+    pub id: String,
+    pub status: VideoStatus,
+    pub prompt: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VideoBatch {
+    pub id: String,
+    pub user_id: String,
+    pub org_id: Option<String>,
+    pub total_credits: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    Pending,
+    InProgress,
+    Completed,
+    PartiallyCompleted,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct BatchCounts {
+    pub pending_submission: i64,
+    pub queued: i64,
+    pub in_progress: i64,
+    pub completed: i64,
+    pub failed: i64,
+    pub cancelled: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchResponse {
+    pub id: String,
+    pub status: BatchStatus,
+    pub total: i64,
+    pub counts: BatchCounts,
+    pub progress: i32,
+    pub credits_cost: i64,
+    pub refunded_credits: i64,
+    pub org_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub videos: Vec<Video>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateBatchResponse {
+    #[serde(flatten)]
+    pub batch: BatchResponse,
+    pub new_balance: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CancelVideoResponse {
+    pub id: String,
+    pub status: VideoStatus,
+    pub refunded_credits: i64,
+    pub new_balance: i64,
 }
 
 #[derive(Debug, Serialize)]
 pub struct BalanceResponse {
     pub credits_balance: i64,
     pub usd_equivalent: String,
+    pub equivalent: Money,
 }
 
 #[derive(Debug, Serialize)]
@@ -132,6 +514,7 @@ pub struct VideoListResponse {
 #[derive(Debug, Deserialize)]
 pub struct AppleIAPValidateRequest {
     pub transaction_jws: String,
+    pub org_id: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -142,6 +525,107 @@ pub struct AppleIAPValidateResponse {
     pub transaction_id: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct GiftCreditsRequest {
+    pub recipient_referral_code: Option<String>,
+    pub recipient_email: Option<String>,
+    pub amount: i64,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GiftCreditsResponse {
+    pub success: bool,
+    pub amount: i64,
+    pub new_balance: i64,
+    pub recipient_referral_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateOrganizationRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrganizationSummary {
+    #[serde(flatten)]
+    pub organization: Organization,
+    pub role: OrgRole,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrganizationDetailResponse {
+    #[serde(flatten)]
+    pub organization: Organization,
+    pub role: OrgRole,
+    pub members: Vec<OrganizationMember>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInvitationRequest {
+    pub email: Option<String>,
+    pub role: Option<OrgRole>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateMemberRequest {
+    pub role: Option<OrgRole>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub monthly_credit_cap: Option<Option<i64>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserBudget {
+    pub user_id: String,
+    pub daily_limit: Option<i64>,
+    pub weekly_limit: Option<i64>,
+    pub monthly_limit: Option<i64>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateBudgetRequest {
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub daily_limit: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub weekly_limit: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub monthly_limit: Option<Option<i64>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BudgetPeriodStatus {
+    pub limit: i64,
+    pub spent: i64,
+    pub remaining: i64,
+    pub resets_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BudgetResponse {
+    pub daily: Option<BudgetPeriodStatus>,
+    pub weekly: Option<BudgetPeriodStatus>,
+    pub monthly: Option<BudgetPeriodStatus>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub id: String,
+    pub user_id: String,
+    pub notification_type: String,
+    pub title: String,
+    pub body: String,
+    pub metadata: Option<String>,
+    pub read_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotificationListResponse {
+    pub notifications: Vec<Notification>,
+    pub unread_count: i64,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OpenAIWebhookEvent {
     pub id: String,
@@ -157,7 +641,7 @@ pub struct OpenAIWebhookData {
     pub id: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct OpenAIVideoResponse {
     pub id: String,
     #[allow(dead_code)]
@@ -175,7 +659,7 @@ pub struct OpenAIVideoResponse {
     pub error: Option<OpenAIError>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct OpenAIError {
     #[allow(dead_code)]
     pub code: String,
@@ -188,6 +672,7 @@ impl User {
             id: uuid::Uuid::new_v4().to_string(),
             apple_user_id,
             email,
+            referral_code: Some(generate_referral_code()),
             credits_balance: 0,
             total_videos_generated: 0,
             created_at: now,
@@ -197,6 +682,7 @@ impl User {
 }
 
 impl Video {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         user_id: String,
         openai_video_id: String,
@@ -223,9 +709,83 @@ impl Video {
             credits_cost,
             progress: 0,
             created_at: now,
+            started_at: None,
             completed_at: None,
             failed_at: None,
+            cancelled_at: None,
             error_message: None,
+            org_id: None,
+            reference_image_id: None,
+            parent_video_id: None,
+            batch_id: None,
+            template_id: None,
+            template_variables: None,
+            original_prompt: None,
+            storage_key: None,
+            storage_size: None,
+            storage_checksum: None,
+            favorite: false,
+            tags: Vec::new(),
         }
     }
+
+    /// A job waiting in the user's queue. `openai_video_id` is unique and
+    /// required, so it holds a placeholder until the job is submitted.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_pending(
+        user_id: String,
+        model: String,
+        prompt: String,
+        size: String,
+        seconds: i32,
+        credits_cost: i64,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let mut video = Self::new(user_id, String::new(), model, prompt, size, seconds, credits_cost, now);
+        video.openai_video_id = format!("{}{}", PENDING_OPENAI_ID_PREFIX, video.id);
+        video.status = VideoStatus::PendingSubmission;
+        video
+    }
+
+    pub fn is_submitted(&self) -> bool {
+        !self.openai_video_id.starts_with(PENDING_OPENAI_ID_PREFIX)
+    }
+}
+
+/// Reads a column holding a JSON document as text.
+fn deserialize_json_text<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: serde::de::DeserializeOwned,
+{
+    match Option::<serde_json::Value>::deserialize(deserializer)? {
+        Some(serde_json::Value::String(text)) => serde_json::from_str(&text).map(Some).map_err(serde::de::Error::custom),
+        Some(serde_json::Value::Null) | None => Ok(None),
+        Some(value) => serde_json::from_value(value).map(Some).map_err(serde::de::Error::custom),
+    }
+}
+
+/// Reads an SQLite boolean, stored as 0 or 1.
+fn deserialize_int_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<serde_json::Value>::deserialize(deserializer)? {
+        Some(serde_json::Value::Bool(value)) => Ok(value),
+        Some(serde_json::Value::Number(n)) => Ok(n.as_f64().is_some_and(|n| n != 0.0)),
+        Some(serde_json::Value::Null) | None => Ok(false),
+        Some(other) => Err(serde::de::Error::custom(format!("expected a boolean, got {}", other))),
+    }
+}
+
+fn deserialize_nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+fn generate_referral_code() -> String {
+    uuid::Uuid::new_v4().simple().to_string()[..8].to_uppercase()
 }
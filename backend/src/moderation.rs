@@ -0,0 +1,235 @@
+use crate::db::{get_db, now_rfc3339};
+use crate::error::AppError;
+use crate::openai_client::OpenAIClient;
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+use std::rc::Rc;
+use worker::{async_trait::async_trait, console_log, Env};
+
+const MAX_STORED_PROMPT_CHARS: usize = 1000;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ModerationVerdict {
+    pub flagged: bool,
+    pub categories: Vec<String>,
+}
+
+/// A remote classifier consulted after the local blocklist passes a prompt.
+#[async_trait(?Send)]
+pub trait ModerationProvider {
+    async fn moderate(&self, text: &str) -> Result<ModerationVerdict, AppError>;
+}
+
+/// Picks the remote classifier from `MODERATION_PROVIDER` (`openai` or
+/// `none`, default `none`).
+pub fn from_env(env: &Env) -> Result<Option<Rc<dyn ModerationProvider>>, AppError> {
+    let provider = env
+        .var("MODERATION_PROVIDER")
+        .map(|v| v.to_string())
+        .unwrap_or_else(|_| "none".to_string());
+
+    match provider.as_str() {
+        "none" => Ok(None),
+        "openai" => Ok(Some(Rc::new(OpenAIClient::from_env(env)))),
+        other => Err(AppError::InternalError(format!("Unknown MODERATION_PROVIDER: {}", other))),
+    }
+}
+
+/// Escapes `term` and anchors it at word boundaries, skipping ends that
+/// aren't word characters since `\b` would never match there.
+fn word_pattern(term: &str) -> String {
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let start = if is_word(term.chars().next()) { r"\b" } else { "" };
+    let end = if is_word(term.chars().last()) { r"\b" } else { "" };
+
+    format!("{}{}{}", start, regex::escape(term), end)
+}
+
+struct Rule {
+    category: String,
+    pattern: Regex,
+}
+
+/// Local rules from `MODERATION_BLOCKLIST` (whole-word terms) and
+/// `MODERATION_PATTERNS` (regexes). Both are JSON objects mapping a category
+/// to a list of entries, and both match case-insensitively.
+#[derive(Default)]
+pub struct Blocklist {
+    rules: Vec<Rule>,
+}
+
+impl Blocklist {
+    pub fn from_env(env: &Env) -> Result<Self, AppError> {
+        let read = |name: &str| env.var(name).ok().map(|v| v.to_string());
+
+        Self::from_config(
+            read("MODERATION_BLOCKLIST").as_deref(),
+            read("MODERATION_PATTERNS").as_deref(),
+        )
+    }
+
+    fn from_config(terms: Option<&str>, patterns: Option<&str>) -> Result<Self, AppError> {
+        let parse = |name: &str, json: Option<&str>| -> Result<HashMap<String, Vec<String>>, AppError> {
+            match json.map(str::trim).filter(|s| !s.is_empty()) {
+                Some(json) => serde_json::from_str(json)
+                    .map_err(|e| AppError::InternalError(format!("Invalid {}: {}", name, e))),
+                None => Ok(HashMap::new()),
+            }
+        };
+
+        let compile = |category: &str, source: &str| -> Result<Rule, AppError> {
+            let pattern = RegexBuilder::new(source)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| AppError::InternalError(format!("Invalid moderation pattern {:?}: {}", source, e)))?;
+
+            Ok(Rule {
+                category: category.to_string(),
+                pattern,
+            })
+        };
+
+        let mut rules = Vec::new();
+
+        for (category, entries) in parse("MODERATION_BLOCKLIST", terms)? {
+            for term in entries.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+                rules.push(compile(&category, &word_pattern(term))?);
+            }
+        }
+
+        for (category, entries) in parse("MODERATION_PATTERNS", patterns)? {
+            for source in &entries {
+                rules.push(compile(&category, source)?);
+            }
+        }
+
+        Ok(Self { rules })
+    }
+
+    /// The sorted, de-duplicated categories whose rules match `text`.
+    pub fn matches(&self, text: &str) -> Vec<String> {
+        let mut categories: Vec<String> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.pattern.is_match(text))
+            .map(|rule| rule.category.clone())
+            .collect();
+
+        categories.sort();
+        categories.dedup();
+        categories
+    }
+}
+
+async fn record_violation(
+    env: &Env,
+    user_id: &str,
+    prompt: &str,
+    categories: &[String],
+    source: &str,
+) -> Result<(), AppError> {
+    let db = get_db(env)?;
+    let now = now_rfc3339();
+    let stored_prompt: String = prompt.chars().take(MAX_STORED_PROMPT_CHARS).collect();
+
+    let statements = vec![
+        db.prepare("INSERT INTO moderation_violations (id, user_id, source, categories, prompt, created_at) VALUES (?, ?, ?, ?, ?, ?)")
+            .bind(&[
+                uuid::Uuid::new_v4().to_string().into(),
+                user_id.into(),
+                source.into(),
+                serde_json::to_string(categories)?.into(),
+                stored_prompt.into(),
+                now.clone().into(),
+            ])?,
+        db.prepare("INSERT INTO user_moderation_stats (user_id, violation_count, last_violation_at) VALUES (?, 1, ?) ON CONFLICT(user_id) DO UPDATE SET violation_count = violation_count + 1, last_violation_at = excluded.last_violation_at")
+            .bind(&[user_id.into(), now.into()])?,
+    ];
+
+    db.batch(statements)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Screens a prompt before any credits are charged. The local blocklist runs
+/// first; the remote provider, if configured, only sees prompts it passes.
+/// A provider outage lets the prompt through, since OpenAI moderates again at
+/// generation time. Blocked prompts are logged against the user.
+pub async fn check_prompt(
+    env: &Env,
+    provider: Option<&dyn ModerationProvider>,
+    user_id: &str,
+    prompt: &str,
+) -> Result<(), AppError> {
+    let mut categories = Blocklist::from_env(env)?.matches(prompt);
+    let mut source = "blocklist";
+
+    if categories.is_empty() {
+        if let Some(provider) = provider {
+            match provider.moderate(prompt).await {
+                Ok(verdict) if verdict.flagged => {
+                    categories = verdict.categories;
+                    source = "provider";
+                    if categories.is_empty() {
+                        categories.push("flagged".to_string());
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => console_log!("Moderation provider failed, allowing prompt: {:?}", e),
+            }
+        }
+    }
+
+    if categories.is_empty() {
+        return Ok(());
+    }
+
+    console_log!("Prompt from user {} blocked by {}: {:?}", user_id, source, categories);
+
+    if let Err(e) = record_violation(env, user_id, prompt, &categories, source).await {
+        console_log!("Failed to record moderation violation: {:?}", e);
+    }
+
+    Err(AppError::ContentPolicy(categories))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_whole_word_terms_case_insensitively() {
+        let blocklist = Blocklist::from_config(Some(r#"{"violence": ["gore", "blood bath"]}"#), None).unwrap();
+
+        assert_eq!(blocklist.matches("A GORE-soaked scene"), vec!["violence"]);
+        assert_eq!(blocklist.matches("a Blood Bath at dawn"), vec!["violence"]);
+        assert!(blocklist.matches("a gorey gorilla").is_empty());
+    }
+
+    #[test]
+    fn escapes_terms_and_compiles_patterns() {
+        let blocklist = Blocklist::from_config(
+            Some(r#"{"spam": ["c++"]}"#),
+            Some(r#"{"pii": ["\\b\\d{3}-\\d{2}-\\d{4}\\b"], "spam": ["buy\\s+now"]}"#),
+        )
+        .unwrap();
+
+        assert_eq!(blocklist.matches("learn C++ fast"), vec!["spam"]);
+        assert_eq!(blocklist.matches("ssn 123-45-6789, BUY   now"), vec!["pii", "spam"]);
+        assert!(blocklist.matches("a quiet forest").is_empty());
+    }
+
+    #[test]
+    fn empty_config_allows_everything() {
+        let blocklist = Blocklist::from_config(None, Some("  ")).unwrap();
+        assert!(blocklist.matches("anything at all").is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_config() {
+        assert!(Blocklist::from_config(Some("not json"), None).is_err());
+        assert!(Blocklist::from_config(None, Some(r#"{"x": ["("]}"#)).is_err());
+    }
+}
@@ -0,0 +1,325 @@
+const MAX_BOUNDARY_LENGTH: usize = 70;
+
+// Characters that RFC 2045 doesn't allow in an unquoted parameter value.
+const TSPECIALS: &str = "()<>@,;:\\\"/[]?= ";
+
+struct Part {
+    headers: String,
+    body: Vec<u8>,
+}
+
+/// A `multipart/form-data` body (RFC 7578).
+///
+/// Field names and filenames are escaped the way browsers do it, so user
+/// supplied values can't break out of their part.
+pub struct MultipartForm {
+    boundary: String,
+    parts: Vec<Part>,
+}
+
+impl Default for MultipartForm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MultipartForm {
+    pub fn new() -> Self {
+        let boundary = format!("sora-engine-{}", uuid::Uuid::new_v4().simple());
+        Self::with_boundary(&boundary).expect("generated boundary is valid")
+    }
+
+    /// Uses a fixed boundary instead of a random one. Returns `None` if the
+    /// boundary isn't valid per RFC 2046.
+    pub fn with_boundary(boundary: &str) -> Option<Self> {
+        let valid_char = |c: char| c.is_ascii_alphanumeric() || "'()+_,-./:=? ".contains(c);
+
+        if boundary.is_empty()
+            || boundary.len() > MAX_BOUNDARY_LENGTH
+            || boundary.ends_with(' ')
+            || !boundary.chars().all(valid_char)
+        {
+            return None;
+        }
+
+        Some(Self {
+            boundary: boundary.to_string(),
+            parts: Vec::new(),
+        })
+    }
+
+    /// The boundary is quoted when it contains characters that aren't allowed
+    /// in a bare parameter value.
+    pub fn content_type(&self) -> String {
+        if self.boundary.contains(|c| TSPECIALS.contains(c)) {
+            format!("multipart/form-data; boundary=\"{}\"", self.boundary)
+        } else {
+            format!("multipart/form-data; boundary={}", self.boundary)
+        }
+    }
+
+    pub fn text(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.parts.push(Part {
+            headers: format!("Content-Disposition: form-data; name=\"{}\"\r\n", escape_quoted(name)),
+            body: value.into().into_bytes(),
+        });
+        self
+    }
+
+    pub fn file(mut self, name: &str, filename: &str, content_type: &str, bytes: Vec<u8>) -> Self {
+        self.parts.push(Part {
+            headers: file_headers(name, filename, content_type),
+            body: bytes,
+        });
+        self
+    }
+
+    pub fn content_length(&self) -> u64 {
+        let parts: u64 = self
+            .parts
+            .iter()
+            .map(|p| (self.part_preamble(p).len() + 2 + p.body.len()) as u64)
+            .sum();
+
+        parts + self.closing_delimiter().len() as u64
+    }
+
+    fn part_preamble(&self, part: &Part) -> String {
+        format!("--{}\r\n{}\r\n", self.boundary, part.headers)
+    }
+
+    fn closing_delimiter(&self) -> String {
+        format!("--{}--\r\n", self.boundary)
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(self.content_length() as usize);
+
+        for part in &self.parts {
+            body.extend_from_slice(self.part_preamble(part).as_bytes());
+            body.extend_from_slice(&part.body);
+            body.extend_from_slice(b"\r\n");
+        }
+
+        body.extend_from_slice(self.closing_delimiter().as_bytes());
+        body
+    }
+}
+
+fn file_headers(name: &str, filename: &str, content_type: &str) -> String {
+    format!(
+        "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n",
+        escape_quoted(name),
+        escape_quoted(filename),
+        sanitize_header_value(content_type)
+    )
+}
+
+/// Escapes a value for a quoted Content-Disposition parameter using the
+/// WHATWG form encoding: `"`, CR and LF are percent-encoded, and everything
+/// else (including non-ASCII) is sent as UTF-8.
+fn escape_quoted(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("%22"),
+            '\r' => escaped.push_str("%0D"),
+            '\n' => escaped.push_str("%0A"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+fn sanitize_header_value(value: &str) -> String {
+    let value: String = value.chars().filter(|c| !c.is_control()).collect();
+    if value.trim().is_empty() {
+        "application/octet-stream".to_string()
+    } else {
+        value.trim().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct ParsedPart {
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    impl ParsedPart {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        }
+
+        fn disposition_param(&self, param: &str) -> Option<String> {
+            let disposition = self.header("Content-Disposition")?;
+            let key = format!("{}=\"", param);
+            let start = disposition
+                .split("; ")
+                .find_map(|p| p.strip_prefix(key.as_str()))?;
+            Some(start.strip_suffix('"')?.to_string())
+        }
+    }
+
+    fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+        haystack[from..]
+            .windows(needle.len())
+            .position(|w| w == needle)
+            .map(|i| i + from)
+    }
+
+    /// A strict RFC 7578 parser: every delimiter must be `CRLF--boundary` and
+    /// the body must end with the closing delimiter.
+    fn parse(content_type: &str, body: &[u8]) -> Vec<ParsedPart> {
+        let boundary = content_type
+            .strip_prefix("multipart/form-data; boundary=")
+            .expect("multipart content type");
+        let boundary = boundary
+            .strip_prefix('"')
+            .and_then(|b| b.strip_suffix('"'))
+            .unwrap_or(boundary);
+        let delimiter = format!("--{}", boundary).into_bytes();
+        let separator = format!("\r\n--{}", boundary).into_bytes();
+
+        assert!(body.starts_with(&delimiter), "body must start with the first delimiter");
+        let mut pos = delimiter.len();
+        let mut parts = Vec::new();
+
+        loop {
+            if body[pos..].starts_with(b"--\r\n") {
+                assert_eq!(pos + 4, body.len(), "nothing may follow the closing delimiter");
+                return parts;
+            }
+
+            assert!(body[pos..].starts_with(b"\r\n"), "delimiter must be followed by CRLF");
+            pos += 2;
+
+            let headers_end = find(body, b"\r\n\r\n", pos).expect("end of part headers");
+            let headers = std::str::from_utf8(&body[pos..headers_end])
+                .expect("utf-8 headers")
+                .split("\r\n")
+                .map(|line| {
+                    let (k, v) = line.split_once(": ").expect("header line");
+                    (k.to_string(), v.to_string())
+                })
+                .collect();
+            pos = headers_end + 4;
+
+            let body_end = find(body, &separator, pos).expect("next delimiter");
+            parts.push(ParsedPart {
+                headers,
+                body: body[pos..body_end].to_vec(),
+            });
+            pos = body_end + separator.len();
+        }
+    }
+
+    #[test]
+    fn round_trips_text_fields() {
+        let form = MultipartForm::new()
+            .text("model", "sora-2")
+            .text("prompt", "A cat\r\n--boundary\r\nContent-Disposition: form-data; name=\"x\"\r\n\r\nhi")
+            .text("seconds", "8");
+        let content_type = form.content_type();
+        let body = form.into_bytes();
+
+        let parts = parse(&content_type, &body);
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0].disposition_param("name").as_deref(), Some("model"));
+        assert_eq!(parts[0].body, b"sora-2");
+        assert_eq!(
+            parts[1].body,
+            b"A cat\r\n--boundary\r\nContent-Disposition: form-data; name=\"x\"\r\n\r\nhi"
+        );
+        assert_eq!(parts[2].body, b"8");
+    }
+
+    #[test]
+    fn escapes_names_and_filenames() {
+        let form = MultipartForm::with_boundary("test-boundary")
+            .unwrap()
+            .text("we\"ird\r\nname", "value")
+            .file("input_reference", "caf\u{e9} \"night\".png", "image/png\r\nX-Injected: 1", vec![1, 2, 3]);
+        let content_type = form.content_type();
+        let body = form.into_bytes();
+
+        let parts = parse(&content_type, &body);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].headers.len(), 1);
+        assert_eq!(parts[0].disposition_param("name").as_deref(), Some("we%22ird%0D%0Aname"));
+        assert_eq!(parts[1].disposition_param("filename").as_deref(), Some("caf\u{e9} %22night%22.png"));
+        assert_eq!(parts[1].header("Content-Type"), Some("image/pngX-Injected: 1"));
+        assert!(parts[1].header("X-Injected").is_none());
+    }
+
+    #[test]
+    fn round_trips_binary_file_parts() {
+        let image: Vec<u8> = (0..=255u8).cycle().take(4096).collect();
+        let mut tricky = b"\r\n--".to_vec();
+        tricky.extend_from_slice(&image);
+
+        let form = MultipartForm::new()
+            .text("prompt", "sunset")
+            .file("input_reference", "frame.png", "image/png", tricky.clone());
+        let content_type = form.content_type();
+        let expected_length = form.content_length();
+        let body = form.into_bytes();
+
+        assert_eq!(body.len() as u64, expected_length);
+
+        let parts = parse(&content_type, &body);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[1].header("Content-Type"), Some("image/png"));
+        assert_eq!(parts[1].disposition_param("filename").as_deref(), Some("frame.png"));
+        assert_eq!(parts[1].body, tricky);
+    }
+
+    #[test]
+    fn boundaries_are_random_and_valid() {
+        let a = MultipartForm::new();
+        let b = MultipartForm::new();
+        assert_ne!(a.boundary, b.boundary);
+        assert!(MultipartForm::with_boundary(&a.boundary).is_some());
+    }
+
+    #[test]
+    fn quotes_boundaries_with_special_characters() {
+        let plain = MultipartForm::with_boundary("abc-123_x.y").unwrap();
+        assert_eq!(plain.content_type(), "multipart/form-data; boundary=abc-123_x.y");
+
+        for boundary in ["a:b", "a=b", "a/b", "a?b", "a,b", "(ab)", "a b"] {
+            let form = MultipartForm::with_boundary(boundary).unwrap().text("prompt", "hi");
+            let content_type = form.content_type();
+            assert_eq!(content_type, format!("multipart/form-data; boundary=\"{}\"", boundary));
+
+            let parts = parse(&content_type, &form.into_bytes());
+            assert_eq!(parts[0].body, b"hi");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_boundaries() {
+        assert!(MultipartForm::with_boundary("").is_none());
+        assert!(MultipartForm::with_boundary("trailing ").is_none());
+        assert!(MultipartForm::with_boundary("has\"quote").is_none());
+        assert!(MultipartForm::with_boundary(&"a".repeat(71)).is_none());
+    }
+
+    #[test]
+    fn empty_content_type_falls_back_to_octet_stream() {
+        let form = MultipartForm::with_boundary("b")
+            .unwrap()
+            .file("file", "x", "  ", vec![0]);
+        let content_type = form.content_type();
+        let parts = parse(&content_type, &form.into_bytes());
+        assert_eq!(parts[0].header("Content-Type"), Some("application/octet-stream"));
+    }
+}
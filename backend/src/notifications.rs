@@ -0,0 +1,96 @@
+use crate::db::{get_db, now_rfc3339};
+use crate::error::AppError;
+use crate::models::Notification;
+use serde::Deserialize;
+use worker::{wasm_bindgen::JsValue, Env};
+
+const NOTIFICATION_COLUMNS: &str = "id, user_id, notification_type, title, body, metadata, read_at, created_at";
+
+#[derive(Deserialize)]
+struct CountResult {
+    count: f64,
+}
+
+pub async fn create_notification(
+    env: &Env,
+    user_id: &str,
+    notification_type: &str,
+    title: &str,
+    body: &str,
+    metadata: Option<serde_json::Value>,
+) -> Result<(), AppError> {
+    let db = get_db(env)?;
+
+    db.prepare("INSERT INTO notifications (id, user_id, notification_type, title, body, metadata, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)")
+        .bind(&[
+            uuid::Uuid::new_v4().to_string().into(),
+            user_id.into(),
+            notification_type.into(),
+            title.into(),
+            body.into(),
+            metadata
+                .map(|m| JsValue::from_str(&m.to_string()))
+                .unwrap_or(JsValue::NULL),
+            now_rfc3339().into(),
+        ])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+pub async fn list_notifications(
+    env: &Env,
+    user_id: &str,
+    unread_only: bool,
+    limit: i32,
+) -> Result<Vec<Notification>, AppError> {
+    let db = get_db(env)?;
+
+    let filter = if unread_only { " AND read_at IS NULL" } else { "" };
+
+    db.prepare(format!(
+        "SELECT {} FROM notifications WHERE user_id = ?{} ORDER BY created_at DESC LIMIT ?",
+        NOTIFICATION_COLUMNS, filter
+    ))
+    .bind(&[user_id.into(), limit.into()])?
+    .all()
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?
+    .results::<Notification>()
+    .map_err(|e| AppError::DatabaseError(e.to_string()))
+}
+
+pub async fn count_unread(env: &Env, user_id: &str) -> Result<i64, AppError> {
+    let db = get_db(env)?;
+
+    let count_result: Option<CountResult> = db
+        .prepare("SELECT COUNT(*) as count FROM notifications WHERE user_id = ? AND read_at IS NULL")
+        .bind(&[user_id.into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(count_result.map(|r| r.count as i64).unwrap_or(0))
+}
+
+pub async fn mark_read(env: &Env, user_id: &str, notification_id: Option<&str>) -> Result<(), AppError> {
+    let db = get_db(env)?;
+
+    let statement = match notification_id {
+        Some(id) => db
+            .prepare("UPDATE notifications SET read_at = ? WHERE id = ? AND user_id = ? AND read_at IS NULL")
+            .bind(&[now_rfc3339().into(), id.into(), user_id.into()])?,
+        None => db
+            .prepare("UPDATE notifications SET read_at = ? WHERE user_id = ? AND read_at IS NULL")
+            .bind(&[now_rfc3339().into(), user_id.into()])?,
+    };
+
+    statement
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
@@ -1,99 +1,624 @@
+use crate::db::now_datetime;
 use crate::error::AppError;
 use crate::models::OpenAIVideoResponse;
-use worker::{Env, Fetch, Headers, Method, Request, RequestInit};
-
-pub async fn create_video(
-    env: &Env,
-    model: &str,
-    prompt: &str,
-    size: &str,
-    seconds: i32,
-) -> Result<OpenAIVideoResponse, AppError> {
-    let api_key = env
-        .secret("OPENAI_API_KEY")
-        .map_err(|_| AppError::InternalError("OPENAI_API_KEY not configured".into()))?
-        .to_string();
+use crate::moderation::{ModerationProvider, ModerationVerdict};
+use crate::prompt_enhancer::{self, Enhancement, PromptEnhancer};
+use crate::multipart::MultipartForm;
+use crate::video_provider::{InputReference, VideoProvider};
+use chrono::{DateTime, Utc};
+use futures_util::future::{select, Either};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use worker::{
+    async_trait::async_trait, console_log, AbortController, Delay, Env, Fetch, Headers, Method,
+    Request, RequestInit, Response,
+};
 
-    let url = "https://api.openai.com/v1/videos";
-
-    let form_data = format!(
-        "--boundary\r\nContent-Disposition: form-data; name=\"model\"\r\n\r\n{}\r\n\
-        --boundary\r\nContent-Disposition: form-data; name=\"prompt\"\r\n\r\n{}\r\n\
-        --boundary\r\nContent-Disposition: form-data; name=\"size\"\r\n\r\n{}\r\n\
-        --boundary\r\nContent-Disposition: form-data; name=\"seconds\"\r\n\r\n{}\r\n\
-        --boundary--\r\n",
-        model, prompt, size, seconds
-    );
-
-    let headers = Headers::new();
-    headers.set("Authorization", &format!("Bearer {}", api_key))?;
-    headers.set("Content-Type", "multipart/form-data; boundary=boundary")?;
-
-    let request = Request::new_with_init(
-        url,
-        &RequestInit::new()
-            .with_method(Method::Post)
-            .with_headers(headers)
-            .with_body(Some(form_data.into())),
-    )?;
-
-    let mut response = Fetch::Request(request).send().await?;
-    let text = response.text().await?;
-
-    if response.status_code() < 200 || response.status_code() >= 300 {
-        return Err(AppError::ExternalApiError(format!(
-            "OpenAI API error ({}): {}",
-            response.status_code(),
-            text
-        )));
-    }
-
-    serde_json::from_str(&text).map_err(|e| {
-        AppError::ExternalApiError(format!("Failed to parse OpenAI response: {}", e))
-    })
-}
-
-pub async fn get_video_status(
-    env: &Env,
-    video_id: &str,
-) -> Result<OpenAIVideoResponse, AppError> {
-    let api_key = env
-        .secret("OPENAI_API_KEY")
-        .map_err(|_| AppError::InternalError("OPENAI_API_KEY not configured".into()))?
-        .to_string();
+const DEFAULT_OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+const DEFAULT_MODERATION_MODEL: &str = "omni-moderation-latest";
+const DEFAULT_ENHANCER_MODEL: &str = "gpt-4o-mini";
+const DEFAULT_TIMEOUT_MS: u64 = 30_000;
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const BASE_BACKOFF_MS: u64 = 500;
+const MAX_BACKOFF_MS: u64 = 8_000;
+const MAX_RETRY_AFTER_MS: u64 = 20_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpenAIErrorKind {
+    RateLimited,
+    ModerationBlocked,
+    InvalidRequest,
+    ServerError,
+    QuotaExceeded,
+}
+
+impl OpenAIErrorKind {
+    pub fn status(&self) -> u16 {
+        match self {
+            OpenAIErrorKind::RateLimited => 503,
+            OpenAIErrorKind::ModerationBlocked => 422,
+            OpenAIErrorKind::InvalidRequest => 400,
+            OpenAIErrorKind::ServerError => 502,
+            OpenAIErrorKind::QuotaExceeded => 503,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            OpenAIErrorKind::RateLimited => "provider_rate_limited",
+            OpenAIErrorKind::ModerationBlocked => "moderation_blocked",
+            OpenAIErrorKind::InvalidRequest => "provider_invalid_request",
+            OpenAIErrorKind::ServerError => "provider_unavailable",
+            OpenAIErrorKind::QuotaExceeded => "provider_quota_exceeded",
+        }
+    }
+
+    fn is_retryable(&self) -> bool {
+        matches!(self, OpenAIErrorKind::RateLimited | OpenAIErrorKind::ServerError)
+    }
+}
+
+#[derive(Deserialize)]
+struct ErrorEnvelope {
+    error: ErrorBody,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    message: Option<String>,
+    #[serde(rename = "type")]
+    error_type: Option<String>,
+    code: Option<String>,
+}
+
+fn classify_error(status: u16, body: &str) -> (OpenAIErrorKind, String) {
+    let parsed = serde_json::from_str::<ErrorEnvelope>(body).ok().map(|e| e.error);
+
+    let code = parsed.as_ref().and_then(|e| e.code.clone()).unwrap_or_default();
+    let error_type = parsed.as_ref().and_then(|e| e.error_type.clone()).unwrap_or_default();
+    let message = parsed
+        .and_then(|e| e.message)
+        .filter(|m| !m.is_empty())
+        .unwrap_or_else(|| format!("OpenAI API error ({})", status));
+
+    let is = |needle: &str| code.contains(needle) || error_type.contains(needle);
+
+    let kind = if is("insufficient_quota") || is("billing") {
+        OpenAIErrorKind::QuotaExceeded
+    } else if is("moderation") || is("content_policy") || is("safety") {
+        OpenAIErrorKind::ModerationBlocked
+    } else if status == 429 {
+        OpenAIErrorKind::RateLimited
+    } else if status >= 500 || status == 408 {
+        OpenAIErrorKind::ServerError
+    } else {
+        OpenAIErrorKind::InvalidRequest
+    };
+
+    (kind, message)
+}
+
+/// Reads `retry-after-ms` or `Retry-After` (delta-seconds or an HTTP date).
+fn parse_retry_after(retry_after_ms: Option<&str>, retry_after: Option<&str>, now: DateTime<Utc>) -> Option<Duration> {
+    if let Some(ms) = retry_after_ms.and_then(|v| v.trim().parse::<f64>().ok()) {
+        return Some(Duration::from_millis(ms.max(0.0) as u64));
+    }
+
+    let value = retry_after?.trim();
+
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Some(Duration::from_millis((seconds.max(0.0) * 1000.0) as u64));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?.with_timezone(&Utc);
+    Some((date - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Exponential backoff with full jitter, or the server's `Retry-After` when
+/// it sent one. Returns `None` when the server asks us to wait too long.
+fn backoff_delay(attempt: u32, retry_after: Option<Duration>, jitter: f64) -> Option<Duration> {
+    if let Some(retry_after) = retry_after {
+        return (retry_after.as_millis() as u64 <= MAX_RETRY_AFTER_MS).then_some(retry_after);
+    }
+
+    let ceiling = BASE_BACKOFF_MS
+        .saturating_mul(1u64 << attempt.min(16))
+        .min(MAX_BACKOFF_MS);
+
+    Some(Duration::from_millis((ceiling as f64 * jitter.clamp(0.0, 1.0)) as u64))
+}
+
+pub struct OpenAIClient {
+    api_key: Option<String>,
+    base_url: String,
+    timeout: Duration,
+    max_retries: u32,
+    moderation_model: String,
+    chat_model: String,
+}
+
+impl OpenAIClient {
+    pub fn from_env(env: &Env) -> Self {
+        let base_url = env
+            .var("OPENAI_BASE_URL")
+            .map(|v| v.to_string())
+            .unwrap_or_else(|_| DEFAULT_OPENAI_BASE_URL.to_string());
+
+        let timeout_ms = env
+            .var("OPENAI_TIMEOUT_MS")
+            .map(|v| v.to_string().parse::<u64>().unwrap_or(DEFAULT_TIMEOUT_MS))
+            .unwrap_or(DEFAULT_TIMEOUT_MS);
+
+        let max_retries = env
+            .var("OPENAI_MAX_RETRIES")
+            .map(|v| v.to_string().parse::<u32>().unwrap_or(DEFAULT_MAX_RETRIES))
+            .unwrap_or(DEFAULT_MAX_RETRIES);
+
+        Self {
+            api_key: env.secret("OPENAI_API_KEY").ok().map(|s| s.to_string()),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            timeout: Duration::from_millis(timeout_ms),
+            max_retries,
+            moderation_model: env
+                .var("MODERATION_MODEL")
+                .map(|v| v.to_string())
+                .unwrap_or_else(|_| DEFAULT_MODERATION_MODEL.to_string()),
+            chat_model: env
+                .var("ENHANCER_MODEL")
+                .map(|v| v.to_string())
+                .unwrap_or_else(|_| DEFAULT_ENHANCER_MODEL.to_string()),
+        }
+    }
+
+    /// A client for prompt enhancement. `ENHANCER_BASE_URL` and the
+    /// `ENHANCER_API_KEY` secret point it at any OpenAI-compatible
+    /// chat-completions API, falling back to the OpenAI settings.
+    pub fn enhancer_from_env(env: &Env) -> Self {
+        let mut client = Self::from_env(env);
+
+        if let Ok(base_url) = env.var("ENHANCER_BASE_URL") {
+            client.base_url = base_url.to_string().trim_end_matches('/').to_string();
+        }
+        if let Ok(api_key) = env.secret("ENHANCER_API_KEY") {
+            client.api_key = Some(api_key.to_string());
+        }
+
+        client
+    }
+
+    fn headers(&self) -> Result<Headers, AppError> {
+        let api_key = self
+            .api_key
+            .as_deref()
+            .ok_or_else(|| AppError::InternalError("OPENAI_API_KEY not configured".into()))?;
+
+        let headers = Headers::new();
+        headers.set("Authorization", &format!("Bearer {}", api_key))?;
+        Ok(headers)
+    }
+
+    async fn fetch_with_timeout(&self, request: Request) -> Result<Response, AppError> {
+        let controller = AbortController::default();
+        let signal = controller.signal();
+
+        let fetch = Fetch::Request(request);
+        let send = Box::pin(fetch.send_with_signal(&signal));
+        let timeout = Box::pin(Delay::from(self.timeout));
+
+        let outcome = match select(send, timeout).await {
+            Either::Left((response, _)) => Some(response),
+            Either::Right(_) => None,
+        };
+
+        match outcome {
+            Some(response) => response.map_err(|e| {
+                AppError::OpenAIError(OpenAIErrorKind::ServerError, format!("Request to OpenAI failed: {}", e))
+            }),
+            None => {
+                controller.abort();
+                Err(AppError::OpenAIError(
+                    OpenAIErrorKind::ServerError,
+                    format!("OpenAI didn't respond within {}ms", self.timeout.as_millis()),
+                ))
+            }
+        }
+    }
+
+    /// Sends a request built by `build`, retrying timeouts, network errors,
+    /// 429s and 5xx with backoff. Requests are rebuilt for every attempt since
+    /// bodies can only be sent once. Other responses are returned untouched.
+    async fn execute(&self, build: impl Fn() -> Result<Request, AppError>) -> Result<Response, AppError> {
+        let mut attempt = 0;
+
+        loop {
+            let (error, retry_after) = match self.fetch_with_timeout(build()?).await {
+                Ok(mut response) => {
+                    let status = response.status_code();
+                    if status != 429 && status < 500 {
+                        return Ok(response);
+                    }
+
+                    let retry_after = parse_retry_after(
+                        response.headers().get("retry-after-ms")?.as_deref(),
+                        response.headers().get("Retry-After")?.as_deref(),
+                        now_datetime(),
+                    );
+                    let text = response.text().await.unwrap_or_default();
+                    let (kind, message) = classify_error(status, &text);
+
+                    (AppError::OpenAIError(kind, message), retry_after)
+                }
+                Err(e) => (e, None),
+            };
+
+            let retryable = matches!(&error, AppError::OpenAIError(kind, _) if kind.is_retryable());
+            if !retryable || attempt >= self.max_retries {
+                return Err(error);
+            }
+
+            let Some(delay) = backoff_delay(attempt, retry_after, worker::js_sys::Math::random()) else {
+                return Err(error);
+            };
+
+            console_log!("OpenAI call failed ({}), retrying in {}ms (attempt {})", error, delay.as_millis(), attempt + 1);
+            Delay::from(delay).await;
+            attempt += 1;
+        }
+    }
+
+    async fn error_from(mut response: Response) -> AppError {
+        let status = response.status_code();
+        let text = response.text().await.unwrap_or_default();
+        let (kind, message) = classify_error(status, &text);
+        AppError::OpenAIError(kind, message)
+    }
+
+    async fn send_json<T: DeserializeOwned>(&self, build: impl Fn() -> Result<Request, AppError>) -> Result<T, AppError> {
+        let mut response = self.execute(build).await?;
+
+        if response.status_code() < 200 || response.status_code() >= 300 {
+            return Err(Self::error_from(response).await);
+        }
+
+        let text = response.text().await?;
+
+        serde_json::from_str(&text).map_err(|e| {
+            AppError::ExternalApiError(format!("Failed to parse OpenAI response: {}", e))
+        })
+    }
+}
+
+#[async_trait(?Send)]
+impl VideoProvider for OpenAIClient {
+    async fn create_video(
+        &self,
+        request_id: &str,
+        model: &str,
+        prompt: &str,
+        size: &str,
+        seconds: i32,
+        input_reference: Option<InputReference<'_>>,
+    ) -> Result<OpenAIVideoResponse, AppError> {
+        let url = format!("{}/videos", self.base_url);
+        let idempotency_key = format!("create-video-{}", request_id);
+
+        let mut form = MultipartForm::new()
+            .text("model", model)
+            .text("prompt", prompt)
+            .text("size", size)
+            .text("seconds", seconds.to_string());
+
+        if let Some(reference) = input_reference {
+            form = form.file(
+                "input_reference",
+                reference.filename,
+                reference.mime_type,
+                reference.bytes.to_vec(),
+            );
+        }
+
+        let content_type = form.content_type();
+        let body = form.into_bytes();
+
+        self.send_json(|| {
+            let headers = self.headers()?;
+            headers.set("Content-Type", &content_type)?;
+            headers.set("Idempotency-Key", &idempotency_key)?;
+
+            Ok(Request::new_with_init(
+                &url,
+                RequestInit::new()
+                    .with_method(Method::Post)
+                    .with_headers(headers)
+                    .with_body(Some(worker::js_sys::Uint8Array::from(body.as_slice()).into())),
+            )?)
+        })
+        .await
+    }
+
+    async fn retrieve_video(&self, video_id: &str) -> Result<OpenAIVideoResponse, AppError> {
+        let url = format!("{}/videos/{}", self.base_url, video_id);
+
+        self.send_json(|| {
+            Ok(Request::new_with_init(
+                &url,
+                RequestInit::new()
+                    .with_method(Method::Get)
+                    .with_headers(self.headers()?),
+            )?)
+        })
+        .await
+    }
+
+    async fn download_content(
+        &self,
+        video_id: &str,
+        variant: &str,
+        range: Option<&str>,
+    ) -> Result<Response, AppError> {
+        let url = format!("{}/videos/{}/content?variant={}", self.base_url, video_id, variant);
+
+        let response = self
+            .execute(|| {
+                let headers = self.headers()?;
+                if let Some(range) = range {
+                    headers.set("Range", range)?;
+                }
+
+                Ok(Request::new_with_init(
+                    &url,
+                    RequestInit::new()
+                        .with_method(Method::Get)
+                        .with_headers(headers),
+                )?)
+            })
+            .await?;
+
+        if response.status_code() < 200 || response.status_code() >= 300 {
+            return Err(Self::error_from(response).await);
+        }
+
+        Ok(response)
+    }
+
+    async fn delete_video(&self, video_id: &str) -> Result<(), AppError> {
+        let url = format!("{}/videos/{}", self.base_url, video_id);
+
+        let response = self
+            .execute(|| {
+                Ok(Request::new_with_init(
+                    &url,
+                    RequestInit::new()
+                        .with_method(Method::Delete)
+                        .with_headers(self.headers()?),
+                )?)
+            })
+            .await?;
+
+        if response.status_code() == 404 {
+            return Ok(());
+        }
+
+        if response.status_code() < 200 || response.status_code() >= 300 {
+            return Err(Self::error_from(response).await);
+        }
+
+        Ok(())
+    }
+
+    async fn remix_video(&self, video_id: &str, prompt: &str) -> Result<OpenAIVideoResponse, AppError> {
+        let url = format!("{}/videos/{}/remix", self.base_url, video_id);
+        let idempotency_key = uuid::Uuid::new_v4().to_string();
+        let body = serde_json::json!({ "prompt": prompt }).to_string();
+
+        self.send_json(|| {
+            let headers = self.headers()?;
+            headers.set("Content-Type", "application/json")?;
+            headers.set("Idempotency-Key", &idempotency_key)?;
+
+            Ok(Request::new_with_init(
+                &url,
+                RequestInit::new()
+                    .with_method(Method::Post)
+                    .with_headers(headers)
+                    .with_body(Some(body.as_str().into())),
+            )?)
+        })
+        .await
+    }
+}
+
+#[derive(Deserialize)]
+struct ModerationResponse {
+    results: Vec<ModerationResult>,
+}
+
+#[derive(Deserialize)]
+struct ModerationResult {
+    flagged: bool,
+    #[serde(default)]
+    categories: BTreeMap<String, bool>,
+}
 
-    let url = format!("https://api.openai.com/v1/videos/{}", video_id);
+fn verdict_from(response: ModerationResponse) -> ModerationVerdict {
+    let mut verdict = ModerationVerdict::default();
 
-    let headers = Headers::new();
-    headers.set("Authorization", &format!("Bearer {}", api_key))?;
+    for result in response.results {
+        verdict.flagged |= result.flagged;
+        verdict.categories.extend(
+            result
+                .categories
+                .into_iter()
+                .filter(|(_, hit)| *hit)
+                .map(|(category, _)| category),
+        );
+    }
+
+    verdict.categories.sort();
+    verdict.categories.dedup();
+    verdict
+}
 
-    let request = Request::new_with_init(
-        &url,
-        &RequestInit::new()
-            .with_method(Method::Get)
-            .with_headers(headers),
-    )?;
+#[async_trait(?Send)]
+impl ModerationProvider for OpenAIClient {
+    async fn moderate(&self, text: &str) -> Result<ModerationVerdict, AppError> {
+        let url = format!("{}/moderations", self.base_url);
+        let body = serde_json::json!({ "model": self.moderation_model, "input": text }).to_string();
 
-    let mut response = Fetch::Request(request).send().await?;
-    let text = response.text().await?;
+        let response: ModerationResponse = self
+            .send_json(|| {
+                let headers = self.headers()?;
+                headers.set("Content-Type", "application/json")?;
 
-    if response.status_code() < 200 || response.status_code() >= 300 {
-        return Err(AppError::ExternalApiError(format!(
-            "OpenAI API error ({}): {}",
-            response.status_code(),
-            text
-        )));
+                Ok(Request::new_with_init(
+                    &url,
+                    RequestInit::new()
+                        .with_method(Method::Post)
+                        .with_headers(headers)
+                        .with_body(Some(body.as_str().into())),
+                )?)
+            })
+            .await?;
+
+        Ok(verdict_from(response))
     }
+}
+
+#[derive(Deserialize)]
+struct ChatCompletionResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatMessage,
+}
 
-    serde_json::from_str(&text).map_err(|e| {
-        AppError::ExternalApiError(format!("Failed to parse OpenAI response: {}", e))
-    })
+#[derive(Deserialize)]
+struct ChatMessage {
+    content: Option<String>,
+}
+
+#[async_trait(?Send)]
+impl PromptEnhancer for OpenAIClient {
+    async fn enhance(&self, prompt: &str) -> Result<Enhancement, AppError> {
+        let url = format!("{}/chat/completions", self.base_url);
+        let body = serde_json::json!({
+            "model": self.chat_model,
+            "messages": [
+                { "role": "system", "content": prompt_enhancer::ENHANCER_INSTRUCTIONS },
+                { "role": "user", "content": prompt },
+            ],
+            "response_format": { "type": "json_object" },
+            "temperature": 0.7,
+        })
+        .to_string();
+
+        let response: ChatCompletionResponse = self
+            .send_json(|| {
+                let headers = self.headers()?;
+                headers.set("Content-Type", "application/json")?;
+
+                Ok(Request::new_with_init(
+                    &url,
+                    RequestInit::new()
+                        .with_method(Method::Post)
+                        .with_headers(headers)
+                        .with_body(Some(body.as_str().into())),
+                )?)
+            })
+            .await?;
+
+        let content = response
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .ok_or_else(|| AppError::ExternalApiError("Prompt enhancer returned no message".into()))?;
+
+        prompt_enhancer::parse_enhancement(&content)
+    }
 }
 
-#[allow(dead_code)]
-pub fn build_download_url(video_id: &str, variant: &str) -> String {
-    format!(
-        "https://api.openai.com/v1/videos/{}/content?variant={}",
-        video_id, variant
-    )
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_openai_errors() {
+        let body = |code: &str, error_type: &str| {
+            format!(r#"{{"error": {{"message": "nope", "type": "{}", "code": "{}"}}}}"#, error_type, code)
+        };
+
+        assert_eq!(classify_error(429, &body("rate_limit_exceeded", "requests")).0, OpenAIErrorKind::RateLimited);
+        assert_eq!(classify_error(429, &body("insufficient_quota", "insufficient_quota")).0, OpenAIErrorKind::QuotaExceeded);
+        assert_eq!(classify_error(400, &body("moderation_blocked", "invalid_request_error")).0, OpenAIErrorKind::ModerationBlocked);
+        assert_eq!(classify_error(400, &body("invalid_value", "invalid_request_error")).0, OpenAIErrorKind::InvalidRequest);
+        assert_eq!(classify_error(503, "upstream connect error").0, OpenAIErrorKind::ServerError);
+        assert_eq!(classify_error(500, &body("", "server_error")).1, "nope");
+        assert_eq!(classify_error(502, "<html>").1, "OpenAI API error (502)");
+    }
+
+    #[test]
+    fn only_transient_errors_are_retried() {
+        assert!(OpenAIErrorKind::RateLimited.is_retryable());
+        assert!(OpenAIErrorKind::ServerError.is_retryable());
+        assert!(!OpenAIErrorKind::QuotaExceeded.is_retryable());
+        assert!(!OpenAIErrorKind::ModerationBlocked.is_retryable());
+        assert!(!OpenAIErrorKind::InvalidRequest.is_retryable());
+    }
+
+    #[test]
+    fn parses_retry_after() {
+        let now = DateTime::parse_from_rfc3339("2025-01-01T00:00:00Z").unwrap().with_timezone(&Utc);
+
+        assert_eq!(parse_retry_after(Some("1500"), Some("9"), now), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_retry_after(None, Some("2"), now), Some(Duration::from_secs(2)));
+        assert_eq!(
+            parse_retry_after(None, Some("Wed, 01 Jan 2025 00:00:05 GMT"), now),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            parse_retry_after(None, Some("Tue, 31 Dec 2024 23:59:00 GMT"), now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after(None, Some("soon"), now), None);
+        assert_eq!(parse_retry_after(None, None, now), None);
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_with_jitter() {
+        assert_eq!(backoff_delay(0, None, 1.0), Some(Duration::from_millis(500)));
+        assert_eq!(backoff_delay(2, None, 1.0), Some(Duration::from_millis(2000)));
+        assert_eq!(backoff_delay(2, None, 0.5), Some(Duration::from_millis(1000)));
+        assert_eq!(backoff_delay(10, None, 1.0), Some(Duration::from_millis(MAX_BACKOFF_MS)));
+        assert_eq!(backoff_delay(3, None, 0.0), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn backoff_honours_retry_after() {
+        assert_eq!(backoff_delay(0, Some(Duration::from_secs(3)), 0.1), Some(Duration::from_secs(3)));
+        assert_eq!(backoff_delay(0, Some(Duration::from_secs(60)), 0.1), None);
+    }
+
+    #[test]
+    fn collects_flagged_moderation_categories() {
+        let response: ModerationResponse = serde_json::from_str(
+            r#"{"id": "modr-1", "results": [{"flagged": true, "categories": {"violence": true, "self-harm": false, "harassment": true}, "category_scores": {}}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            verdict_from(response),
+            ModerationVerdict {
+                flagged: true,
+                categories: vec!["harassment".into(), "violence".into()],
+            }
+        );
+
+        let clean: ModerationResponse =
+            serde_json::from_str(r#"{"results": [{"flagged": false, "categories": {"violence": false}}]}"#).unwrap();
+        assert_eq!(verdict_from(clean), ModerationVerdict::default());
+    }
 }
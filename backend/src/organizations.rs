@@ -0,0 +1,372 @@
+use crate::db::{self, get_db, now_datetime};
+use crate::error::AppError;
+use crate::models::{
+    CreditTransaction, OrgRole, Organization, OrganizationInvitation, OrganizationMember,
+    OrganizationSummary, User, Video,
+};
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use serde::Deserialize;
+use worker::{wasm_bindgen::JsValue, Env};
+
+const INVITATION_TTL_DAYS: i64 = 7;
+
+const ORGANIZATION_COLUMNS: &str = "id, name, credits_balance, created_by, created_at, updated_at";
+
+const INVITATION_COLUMNS: &str = "id, org_id, token, email, role, invited_by, created_at, expires_at, accepted_at, accepted_by";
+
+#[derive(Deserialize)]
+struct CountResult {
+    count: f64,
+}
+
+#[derive(Deserialize)]
+struct SumResult {
+    total: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct MembershipRow {
+    id: String,
+    name: String,
+    credits_balance: i64,
+    created_by: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    role: OrgRole,
+}
+
+pub async fn create_organization(env: &Env, name: &str, owner_id: &str) -> Result<Organization, AppError> {
+    let db = get_db(env)?;
+    let now = now_datetime();
+
+    let organization = Organization {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        credits_balance: 0,
+        created_by: owner_id.to_string(),
+        created_at: now,
+        updated_at: now,
+    };
+
+    let statements = vec![
+        db.prepare("INSERT INTO organizations (id, name, credits_balance, created_by, created_at, updated_at) VALUES (?, ?, 0, ?, ?, ?)")
+            .bind(&[
+                organization.id.clone().into(),
+                organization.name.clone().into(),
+                owner_id.into(),
+                now.to_rfc3339().into(),
+                now.to_rfc3339().into(),
+            ])?,
+        db.prepare("INSERT INTO organization_members (org_id, user_id, role, joined_at) VALUES (?, ?, ?, ?)")
+            .bind(&[
+                organization.id.clone().into(),
+                owner_id.into(),
+                OrgRole::Owner.to_string().into(),
+                now.to_rfc3339().into(),
+            ])?,
+    ];
+
+    db.batch(statements)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(organization)
+}
+
+pub async fn get_organization(env: &Env, org_id: &str) -> Result<Organization, AppError> {
+    let db = get_db(env)?;
+
+    db.prepare(format!("SELECT {} FROM organizations WHERE id = ?", ORGANIZATION_COLUMNS))
+        .bind(&[org_id.into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("Organization not found".into()))
+}
+
+pub async fn list_user_organizations(env: &Env, user_id: &str) -> Result<Vec<OrganizationSummary>, AppError> {
+    let db = get_db(env)?;
+
+    let rows = db
+        .prepare("SELECT o.id, o.name, o.credits_balance, o.created_by, o.created_at, o.updated_at, m.role FROM organizations o JOIN organization_members m ON m.org_id = o.id WHERE m.user_id = ? ORDER BY o.created_at DESC")
+        .bind(&[user_id.into()])?
+        .all()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .results::<MembershipRow>()
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| OrganizationSummary {
+            organization: Organization {
+                id: row.id,
+                name: row.name,
+                credits_balance: row.credits_balance,
+                created_by: row.created_by,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+            },
+            role: row.role,
+        })
+        .collect())
+}
+
+pub async fn get_membership(
+    env: &Env,
+    org_id: &str,
+    user_id: &str,
+) -> Result<Option<OrganizationMember>, AppError> {
+    let db = get_db(env)?;
+
+    db.prepare("SELECT org_id, user_id, role, monthly_credit_cap, joined_at FROM organization_members WHERE org_id = ? AND user_id = ?")
+        .bind(&[org_id.into(), user_id.into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))
+}
+
+pub async fn require_role(
+    env: &Env,
+    org_id: &str,
+    user_id: &str,
+    minimum: OrgRole,
+) -> Result<OrganizationMember, AppError> {
+    let member = get_membership(env, org_id, user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Organization not found".into()))?;
+
+    if member.role < minimum {
+        return Err(AppError::Forbidden(format!(
+            "This action requires the {} role",
+            minimum
+        )));
+    }
+
+    Ok(member)
+}
+
+pub async fn list_members(env: &Env, org_id: &str) -> Result<Vec<OrganizationMember>, AppError> {
+    let db = get_db(env)?;
+
+    db.prepare("SELECT org_id, user_id, role, monthly_credit_cap, joined_at FROM organization_members WHERE org_id = ? ORDER BY joined_at ASC")
+        .bind(&[org_id.into()])?
+        .all()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .results::<OrganizationMember>()
+        .map_err(|e| AppError::DatabaseError(e.to_string()))
+}
+
+pub async fn update_member(
+    env: &Env,
+    org_id: &str,
+    user_id: &str,
+    role: OrgRole,
+    monthly_credit_cap: Option<i64>,
+) -> Result<(), AppError> {
+    let db = get_db(env)?;
+
+    db.prepare("UPDATE organization_members SET role = ?, monthly_credit_cap = ? WHERE org_id = ? AND user_id = ?")
+        .bind(&[
+            role.to_string().into(),
+            monthly_credit_cap.map(|c| JsValue::from_f64(c as f64)).unwrap_or(JsValue::NULL),
+            org_id.into(),
+            user_id.into(),
+        ])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+pub async fn remove_member(env: &Env, org_id: &str, user_id: &str) -> Result<(), AppError> {
+    let db = get_db(env)?;
+
+    db.prepare("DELETE FROM organization_members WHERE org_id = ? AND user_id = ?")
+        .bind(&[org_id.into(), user_id.into()])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+pub async fn create_invitation(
+    env: &Env,
+    org_id: &str,
+    invited_by: &str,
+    email: Option<String>,
+    role: OrgRole,
+) -> Result<OrganizationInvitation, AppError> {
+    let db = get_db(env)?;
+    let now = now_datetime();
+
+    let invitation = OrganizationInvitation {
+        id: uuid::Uuid::new_v4().to_string(),
+        org_id: org_id.to_string(),
+        token: uuid::Uuid::new_v4().simple().to_string(),
+        email,
+        role,
+        invited_by: invited_by.to_string(),
+        created_at: now,
+        expires_at: now + chrono::Duration::days(INVITATION_TTL_DAYS),
+        accepted_at: None,
+        accepted_by: None,
+    };
+
+    db.prepare("INSERT INTO organization_invitations (id, org_id, token, email, role, invited_by, created_at, expires_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
+        .bind(&[
+            invitation.id.clone().into(),
+            invitation.org_id.clone().into(),
+            invitation.token.clone().into(),
+            invitation.email.as_deref().map(JsValue::from_str).unwrap_or(JsValue::NULL),
+            invitation.role.to_string().into(),
+            invitation.invited_by.clone().into(),
+            invitation.created_at.to_rfc3339().into(),
+            invitation.expires_at.to_rfc3339().into(),
+        ])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(invitation)
+}
+
+pub async fn accept_invitation(env: &Env, token: &str, user: &User) -> Result<OrganizationMember, AppError> {
+    let db = get_db(env)?;
+    let now = now_datetime();
+
+    let invitation: OrganizationInvitation = db
+        .prepare(format!("SELECT {} FROM organization_invitations WHERE token = ?", INVITATION_COLUMNS))
+        .bind(&[token.into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("Invitation not found".into()))?;
+
+    if invitation.accepted_at.is_some() {
+        return Err(AppError::BadRequest("Invitation has already been used".into()));
+    }
+
+    if invitation.expires_at < now {
+        return Err(AppError::BadRequest("Invitation has expired".into()));
+    }
+
+    if let Some(invited_email) = invitation.email.as_deref() {
+        let matches = user
+            .email
+            .as_deref()
+            .is_some_and(|email| email.eq_ignore_ascii_case(invited_email));
+
+        if !matches {
+            return Err(AppError::Forbidden("This invitation was sent to a different email address".into()));
+        }
+    }
+
+    if get_membership(env, &invitation.org_id, &user.id).await?.is_some() {
+        return Err(AppError::BadRequest("You are already a member of this organization".into()));
+    }
+
+    let statements = vec![
+        db.prepare("UPDATE organization_invitations SET accepted_at = ?, accepted_by = ? WHERE id = ? AND accepted_at IS NULL")
+            .bind(&[now.to_rfc3339().into(), user.id.clone().into(), invitation.id.clone().into()])?,
+        // Only joins if the update above claimed the invitation for this user.
+        db.prepare("INSERT INTO organization_members (org_id, user_id, role, joined_at) SELECT ?, ?, ?, ? WHERE EXISTS (SELECT 1 FROM organization_invitations WHERE id = ? AND accepted_by = ?)")
+            .bind(&[
+                invitation.org_id.clone().into(),
+                user.id.clone().into(),
+                invitation.role.to_string().into(),
+                now.to_rfc3339().into(),
+                invitation.id.clone().into(),
+                user.id.clone().into(),
+            ])?,
+    ];
+
+    let results = db
+        .batch(statements)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    let claimed = results
+        .first()
+        .and_then(|r| r.meta().ok().flatten())
+        .and_then(|meta| meta.changes)
+        .unwrap_or(0);
+
+    if claimed == 0 {
+        return Err(AppError::BadRequest("Invitation has already been used".into()));
+    }
+
+    Ok(OrganizationMember {
+        org_id: invitation.org_id,
+        user_id: user.id.clone(),
+        role: invitation.role,
+        monthly_credit_cap: None,
+        joined_at: now,
+    })
+}
+
+pub async fn get_member_monthly_spend(env: &Env, org_id: &str, user_id: &str) -> Result<i64, AppError> {
+    let db = get_db(env)?;
+    let now = now_datetime();
+    let month_start = Utc
+        .with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .single()
+        .unwrap_or(now);
+
+    let result: Option<SumResult> = db
+        .prepare("SELECT SUM(amount) as total FROM credit_transactions WHERE org_id = ? AND user_id = ? AND transaction_type IN ('video_generation', 'prompt_enhancement', 'refund') AND created_at >= ?")
+        .bind(&[org_id.into(), user_id.into(), month_start.to_rfc3339().into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(-result.and_then(|r| r.total).unwrap_or(0.0) as i64)
+}
+
+pub async fn list_org_videos(
+    env: &Env,
+    org_id: &str,
+    limit: i32,
+    offset: i32,
+) -> Result<(Vec<Video>, i64), AppError> {
+    let db = get_db(env)?;
+
+    let videos: Vec<Video> = db
+        .prepare(format!("SELECT {} FROM videos WHERE org_id = ? ORDER BY created_at DESC LIMIT ? OFFSET ?", db::VIDEO_COLUMNS))
+        .bind(&[org_id.into(), limit.into(), offset.into()])?
+        .all()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .results::<Video>()
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    let count_result: Option<CountResult> = db
+        .prepare("SELECT COUNT(*) as count FROM videos WHERE org_id = ?")
+        .bind(&[org_id.into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    let total = count_result.map(|r| r.count as i64).unwrap_or(0);
+
+    Ok((videos, total))
+}
+
+pub async fn get_org_transactions(
+    env: &Env,
+    org_id: &str,
+    limit: i32,
+) -> Result<Vec<CreditTransaction>, AppError> {
+    let db = get_db(env)?;
+
+    db.prepare(format!("SELECT {} FROM credit_transactions WHERE org_id = ? ORDER BY created_at DESC LIMIT ?", db::TRANSACTION_COLUMNS))
+        .bind(&[org_id.into(), limit.into()])?
+        .all()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .results::<CreditTransaction>()
+        .map_err(|e| AppError::DatabaseError(e.to_string()))
+}
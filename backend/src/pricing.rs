@@ -1,3 +1,4 @@
+use crate::currency::Currency;
 use crate::error::AppError;
 
 pub fn calculate_credits(model: &str, seconds: i32) -> Result<i64, AppError> {
@@ -19,6 +20,14 @@ pub fn calculate_credits(model: &str, seconds: i32) -> Result<i64, AppError> {
     Ok(cost)
 }
 
+/// A remix costs the same as generating a video of the parent's model and
+/// length.
+pub fn calculate_remix_credits(model: &str, seconds: i32) -> Result<i64, AppError> {
+    calculate_credits(model, seconds).map_err(|_| {
+        AppError::BadRequest(format!("Videos made with {} at {}s can't be remixed", model, seconds))
+    })
+}
+
 pub fn validate_video_params(model: &str, size: &str, seconds: i32) -> Result<(), AppError> {
     if !["sora-2", "sora-2-pro"].contains(&model) {
         return Err(AppError::BadRequest(format!("Invalid model: {}", model)));
@@ -44,6 +53,15 @@ pub fn credits_to_usd(credits: i64) -> String {
     format!("${:.2}", usd)
 }
 
+pub fn starter_pack_price_minor(currency: Currency) -> i64 {
+    match currency {
+        Currency::Usd => 999,
+        Currency::Eur => 999,
+        Currency::Gbp => 899,
+        Currency::Jpy => 1500,
+    }
+}
+
 pub const STARTER_PACK_CREDITS: i64 = 1000;
 pub const STARTER_PACK_PRICE_USD: f64 = 9.99;
 pub const WELCOME_CREDITS: i64 = 100;
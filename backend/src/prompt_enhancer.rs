@@ -0,0 +1,173 @@
+use crate::budgets;
+use crate::credits::{self, Charge};
+use crate::error::AppError;
+use crate::openai_client::OpenAIClient;
+use crate::prompt_templates;
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+use worker::{async_trait::async_trait, Env};
+
+const DEFAULT_ENHANCE_CREDITS: i64 = 2;
+
+/// Instructions for chat-completions enhancers. The reply must be a JSON
+/// object so `parse_enhancement` can read it.
+pub const ENHANCER_INSTRUCTIONS: &str = "You rewrite short video ideas into detailed prompts for a text-to-video model. \
+Keep the user's subject, action and intent. Add concrete cinematography: shot type and framing, camera movement, lens, \
+lighting, time of day, color palette, mood and pacing. Describe one continuous shot in plain prose, under 120 words, \
+without lists, dialogue markup or mentions of the model. \
+Reply with a JSON object: {\"prompt\": \"<enhanced prompt>\", \"changes\": [\"<one short line per addition or change>\"]}. \
+Each change starts with \"Added:\", \"Changed:\" or \"Kept:\".";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Enhancement {
+    pub prompt: String,
+    #[serde(default)]
+    pub changes: Vec<String>,
+}
+
+#[async_trait(?Send)]
+pub trait PromptEnhancer {
+    async fn enhance(&self, prompt: &str) -> Result<Enhancement, AppError>;
+}
+
+/// Picks the enhancer from `ENHANCER_PROVIDER` (`openai` or `mock`, default
+/// `openai`).
+pub fn from_env(env: &Env) -> Result<Rc<dyn PromptEnhancer>, AppError> {
+    let provider = env
+        .var("ENHANCER_PROVIDER")
+        .map(|v| v.to_string())
+        .unwrap_or_else(|_| "openai".to_string());
+
+    match provider.as_str() {
+        "openai" => Ok(Rc::new(OpenAIClient::enhancer_from_env(env))),
+        "mock" => Ok(Rc::new(MockPromptEnhancer)),
+        other => Err(AppError::InternalError(format!("Unknown ENHANCER_PROVIDER: {}", other))),
+    }
+}
+
+pub fn enhance_credits(env: &Env) -> i64 {
+    env.var("ENHANCE_PROMPT_CREDITS")
+        .map(|v| v.to_string().parse().unwrap_or(DEFAULT_ENHANCE_CREDITS))
+        .unwrap_or(DEFAULT_ENHANCE_CREDITS)
+        .max(0)
+}
+
+/// Runs `enhancer` and checks the result still fits the prompt length limit.
+/// Charging is up to the caller: video creation adds the fee to the video's
+/// own charge so both are taken, budgeted and refunded together.
+pub async fn enhance(env: &Env, enhancer: &dyn PromptEnhancer, prompt: &str) -> Result<Enhancement, AppError> {
+    let enhancement = enhancer.enhance(prompt).await?;
+
+    let max_chars = prompt_templates::max_prompt_chars(env);
+    if enhancement.prompt.chars().count() > max_chars {
+        return Err(AppError::ExternalApiError(format!(
+            "Enhanced prompt is longer than {} characters",
+            max_chars
+        )));
+    }
+
+    Ok(enhancement)
+}
+
+/// Enhances `prompt` on its own and charges the enhancement fee to the user,
+/// or to the organization when `org_id` is set. The balance must cover the
+/// fee before the enhancer is called, and the fee is only charged once it
+/// succeeds. Returns the enhancement and the balance after the fee.
+pub async fn enhance_and_charge(
+    env: &Env,
+    enhancer: &dyn PromptEnhancer,
+    user_id: &str,
+    org_id: Option<&str>,
+    prompt: &str,
+) -> Result<(Enhancement, i64), AppError> {
+    let fee = enhance_credits(env);
+    credits::ensure_balance(env, user_id, org_id, fee).await?;
+    if org_id.is_none() {
+        budgets::check_budget(env, user_id, fee).await?;
+    }
+
+    let enhancement = enhance(env, enhancer, prompt).await?;
+
+    let charges: Vec<Charge> = (fee > 0).then(|| Charge::prompt_enhancement(None, fee)).into_iter().collect();
+    let new_balance = credits::reserve_credits(env, user_id, org_id, &charges).await?;
+
+    Ok((enhancement, new_balance))
+}
+
+/// Reads a chat model's reply, tolerating a Markdown code fence around the
+/// JSON.
+pub fn parse_enhancement(content: &str) -> Result<Enhancement, AppError> {
+    let json = content
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+
+    let mut enhancement: Enhancement = serde_json::from_str(json).map_err(|e| {
+        AppError::ExternalApiError(format!("Prompt enhancer returned an unexpected reply: {}", e))
+    })?;
+
+    enhancement.prompt = enhancement.prompt.trim().to_string();
+    enhancement.changes.retain(|change| !change.trim().is_empty());
+
+    if enhancement.prompt.is_empty() {
+        return Err(AppError::ExternalApiError("Prompt enhancer returned an empty prompt".into()));
+    }
+
+    Ok(enhancement)
+}
+
+/// Appends fixed cinematography so the worker can run offline.
+pub struct MockPromptEnhancer;
+
+const MOCK_ADDITIONS: [&str; 3] = [
+    "slow dolly-in on a 35mm lens",
+    "soft golden-hour backlight",
+    "shallow depth of field with gentle film grain",
+];
+
+#[async_trait(?Send)]
+impl PromptEnhancer for MockPromptEnhancer {
+    async fn enhance(&self, prompt: &str) -> Result<Enhancement, AppError> {
+        let base = prompt.trim().trim_end_matches('.');
+
+        Ok(Enhancement {
+            prompt: format!("{}. {}.", base, MOCK_ADDITIONS.join(", ")),
+            changes: std::iter::once(format!("Kept: {}", base))
+                .chain(MOCK_ADDITIONS.iter().map(|addition| format!("Added: {}", addition)))
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::FutureExt;
+
+    #[test]
+    fn parses_fenced_and_bare_replies() {
+        let bare = parse_enhancement(r#"{"prompt": " A fox at dawn ", "changes": ["Added: dawn", " "]}"#).unwrap();
+        assert_eq!(bare.prompt, "A fox at dawn");
+        assert_eq!(bare.changes, vec!["Added: dawn"]);
+
+        let fenced = parse_enhancement("```json\n{\"prompt\": \"A fox\"}\n```").unwrap();
+        assert_eq!(fenced.prompt, "A fox");
+        assert!(fenced.changes.is_empty());
+    }
+
+    #[test]
+    fn rejects_unusable_replies() {
+        assert!(parse_enhancement("Sure! Here is your prompt").is_err());
+        assert!(parse_enhancement(r#"{"prompt": "  "}"#).is_err());
+    }
+
+    #[test]
+    fn mock_enhancer_keeps_the_prompt() {
+        let enhancement = MockPromptEnhancer.enhance("a red fox.").now_or_never().unwrap().unwrap();
+        assert!(enhancement.prompt.starts_with("a red fox. slow dolly-in"));
+        assert_eq!(enhancement.changes[0], "Kept: a red fox");
+        assert_eq!(enhancement.changes.len(), 4);
+    }
+}
@@ -0,0 +1,314 @@
+use crate::db::get_db;
+use crate::error::AppError;
+use crate::models::{CreateVideoRequest, PromptTemplate};
+use std::collections::BTreeMap;
+use worker::{wasm_bindgen::JsValue, Env};
+
+const DEFAULT_MAX_PROMPT_CHARS: usize = 2000;
+const MAX_NAME_CHARS: usize = 100;
+const MAX_DESCRIPTION_CHARS: usize = 500;
+
+/// The variable a template uses for the caller's own `prompt`, so style
+/// presets can wrap it.
+const PROMPT_VARIABLE: &str = "prompt";
+
+const TEMPLATE_COLUMNS: &str = "id, user_id, name, description, body, created_at, updated_at";
+
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits a template body into text and `{{name}}` placeholders. Whitespace
+/// inside the braces is ignored.
+fn parse(body: &str) -> Result<Vec<Segment<'_>>, AppError> {
+    let mut segments = Vec::new();
+    let mut rest = body;
+
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            segments.push(Segment::Text(&rest[..start]));
+        }
+
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| AppError::BadRequest("Template has an unclosed {{ placeholder".into()))?;
+
+        let name = after[..end].trim();
+        if !is_valid_name(name) {
+            return Err(AppError::BadRequest(format!(
+                "Invalid placeholder {{{{{}}}}}. Names use letters, digits and underscores",
+                &after[..end]
+            )));
+        }
+
+        segments.push(Segment::Placeholder(name));
+        rest = &after[end + 2..];
+    }
+
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+
+    Ok(segments)
+}
+
+/// The template's placeholder names, in order of first use.
+pub fn placeholders(body: &str) -> Result<Vec<String>, AppError> {
+    let mut names: Vec<String> = Vec::new();
+
+    for segment in parse(body)? {
+        if let Segment::Placeholder(name) = segment {
+            if !names.iter().any(|n| n == name) {
+                names.push(name.to_string());
+            }
+        }
+    }
+
+    Ok(names)
+}
+
+pub fn render(body: &str, variables: &BTreeMap<String, String>) -> Result<String, AppError> {
+    let segments = parse(body)?;
+    let names = placeholders(body)?;
+
+    let missing: Vec<&str> = names
+        .iter()
+        .filter(|name| !variables.contains_key(name.as_str()))
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        return Err(AppError::BadRequest(format!("Missing template variables: {}", missing.join(", "))));
+    }
+
+    let unknown: Vec<&str> = variables
+        .keys()
+        .filter(|key| !names.contains(key))
+        .map(String::as_str)
+        .collect();
+    if !unknown.is_empty() {
+        return Err(AppError::BadRequest(format!("Unknown template variables: {}", unknown.join(", "))));
+    }
+
+    Ok(segments
+        .into_iter()
+        .map(|segment| match segment {
+            Segment::Text(text) => text,
+            Segment::Placeholder(name) => variables[name].as_str(),
+        })
+        .collect())
+}
+
+pub fn max_prompt_chars(env: &Env) -> usize {
+    env.var("MAX_PROMPT_CHARS")
+        .map(|v| v.to_string().parse().unwrap_or(DEFAULT_MAX_PROMPT_CHARS))
+        .unwrap_or(DEFAULT_MAX_PROMPT_CHARS)
+}
+
+fn check_length(what: &str, text: &str, max: usize) -> Result<(), AppError> {
+    let length = text.chars().count();
+
+    if length > max {
+        return Err(AppError::BadRequest(format!(
+            "{} is {} characters; the limit is {}",
+            what, length, max
+        )));
+    }
+
+    Ok(())
+}
+
+pub fn validate_template(env: &Env, name: &str, description: Option<&str>, body: &str) -> Result<(), AppError> {
+    if name.trim().is_empty() {
+        return Err(AppError::BadRequest("Template name can't be empty".into()));
+    }
+    if body.trim().is_empty() {
+        return Err(AppError::BadRequest("Template body can't be empty".into()));
+    }
+
+    check_length("Template name", name, MAX_NAME_CHARS)?;
+    check_length("Template description", description.unwrap_or_default(), MAX_DESCRIPTION_CHARS)?;
+    check_length("Template body", body, max_prompt_chars(env))?;
+    placeholders(body)?;
+
+    Ok(())
+}
+
+fn with_variables(mut template: PromptTemplate) -> PromptTemplate {
+    template.variables = placeholders(&template.body).unwrap_or_default();
+    template
+}
+
+/// Global templates plus the user's own, globals first.
+pub async fn list_templates(env: &Env, user_id: &str) -> Result<Vec<PromptTemplate>, AppError> {
+    let db = get_db(env)?;
+
+    let templates: Vec<PromptTemplate> = db
+        .prepare(format!("SELECT {} FROM prompt_templates WHERE user_id IS NULL OR user_id = ? ORDER BY user_id IS NOT NULL, name COLLATE NOCASE", TEMPLATE_COLUMNS))
+        .bind(&[user_id.into()])?
+        .all()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .results()
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(templates.into_iter().map(with_variables).collect())
+}
+
+/// A template the user can use: a global one or their own.
+pub async fn get_template(env: &Env, template_id: &str, user_id: &str) -> Result<PromptTemplate, AppError> {
+    let db = get_db(env)?;
+
+    let template: Option<PromptTemplate> = db
+        .prepare(format!("SELECT {} FROM prompt_templates WHERE id = ? AND (user_id IS NULL OR user_id = ?)", TEMPLATE_COLUMNS))
+        .bind(&[template_id.into(), user_id.into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    template
+        .map(with_variables)
+        .ok_or_else(|| AppError::NotFound("Template not found".into()))
+}
+
+pub async fn save_template(env: &Env, template: &PromptTemplate) -> Result<(), AppError> {
+    let db = get_db(env)?;
+
+    db.prepare("INSERT INTO prompt_templates (id, user_id, name, description, body, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?) ON CONFLICT(id) DO UPDATE SET name = excluded.name, description = excluded.description, body = excluded.body, updated_at = excluded.updated_at")
+        .bind(&[
+            template.id.clone().into(),
+            template.user_id.as_deref().map(JsValue::from_str).unwrap_or(JsValue::NULL),
+            template.name.clone().into(),
+            template.description.as_deref().map(JsValue::from_str).unwrap_or(JsValue::NULL),
+            template.body.clone().into(),
+            template.created_at.to_rfc3339().into(),
+            template.updated_at.to_rfc3339().into(),
+        ])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+pub async fn delete_template(env: &Env, template_id: &str) -> Result<(), AppError> {
+    let db = get_db(env)?;
+
+    db.prepare("DELETE FROM prompt_templates WHERE id = ?")
+        .bind(&[template_id.into()])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+pub struct ResolvedPrompt {
+    pub prompt: String,
+    pub template_id: Option<String>,
+    pub variables: Option<BTreeMap<String, String>>,
+}
+
+/// Produces the final prompt for a create request: either its `prompt` as
+/// given, or its template rendered with `variables`. A template that uses
+/// `{{prompt}}` receives the request's `prompt` under that name.
+pub async fn resolve_prompt(env: &Env, user_id: &str, request: &CreateVideoRequest) -> Result<ResolvedPrompt, AppError> {
+    let max_chars = max_prompt_chars(env);
+
+    let Some(template_id) = request.template_id.as_deref() else {
+        if request.prompt.trim().is_empty() {
+            return Err(AppError::BadRequest("Prompt can't be empty".into()));
+        }
+        if !request.variables.is_empty() {
+            return Err(AppError::BadRequest("variables require a template_id".into()));
+        }
+        check_length("Prompt", &request.prompt, max_chars)?;
+
+        return Ok(ResolvedPrompt {
+            prompt: request.prompt.clone(),
+            template_id: None,
+            variables: None,
+        });
+    };
+
+    let template = get_template(env, template_id, user_id).await?;
+    let mut variables = request.variables.clone();
+
+    if !request.prompt.trim().is_empty() {
+        if !template.variables.iter().any(|name| name == PROMPT_VARIABLE) {
+            return Err(AppError::BadRequest(format!(
+                "Template \"{}\" doesn't use {{{{{}}}}}; pass its variables instead of a prompt",
+                template.name, PROMPT_VARIABLE
+            )));
+        }
+        variables
+            .entry(PROMPT_VARIABLE.to_string())
+            .or_insert_with(|| request.prompt.clone());
+    }
+
+    let prompt = render(&template.body, &variables)?;
+    if prompt.trim().is_empty() {
+        return Err(AppError::BadRequest("Rendered prompt is empty".into()));
+    }
+    check_length("Rendered prompt", &prompt, max_chars)?;
+
+    Ok(ResolvedPrompt {
+        prompt,
+        template_id: Some(template.id),
+        variables: Some(variables),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn renders_placeholders() {
+        let body = "{{ subject }} at {{time}}, {{subject}} in focus";
+        assert_eq!(placeholders(body).unwrap(), vec!["subject", "time"]);
+        assert_eq!(
+            render(body, &vars(&[("subject", "a fox"), ("time", "dusk")])).unwrap(),
+            "a fox at dusk, a fox in focus"
+        );
+    }
+
+    #[test]
+    fn values_are_not_rendered_again() {
+        assert_eq!(render("{{a}}!", &vars(&[("a", "{{b}}")])).unwrap(), "{{b}}!");
+        assert_eq!(render("no placeholders { here }", &vars(&[])).unwrap(), "no placeholders { here }");
+    }
+
+    #[test]
+    fn reports_missing_and_unknown_variables() {
+        let missing = render("{{a}} {{b}}", &vars(&[("a", "x")])).unwrap_err();
+        assert!(missing.to_string().contains("Missing template variables: b"));
+
+        let unknown = render("{{a}}", &vars(&[("a", "x"), ("z", "y")])).unwrap_err();
+        assert!(unknown.to_string().contains("Unknown template variables: z"));
+    }
+
+    #[test]
+    fn rejects_malformed_placeholders() {
+        assert!(parse("{{unclosed").is_err());
+        assert!(parse("{{}}").is_err());
+        assert!(parse("{{two words}}").is_err());
+        assert!(parse("{{9lives}}").is_err());
+        assert_eq!(
+            parse("a{{b}}").unwrap(),
+            vec![Segment::Text("a"), Segment::Placeholder("b")]
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use crate::error::AppError;
-use worker::Env;
+use worker::{wasm_bindgen::JsValue, Env};
 use chrono::DateTime;
 use serde::Deserialize;
 
@@ -19,6 +19,11 @@ struct CountResult {
 }
 
 pub async fn check_rate_limit(env: &Env, user_id: &str) -> Result<(), AppError> {
+    check_video_quota(env, user_id, 1).await
+}
+
+/// Checks that `requested` more videos fit in today's limit.
+pub async fn check_video_quota(env: &Env, user_id: &str, requested: i64) -> Result<(), AppError> {
     let max_per_day = env
         .var("MAX_VIDEOS_PER_DAY")
         .map(|v| v.to_string().parse::<i64>().unwrap_or(20))
@@ -46,6 +51,82 @@ pub async fn check_rate_limit(env: &Env, user_id: &str) -> Result<(), AppError>
         )));
     }
 
+    if count + requested > max_per_day {
+        return Err(AppError::RateLimitExceeded(format!(
+            "{} videos would exceed your daily limit of {} ({} remaining today). Limit resets at midnight UTC.",
+            requested,
+            max_per_day,
+            max_per_day - count
+        )));
+    }
+
+    Ok(())
+}
+
+/// Daily gifting limits from `MAX_GIFTS_PER_DAY` and `MAX_GIFT_CREDITS_PER_DAY`.
+pub struct GiftLimits {
+    pub max_gifts: i64,
+    pub max_credits: i64,
+}
+
+impl GiftLimits {
+    /// Holds while one more gift stays within today's limits. Placeholders
+    /// match `bindings`, so it can guard the gift's ledger insert.
+    pub const CONDITION_SQL: &'static str = "(SELECT COUNT(*) FROM credit_transactions WHERE user_id = ? AND transaction_type = 'gift_sent' AND created_at >= ? AND org_id IS NULL) < ? \
+AND (SELECT COALESCE(-SUM(amount), 0) FROM credit_transactions WHERE user_id = ? AND transaction_type = 'gift_sent' AND created_at >= ? AND org_id IS NULL) + ? <= ?";
+
+    pub fn from_env(env: &Env) -> Self {
+        let max_gifts = env
+            .var("MAX_GIFTS_PER_DAY")
+            .map(|v| v.to_string().parse::<i64>().unwrap_or(5))
+            .unwrap_or(5);
+
+        let max_credits = env
+            .var("MAX_GIFT_CREDITS_PER_DAY")
+            .map(|v| v.to_string().parse::<i64>().unwrap_or(1000))
+            .unwrap_or(1000);
+
+        Self { max_gifts, max_credits }
+    }
+
+    pub fn bindings(&self, user_id: &str, amount: i64) -> Vec<JsValue> {
+        let today = today_date();
+
+        vec![
+            user_id.into(),
+            today.clone().into(),
+            JsValue::from_f64(self.max_gifts as f64),
+            user_id.into(),
+            today.into(),
+            JsValue::from_f64(amount as f64),
+            JsValue::from_f64(self.max_credits as f64),
+        ]
+    }
+}
+
+pub async fn check_gift_limit(env: &Env, user_id: &str, amount: i64) -> Result<(), AppError> {
+    let limits = GiftLimits::from_env(env);
+
+    let today = today_date();
+    let (gifts_today, total_today) =
+        crate::db::sum_transaction_amounts(env, user_id, "gift_sent", Some(&today)).await?;
+    let credits_today = -total_today;
+
+    if gifts_today >= limits.max_gifts {
+        return Err(AppError::RateLimitExceeded(format!(
+            "Daily limit of {} gifts reached. Limit resets at midnight UTC.",
+            limits.max_gifts
+        )));
+    }
+
+    if credits_today + amount > limits.max_credits {
+        return Err(AppError::RateLimitExceeded(format!(
+            "Daily gifting limit of {} credits reached ({} remaining today). Limit resets at midnight UTC.",
+            limits.max_credits,
+            (limits.max_credits - credits_today).max(0)
+        )));
+    }
+
     Ok(())
 }
 
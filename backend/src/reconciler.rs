@@ -0,0 +1,386 @@
+use crate::db::{get_db, now_datetime, VIDEO_COLUMNS};
+use crate::error::AppError;
+use crate::generation_queue::{self, QueueLimits};
+use crate::media_storage;
+use crate::models::Video;
+use crate::video_lifecycle::{self, Event};
+use crate::video_provider::{self, VideoProvider};
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use worker::{console_log, Env};
+
+const DEFAULT_STALE_MINUTES: i64 = 10;
+const DEFAULT_TIMEOUT_MINUTES: i64 = 60;
+const DEFAULT_BATCH_SIZE: i64 = 50;
+
+/// How long OpenAI keeps a completed video's assets.
+const OPENAI_ASSET_HOURS: i64 = 24;
+
+#[derive(Deserialize)]
+struct UserRow {
+    user_id: String,
+}
+
+pub struct ReconcileConfig {
+    /// Jobs running this long are re-polled, and unsubmitted claims this old
+    /// are treated as orphaned.
+    pub stale_minutes: i64,
+    /// Jobs still running this long after leaving the queue are failed and
+    /// refunded.
+    pub timeout_minutes: i64,
+    /// Caps the jobs and queues handled per run, to stay within the
+    /// subrequest limit.
+    pub batch_size: i64,
+}
+
+impl ReconcileConfig {
+    pub fn from_env(env: &Env) -> Self {
+        let read = |name: &str, default: i64| {
+            env.var(name)
+                .map(|v| v.to_string().parse::<i64>().unwrap_or(default))
+                .unwrap_or(default)
+                .max(1)
+        };
+
+        let stale_minutes = read("RECONCILE_STALE_MINUTES", DEFAULT_STALE_MINUTES);
+
+        Self {
+            stale_minutes,
+            timeout_minutes: read("GENERATION_TIMEOUT_MINUTES", DEFAULT_TIMEOUT_MINUTES).max(stale_minutes),
+            batch_size: read("RECONCILE_BATCH_SIZE", DEFAULT_BATCH_SIZE),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    /// Ask the provider where the job is.
+    Poll { timed_out: bool },
+    /// Claimed from the queue but never submitted, so the worker died
+    /// mid-submission.
+    Orphaned,
+}
+
+fn action_for(video: &Video, now: DateTime<Utc>, config: &ReconcileConfig) -> Action {
+    if !video.is_submitted() {
+        return Action::Orphaned;
+    }
+
+    let started_at = video.started_at.unwrap_or(video.created_at);
+
+    Action::Poll {
+        timed_out: now - started_at >= Duration::minutes(config.timeout_minutes),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReconcileSummary {
+    pub checked: usize,
+    pub progressed: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub timed_out: usize,
+    pub orphaned: usize,
+    pub persisted: usize,
+    pub queues_advanced: usize,
+    pub locks_expired: u64,
+    pub errors: usize,
+}
+
+/// Jobs that left the queue at least `stale_minutes` ago and haven't
+/// settled, oldest first.
+async fn list_stale_videos(env: &Env, now: DateTime<Utc>, config: &ReconcileConfig) -> Result<Vec<Video>, AppError> {
+    let db = get_db(env)?;
+    let cutoff = (now - Duration::minutes(config.stale_minutes)).to_rfc3339();
+
+    db.prepare(format!(
+        "SELECT {} FROM videos WHERE status IN ('queued', 'in_progress') AND COALESCE(started_at, created_at) < ? ORDER BY COALESCE(started_at, created_at) ASC LIMIT ?",
+        VIDEO_COLUMNS
+    ))
+    .bind(&[cutoff.into(), (config.batch_size as f64).into()])?
+    .all()
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?
+    .results::<Video>()
+    .map_err(|e| AppError::DatabaseError(e.to_string()))
+}
+
+/// Completed videos whose copy to R2 failed, while OpenAI still serves
+/// their assets.
+async fn list_unpersisted_videos(env: &Env, now: DateTime<Utc>, config: &ReconcileConfig) -> Result<Vec<Video>, AppError> {
+    let db = get_db(env)?;
+    let cutoff = (now - Duration::hours(OPENAI_ASSET_HOURS)).to_rfc3339();
+
+    db.prepare(format!(
+        "SELECT {} FROM videos WHERE status = 'completed' AND storage_key IS NULL AND completed_at > ? ORDER BY completed_at ASC LIMIT ?",
+        VIDEO_COLUMNS
+    ))
+    .bind(&[cutoff.into(), (config.batch_size as f64).into()])?
+    .all()
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))?
+    .results::<Video>()
+    .map_err(|e| AppError::DatabaseError(e.to_string()))
+}
+
+/// Users with queued jobs and a free active slot, whose queue stalled
+/// because the request that should have advanced it never ran.
+async fn list_stalled_queues(env: &Env, config: &ReconcileConfig) -> Result<Vec<String>, AppError> {
+    let db = get_db(env)?;
+    let limits = QueueLimits::from_env(env);
+
+    let rows: Vec<UserRow> = db
+        .prepare("SELECT DISTINCT user_id FROM videos v WHERE status = 'pending_submission' AND (SELECT COUNT(*) FROM videos a WHERE a.user_id = v.user_id AND a.status IN ('queued', 'in_progress')) < ? LIMIT ?")
+        .bind(&[(limits.max_active as f64).into(), (config.batch_size as f64).into()])?
+        .all()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?
+        .results()
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(rows.into_iter().map(|row| row.user_id).collect())
+}
+
+/// Clears `user_locks` rows older than `stale_minutes`. The generation queue
+/// doesn't take these locks, so this only removes rows left by deployments
+/// that predate it.
+async fn expire_user_locks(env: &Env, config: &ReconcileConfig) -> Result<u64, AppError> {
+    let db = get_db(env)?;
+
+    let result = db
+        .prepare("DELETE FROM user_locks WHERE locked_at < datetime('now', ?)")
+        .bind(&[format!("-{} minutes", config.stale_minutes).into()])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) as u64)
+}
+
+async fn time_out(env: &Env, provider: &dyn VideoProvider, video: &Video, summary: &mut ReconcileSummary) -> Result<(), AppError> {
+    let event = Event::Failed {
+        error_message: "Video generation timed out".to_string(),
+        refund_description: "Video generation timed out - credits refunded",
+    };
+
+    if !video_lifecycle::apply(env, video, event).await?.is_applied() {
+        return Ok(());
+    }
+
+    summary.timed_out += 1;
+
+    if let Err(e) = provider.delete_video(&video.openai_video_id).await {
+        console_log!("Failed to delete timed-out video {}: {:?}", video.openai_video_id, e);
+    }
+
+    Ok(())
+}
+
+async fn reconcile_video(
+    env: &Env,
+    provider: &dyn VideoProvider,
+    video: &Video,
+    action: Action,
+    summary: &mut ReconcileSummary,
+) -> Result<(), AppError> {
+    let timed_out = match action {
+        Action::Orphaned => {
+            let event = Event::Failed {
+                error_message: "Video submission was interrupted".to_string(),
+                refund_description: "Video submission interrupted - credits refunded",
+            };
+
+            if video_lifecycle::apply(env, video, event).await?.is_applied() {
+                summary.orphaned += 1;
+            }
+            return Ok(());
+        }
+        Action::Poll { timed_out } => timed_out,
+    };
+
+    let response = match provider.retrieve_video(&video.openai_video_id).await {
+        Ok(response) => response,
+        Err(e) if timed_out => {
+            console_log!("Failed to poll timed-out video {}: {:?}", video.openai_video_id, e);
+            return time_out(env, provider, video, summary).await;
+        }
+        Err(e) => return Err(e),
+    };
+
+    match Event::from_response(&response) {
+        Some(Event::Progress { .. }) | None if timed_out => time_out(env, provider, video, summary).await?,
+        Some(event) => {
+            let counter = match event {
+                Event::Completed => &mut summary.completed,
+                Event::Failed { .. } => &mut summary.failed,
+                _ => &mut summary.progressed,
+            };
+
+            if video_lifecycle::apply(env, video, event).await?.is_applied() {
+                *counter += 1;
+            }
+        }
+        None => console_log!("Video {} has unknown status {}", video.id, response.status),
+    }
+
+    Ok(())
+}
+
+/// One reconciliation pass, run by the cron trigger. It settles jobs whose
+/// webhook was lost and that no client is polling, fails and refunds jobs
+/// past the hard timeout or orphaned mid-submission, retries failed copies
+/// to R2, restarts stalled queues and clears stale locks. Each step carries on past errors so one
+/// bad row can't stall the rest.
+pub async fn run(env: &Env) -> ReconcileSummary {
+    let mut summary = ReconcileSummary::default();
+    let config = ReconcileConfig::from_env(env);
+    let now = now_datetime();
+
+    let provider = match video_provider::from_env(env) {
+        Ok(provider) => provider,
+        Err(e) => {
+            console_log!("Reconciler can't build the video provider: {:?}", e);
+            summary.errors += 1;
+            return summary;
+        }
+    };
+
+    let mut touched_users: Vec<String> = Vec::new();
+
+    match list_stale_videos(env, now, &config).await {
+        Ok(videos) => {
+            for video in videos {
+                summary.checked += 1;
+                let action = action_for(&video, now, &config);
+
+                if let Err(e) = reconcile_video(env, provider.as_ref(), &video, action, &mut summary).await {
+                    console_log!("Failed to reconcile video {}: {:?}", video.id, e);
+                    summary.errors += 1;
+                }
+
+                if !touched_users.contains(&video.user_id) {
+                    touched_users.push(video.user_id);
+                }
+            }
+        }
+        Err(e) => {
+            console_log!("Failed to list stale videos: {:?}", e);
+            summary.errors += 1;
+        }
+    }
+
+    match list_unpersisted_videos(env, now, &config).await {
+        Ok(videos) => {
+            for video in videos {
+                match media_storage::persist_assets(env, provider.as_ref(), &video).await {
+                    Ok(_) => summary.persisted += 1,
+                    Err(e) => {
+                        console_log!("Failed to copy video {} to R2: {:?}", video.id, e);
+                        summary.errors += 1;
+                    }
+                }
+            }
+        }
+        Err(e) => {
+            console_log!("Failed to list unpersisted videos: {:?}", e);
+            summary.errors += 1;
+        }
+    }
+
+    match list_stalled_queues(env, &config).await {
+        Ok(users) => {
+            for user_id in users {
+                if !touched_users.contains(&user_id) {
+                    touched_users.push(user_id);
+                }
+            }
+        }
+        Err(e) => {
+            console_log!("Failed to list stalled queues: {:?}", e);
+            summary.errors += 1;
+        }
+    }
+
+    for user_id in &touched_users {
+        generation_queue::advance(env, provider.as_ref(), user_id).await;
+    }
+    summary.queues_advanced = touched_users.len();
+
+    match expire_user_locks(env, &config).await {
+        Ok(expired) => summary.locks_expired = expired,
+        Err(e) => {
+            console_log!("Failed to expire user locks: {:?}", e);
+            summary.errors += 1;
+        }
+    }
+
+    console_log!(
+        "Reconciler: checked {}, progressed {}, completed {}, failed {}, timed out {}, orphaned {}, persisted {}, queues advanced {}, locks expired {}, errors {}",
+        summary.checked,
+        summary.progressed,
+        summary.completed,
+        summary.failed,
+        summary.timed_out,
+        summary.orphaned,
+        summary.persisted,
+        summary.queues_advanced,
+        summary.locks_expired,
+        summary.errors
+    );
+
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ReconcileConfig {
+        ReconcileConfig {
+            stale_minutes: 10,
+            timeout_minutes: 60,
+            batch_size: 50,
+        }
+    }
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + minutes * 60, 0).unwrap()
+    }
+
+    fn video(started_at: i64, submitted: bool) -> Video {
+        let mut video = Video::new_pending(
+            "user".into(),
+            "sora-2".into(),
+            "a lighthouse".into(),
+            "1280x720".into(),
+            8,
+            100,
+            at(0),
+        );
+        video.started_at = Some(at(started_at));
+        if submitted {
+            video.openai_video_id = "video_1".into();
+        }
+        video
+    }
+
+    #[test]
+    fn times_out_from_start_not_creation() {
+        let queued_for_an_hour = video(55, true);
+        assert_eq!(action_for(&queued_for_an_hour, at(70), &config()), Action::Poll { timed_out: false });
+        assert_eq!(action_for(&queued_for_an_hour, at(115), &config()), Action::Poll { timed_out: true });
+    }
+
+    #[test]
+    fn falls_back_to_creation_time() {
+        let mut legacy = video(0, true);
+        legacy.started_at = None;
+        assert_eq!(action_for(&legacy, at(30), &config()), Action::Poll { timed_out: false });
+        assert_eq!(action_for(&legacy, at(60), &config()), Action::Poll { timed_out: true });
+    }
+
+    #[test]
+    fn unsubmitted_claims_are_orphaned() {
+        assert_eq!(action_for(&video(5, false), at(15), &config()), Action::Orphaned);
+    }
+}
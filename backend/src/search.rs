@@ -0,0 +1,161 @@
+use crate::db::{get_db, VIDEO_COLUMNS};
+use crate::error::AppError;
+use crate::library;
+use crate::models::{Video, VideoSearchResult};
+use crate::shares::escape_html;
+use serde::Deserialize;
+use worker::{wasm_bindgen::JsValue, Env};
+
+const MAX_QUERY_CHARS: usize = 200;
+const MAX_TERMS: usize = 8;
+const SNIPPET_TOKENS: u32 = 12;
+
+// snippet() wraps matches in these control characters so the rest of the
+// excerpt can be HTML-escaped before they become <mark> tags.
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+
+/// Turns free text into an FTS5 `MATCH` expression. Every word becomes a
+/// quoted prefix term, so `red fo` finds "red fox", and user input can never
+/// reach FTS5 as operators, column filters or syntax errors.
+pub fn build_match_query(q: &str) -> Result<String, AppError> {
+    let q = q.trim();
+    if q.chars().count() > MAX_QUERY_CHARS {
+        return Err(AppError::BadRequest(format!(
+            "q must be at most {} characters",
+            MAX_QUERY_CHARS
+        )));
+    }
+
+    // Matches the unicode61 tokenizer, which also splits on anything that
+    // is not a letter or digit.
+    let terms: Vec<&str> = q
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .collect();
+
+    if terms.is_empty() {
+        return Err(AppError::BadRequest("q must contain at least one word".into()));
+    }
+    if terms.len() > MAX_TERMS {
+        return Err(AppError::BadRequest(format!(
+            "q must have at most {} words",
+            MAX_TERMS
+        )));
+    }
+
+    Ok(terms
+        .iter()
+        .map(|term| format!("\"{}\"*", term))
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+/// Escapes a raw `snippet()` excerpt and turns its match markers into
+/// `<mark>` tags.
+pub fn render_snippet(raw: &str) -> String {
+    escape_html(raw)
+        .replace(MATCH_START, "<mark>")
+        .replace(MATCH_END, "</mark>")
+}
+
+#[derive(Deserialize)]
+struct SearchHit {
+    snippet: Option<String>,
+    match_rank: f64,
+}
+
+/// The caller's videos matching `match_query`, best match first, with their
+/// tags attached, and whether another page follows.
+pub async fn search_videos(
+    env: &Env,
+    user_id: &str,
+    match_query: &str,
+    limit: i32,
+    offset: i32,
+) -> Result<(Vec<VideoSearchResult>, bool), AppError> {
+    let db = get_db(env)?;
+
+    // Tags are labels the user chose, so they outweigh the prompt; the
+    // pre-enhancement prompt mostly repeats it and counts for less.
+    let sql = format!(
+        "SELECT {}, hits.snippet, hits.match_rank FROM videos \
+         JOIN (SELECT rowid AS hit_rowid, \
+                      snippet(videos_fts, -1, char(2), char(3), '…', {}) AS snippet, \
+                      bm25(videos_fts, 1.0, 0.5, 2.0) AS match_rank \
+               FROM videos_fts WHERE videos_fts MATCH ?) hits \
+           ON videos.rowid = hits.hit_rowid \
+         WHERE videos.user_id = ? \
+         ORDER BY hits.match_rank, videos.created_at DESC LIMIT ? OFFSET ?",
+        VIDEO_COLUMNS, SNIPPET_TOKENS
+    );
+
+    let bindings: Vec<JsValue> = vec![
+        match_query.into(),
+        user_id.into(),
+        (limit + 1).into(),
+        offset.into(),
+    ];
+
+    let result = db
+        .prepare(sql)
+        .bind(&bindings)?
+        .all()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    let mut videos = result
+        .results::<Video>()
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    let hits = result
+        .results::<SearchHit>()
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    let has_more = videos.len() > limit as usize;
+    videos.truncate(limit as usize);
+    library::attach_tags(env, &mut videos).await?;
+
+    let results = videos
+        .into_iter()
+        .zip(hits)
+        .map(|(video, hit)| VideoSearchResult {
+            video,
+            snippet: render_snippet(hit.snippet.as_deref().unwrap_or_default()),
+            rank: hit.match_rank,
+        })
+        .collect();
+
+    Ok((results, has_more))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_quoted_prefix_terms() {
+        assert_eq!(build_match_query("red fo").unwrap(), "\"red\"* \"fo\"*");
+        assert_eq!(build_match_query("  Café, dusk!  ").unwrap(), "\"Café\"* \"dusk\"*");
+        // Operators and syntax are searched as plain words or dropped.
+        assert_eq!(
+            build_match_query("fox OR NOT prompt:\"cat\" NEAR(a b)*").unwrap(),
+            "\"fox\"* \"OR\"* \"NOT\"* \"prompt\"* \"cat\"* \"NEAR\"* \"a\"* \"b\"*"
+        );
+    }
+
+    #[test]
+    fn rejects_empty_and_oversized_queries() {
+        assert!(build_match_query("").is_err());
+        assert!(build_match_query(" \"*- ").is_err());
+        assert!(build_match_query(&"a ".repeat(MAX_TERMS + 1)).is_err());
+        assert!(build_match_query(&"a".repeat(MAX_QUERY_CHARS + 1)).is_err());
+    }
+
+    #[test]
+    fn snippets_escape_text_and_mark_matches() {
+        assert_eq!(
+            render_snippet("a <b>\u{2}fox\u{3}</b> & \u{2}cat\u{3}…"),
+            "a &lt;b&gt;<mark>fox</mark>&lt;/b&gt; &amp; <mark>cat</mark>…"
+        );
+    }
+}
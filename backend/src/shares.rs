@@ -0,0 +1,319 @@
+use crate::db::get_db;
+use crate::error::AppError;
+use crate::models::{Video, VideoShare};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{DateTime, Utc};
+use worker::{wasm_bindgen::JsValue, Env};
+
+const SHARE_COLUMNS: &str = "slug, video_id, user_id, expires_at, max_views, view_count, revoked_at, created_at";
+
+const MAX_EXPIRY_HOURS: i64 = 24 * 365;
+const MAX_VIEWS: i64 = 1_000_000;
+const MAX_SLUG_CHARS: usize = 32;
+
+const SITE_NAME: &str = "Sora Engine";
+const MAX_TITLE_CHARS: usize = 70;
+const MAX_DESCRIPTION_CHARS: usize = 200;
+
+pub fn service_url(env: &Env) -> Result<String, AppError> {
+    Ok(env
+        .var("SERVICE_URL")
+        .map_err(|_| AppError::InternalError("SERVICE_URL not configured".into()))?
+        .to_string()
+        .trim_end_matches('/')
+        .to_string())
+}
+
+/// 72 random bits, URL-safe: 12 characters.
+pub fn generate_slug() -> String {
+    URL_SAFE_NO_PAD.encode(&uuid::Uuid::new_v4().as_bytes()[..9])
+}
+
+/// Rejects slugs we could never have issued before touching the database.
+pub fn is_valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug.len() <= MAX_SLUG_CHARS
+        && slug.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+pub fn validate_limits(expires_in_hours: Option<i64>, max_views: Option<i64>) -> Result<(), AppError> {
+    if expires_in_hours.is_some_and(|hours| !(1..=MAX_EXPIRY_HOURS).contains(&hours)) {
+        return Err(AppError::BadRequest(format!(
+            "expires_in_hours must be between 1 and {}",
+            MAX_EXPIRY_HOURS
+        )));
+    }
+    if max_views.is_some_and(|views| !(1..=MAX_VIEWS).contains(&views)) {
+        return Err(AppError::BadRequest(format!("max_views must be between 1 and {}", MAX_VIEWS)));
+    }
+
+    Ok(())
+}
+
+pub fn page_url(env: &Env, slug: &str) -> Result<String, AppError> {
+    Ok(format!("{}/s/{}", service_url(env)?, slug))
+}
+
+/// A proxy URL that is authorized by the share instead of a user.
+fn media_url(service_url: &str, video: &Video, variant: &str, slug: &str) -> String {
+    format!(
+        "{}/v1/videos/{}/proxy?variant={}&share={}",
+        service_url, video.openai_video_id, variant, slug
+    )
+}
+
+fn optional_date(value: Option<DateTime<Utc>>) -> JsValue {
+    value.map(|d| JsValue::from_str(&d.to_rfc3339())).unwrap_or(JsValue::NULL)
+}
+
+pub async fn create_share(env: &Env, share: &VideoShare) -> Result<(), AppError> {
+    let db = get_db(env)?;
+
+    db.prepare("INSERT INTO video_shares (slug, video_id, user_id, expires_at, max_views, view_count, created_at) VALUES (?, ?, ?, ?, ?, 0, ?)")
+        .bind(&[
+            share.slug.clone().into(),
+            share.video_id.clone().into(),
+            share.user_id.clone().into(),
+            optional_date(share.expires_at),
+            share.max_views.map(|v| JsValue::from_f64(v as f64)).unwrap_or(JsValue::NULL),
+            share.created_at.to_rfc3339().into(),
+        ])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Counts a page view and returns the share, or `None` if the link is
+/// revoked, expired or out of views. One statement, so concurrent views
+/// can't overshoot `max_views`.
+pub async fn record_view(env: &Env, slug: &str, now: DateTime<Utc>) -> Result<Option<VideoShare>, AppError> {
+    let db = get_db(env)?;
+
+    db.prepare(format!(
+        "UPDATE video_shares SET view_count = view_count + 1 WHERE slug = ? AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > ?) AND (max_views IS NULL OR view_count < max_views) RETURNING {}",
+        SHARE_COLUMNS
+    ))
+    .bind(&[slug.into(), now.to_rfc3339().into()])?
+    .first(None)
+    .await
+    .map_err(|e| AppError::DatabaseError(e.to_string()))
+}
+
+/// Checks that `slug` is a live share of `video_id`, for the media requests
+/// a share page makes. Only expiry and revocation apply: `max_views` counts
+/// page loads, and the page load that uses up the last view still needs its
+/// media, so it can't be enforced here without per-view tokens.
+pub async fn authorize_media(env: &Env, slug: &str, video_id: &str, now: DateTime<Utc>) -> Result<(), AppError> {
+    let unauthorized = || AppError::Unauthorized("Not authorized to access this video".into());
+
+    if !is_valid_slug(slug) {
+        return Err(unauthorized());
+    }
+
+    let db = get_db(env)?;
+    let share: Option<VideoShare> = db
+        .prepare(format!(
+            "SELECT {} FROM video_shares WHERE slug = ? AND video_id = ? AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > ?)",
+            SHARE_COLUMNS
+        ))
+        .bind(&[slug.into(), video_id.into(), now.to_rfc3339().into()])?
+        .first(None)
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    share.map(|_| ()).ok_or_else(unauthorized)
+}
+
+/// Revokes one of the user's shares. Returns false if there was no live
+/// share with that slug.
+pub async fn revoke_share(env: &Env, slug: &str, user_id: &str, now: DateTime<Utc>) -> Result<bool, AppError> {
+    let db = get_db(env)?;
+
+    let result = db
+        .prepare("UPDATE video_shares SET revoked_at = ? WHERE slug = ? AND user_id = ? AND revoked_at IS NULL")
+        .bind(&[now.to_rfc3339().into(), slug.into(), user_id.into()])?
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) > 0)
+}
+
+pub fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+fn truncate(value: &str, max_chars: usize) -> String {
+    let value = value.trim();
+    if value.chars().count() <= max_chars {
+        return value.to_string();
+    }
+
+    let cut: String = value.chars().take(max_chars - 1).collect();
+    format!("{}…", cut.trim_end())
+}
+
+fn dimensions(size: &str) -> Option<(u32, u32)> {
+    let (width, height) = size.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+/// The public share page: Open Graph and Twitter player-card tags for
+/// unfurling, and a plain `<video>` for people who open the link.
+pub fn render_page(service_url: &str, video: &Video, slug: &str) -> String {
+    let page_url = format!("{}/s/{}", service_url, slug);
+    let video_url = escape_html(&media_url(service_url, video, "video", slug));
+    let thumbnail_url = escape_html(&media_url(service_url, video, "thumbnail", slug));
+    let title = escape_html(&truncate(&video.prompt, MAX_TITLE_CHARS));
+    let description = escape_html(&truncate(&video.prompt, MAX_DESCRIPTION_CHARS));
+    let page_url = escape_html(&page_url);
+
+    let size_tags = dimensions(&video.size)
+        .map(|(width, height)| {
+            format!(
+                "<meta property=\"og:video:width\" content=\"{w}\">\n\
+                 <meta property=\"og:video:height\" content=\"{h}\">\n\
+                 <meta name=\"twitter:player:width\" content=\"{w}\">\n\
+                 <meta name=\"twitter:player:height\" content=\"{h}\">\n",
+                w = width,
+                h = height
+            )
+        })
+        .unwrap_or_default();
+
+    format!(
+        "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<meta name=\"robots\" content=\"noindex\">
+<title>{title}</title>
+<meta name=\"description\" content=\"{description}\">
+<meta property=\"og:site_name\" content=\"{site}\">
+<meta property=\"og:type\" content=\"video.other\">
+<meta property=\"og:title\" content=\"{title}\">
+<meta property=\"og:description\" content=\"{description}\">
+<meta property=\"og:url\" content=\"{page_url}\">
+<meta property=\"og:image\" content=\"{thumbnail_url}\">
+<meta property=\"og:video\" content=\"{video_url}\">
+<meta property=\"og:video:secure_url\" content=\"{video_url}\">
+<meta property=\"og:video:type\" content=\"video/mp4\">
+{size_tags}<meta name=\"twitter:card\" content=\"player\">
+<meta name=\"twitter:title\" content=\"{title}\">
+<meta name=\"twitter:description\" content=\"{description}\">
+<meta name=\"twitter:image\" content=\"{thumbnail_url}\">
+<meta name=\"twitter:player\" content=\"{page_url}\">
+<meta name=\"twitter:player:stream\" content=\"{video_url}\">
+<meta name=\"twitter:player:stream:content_type\" content=\"video/mp4\">
+<style>body{{margin:0;background:#000;display:flex;align-items:center;justify-content:center;min-height:100vh}}video{{max-width:100%;max-height:100vh}}</style>
+</head>
+<body>
+<video src=\"{video_url}\" poster=\"{thumbnail_url}\" controls playsinline preload=\"metadata\"></video>
+</body>
+</html>
+",
+        site = SITE_NAME,
+    )
+}
+
+pub fn render_unavailable_page() -> String {
+    format!(
+        "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"robots\" content=\"noindex\">
+<title>{site}</title>
+</head>
+<body>
+<p>This video link has expired or is no longer available.</p>
+</body>
+</html>
+",
+        site = SITE_NAME
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::VideoStatus;
+
+    fn video(prompt: &str) -> Video {
+        serde_json::from_value(serde_json::json!({
+            "id": "vid_1",
+            "user_id": "user_1",
+            "openai_video_id": "video_abc",
+            "status": "completed",
+            "model": "sora-2",
+            "prompt": prompt,
+            "size": "1280x720",
+            "seconds": 4,
+            "credits_cost": 10,
+            "progress": 100,
+            "created_at": "2025-01-01T00:00:00Z"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn slugs_are_url_safe() {
+        let slug = generate_slug();
+        assert_eq!(slug.len(), 12);
+        assert!(is_valid_slug(&slug));
+        assert!(!is_valid_slug(""));
+        assert!(!is_valid_slug("../etc"));
+        assert!(!is_valid_slug(&"a".repeat(MAX_SLUG_CHARS + 1)));
+    }
+
+    #[test]
+    fn limits_are_bounded() {
+        assert!(validate_limits(None, None).is_ok());
+        assert!(validate_limits(Some(24), Some(100)).is_ok());
+        assert!(validate_limits(Some(0), None).is_err());
+        assert!(validate_limits(Some(MAX_EXPIRY_HOURS + 1), None).is_err());
+        assert!(validate_limits(None, Some(0)).is_err());
+    }
+
+    #[test]
+    fn page_escapes_the_prompt_and_scopes_media_to_the_share() {
+        let video = video("A \"fox\" <script>alert(1)</script> & friends");
+        assert_eq!(video.status, VideoStatus::Completed);
+
+        let page = render_page("https://api.example.com", &video, "abc123");
+
+        assert!(!page.contains("<script>"));
+        assert!(page.contains("A &quot;fox&quot; &lt;script&gt;alert(1)&lt;/script&gt; &amp; friends"));
+        assert!(page.contains(
+            "<meta property=\"og:video\" content=\"https://api.example.com/v1/videos/video_abc/proxy?variant=video&amp;share=abc123\">"
+        ));
+        assert!(page.contains("<meta property=\"og:image\" content=\"https://api.example.com/v1/videos/video_abc/proxy?variant=thumbnail&amp;share=abc123\">"));
+        assert!(page.contains("<meta property=\"og:video:width\" content=\"1280\">"));
+        assert!(page.contains("<meta name=\"twitter:card\" content=\"player\">"));
+        assert!(!page.contains("user_id"));
+    }
+
+    #[test]
+    fn long_prompts_are_truncated() {
+        let title = truncate(&"word ".repeat(40), MAX_TITLE_CHARS);
+        assert_eq!(title.chars().count(), MAX_TITLE_CHARS);
+        assert!(title.ends_with('…'));
+        assert_eq!(truncate(" short ", MAX_TITLE_CHARS), "short");
+    }
+}
@@ -0,0 +1,290 @@
+use crate::credits;
+use crate::db::{get_db, now_datetime};
+use crate::error::AppError;
+use crate::generation_queue;
+use crate::models::{OpenAIVideoResponse, Video, VideoStatus};
+use crate::notifications;
+use crate::video_provider::VideoProvider;
+use chrono::Duration;
+use worker::{console_log, Env};
+
+const ALL_STATUSES: [VideoStatus; 6] = [
+    VideoStatus::PendingSubmission,
+    VideoStatus::Queued,
+    VideoStatus::InProgress,
+    VideoStatus::Completed,
+    VideoStatus::Failed,
+    VideoStatus::Cancelled,
+];
+
+const DEFAULT_FAILURE_REFUND: &str = "Video generation failed - credits refunded";
+
+/// The transition table. A running status may repeat so progress updates
+/// apply, and terminal statuses never change. `pending_submission` →
+/// `queued` is claimed by `generation_queue`, which checks capacity in the
+/// same statement.
+pub fn can_transition(from: &VideoStatus, to: &VideoStatus) -> bool {
+    use VideoStatus::*;
+
+    matches!(
+        (from, to),
+        (PendingSubmission, Queued | Cancelled)
+            | (Queued, Queued | InProgress | Completed | Failed | Cancelled)
+            | (InProgress, InProgress | Completed | Failed | Cancelled)
+    )
+}
+
+/// The statuses `to` can be reached from, as a SQL list.
+fn sources_sql(to: &VideoStatus) -> String {
+    ALL_STATUSES
+        .iter()
+        .filter(|from| can_transition(from, to))
+        .map(|from| format!("'{}'", from))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Something a webhook, poll, cron run or user observed about a video.
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Progress { status: VideoStatus, progress: i32 },
+    Completed,
+    Failed {
+        error_message: String,
+        refund_description: &'static str,
+    },
+    Cancelled,
+}
+
+impl Event {
+    pub fn failed(error_message: impl Into<String>) -> Self {
+        Event::Failed {
+            error_message: error_message.into(),
+            refund_description: DEFAULT_FAILURE_REFUND,
+        }
+    }
+
+    fn target(&self) -> VideoStatus {
+        match self {
+            Event::Progress { status, .. } => status.clone(),
+            Event::Completed => VideoStatus::Completed,
+            Event::Failed { .. } => VideoStatus::Failed,
+            Event::Cancelled => VideoStatus::Cancelled,
+        }
+    }
+
+    /// Reads a provider status. Statuses we don't know are ignored.
+    pub fn from_response(response: &OpenAIVideoResponse) -> Option<Self> {
+        match response.status.as_str() {
+            "queued" => Some(Event::Progress {
+                status: VideoStatus::Queued,
+                progress: response.progress.unwrap_or(0),
+            }),
+            "in_progress" => Some(Event::Progress {
+                status: VideoStatus::InProgress,
+                progress: response.progress.unwrap_or(0),
+            }),
+            "completed" => Some(Event::Completed),
+            "failed" => Some(Event::failed(
+                response
+                    .error
+                    .as_ref()
+                    .map(|e| e.message.clone())
+                    .unwrap_or_else(|| "Unknown error".to_string()),
+            )),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// This call made the transition and ran its side effects.
+    Applied,
+    /// Another path moved the video first, so nothing was done.
+    Stale,
+    /// The table doesn't allow the transition from the video's status.
+    Rejected,
+}
+
+impl Outcome {
+    pub fn is_applied(&self) -> bool {
+        *self == Outcome::Applied
+    }
+}
+
+fn proxy_url(service_url: &str, video: &Video, variant: &str) -> String {
+    format!(
+        "{}/v1/videos/{}/proxy?variant={}&user_id={}",
+        service_url, video.openai_video_id, variant, video.user_id
+    )
+}
+
+/// Moves the video to the event's status if it is still in a status the
+/// table allows, in one conditional update. Returns whether it changed.
+async fn compare_and_set(env: &Env, video: &Video, event: &Event) -> Result<bool, AppError> {
+    let db = get_db(env)?;
+    let now = now_datetime();
+    let sources = sources_sql(&event.target());
+
+    let statement = match event {
+        Event::Progress { status, progress } => db
+            .prepare(format!("UPDATE videos SET status = ?, progress = ? WHERE id = ? AND status IN ({})", sources))
+            .bind(&[status.to_string().into(), (*progress).into(), video.id.clone().into()])?,
+        Event::Completed => {
+            let service_url = env
+                .var("SERVICE_URL")
+                .map_err(|_| AppError::InternalError("SERVICE_URL not configured".into()))?
+                .to_string();
+
+            db.prepare(format!("UPDATE videos SET status = 'completed', video_url = ?, thumbnail_url = ?, spritesheet_url = ?, download_url_expires_at = ?, completed_at = ?, progress = 100 WHERE id = ? AND status IN ({})", sources))
+                .bind(&[
+                    proxy_url(&service_url, video, "video").into(),
+                    proxy_url(&service_url, video, "thumbnail").into(),
+                    proxy_url(&service_url, video, "spritesheet").into(),
+                    (now + Duration::hours(24)).to_rfc3339().into(),
+                    now.to_rfc3339().into(),
+                    video.id.clone().into(),
+                ])?
+        }
+        Event::Failed { error_message, .. } => db
+            .prepare(format!("UPDATE videos SET status = 'failed', error_message = ?, failed_at = ? WHERE id = ? AND status IN ({})", sources))
+            .bind(&[error_message.clone().into(), now.to_rfc3339().into(), video.id.clone().into()])?,
+        Event::Cancelled => db
+            .prepare(format!("UPDATE videos SET status = 'cancelled', cancelled_at = ? WHERE id = ? AND status IN ({})", sources))
+            .bind(&[now.to_rfc3339().into(), video.id.clone().into()])?,
+    };
+
+    let result = statement
+        .run()
+        .await
+        .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+    Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) > 0)
+}
+
+async fn notify(env: &Env, video: &Video, notification_type: &str, title: &str, body: &str) {
+    let metadata = serde_json::json!({ "video_id": video.id });
+
+    if let Err(e) = notifications::create_notification(env, &video.user_id, notification_type, title, body, Some(metadata)).await {
+        console_log!("Failed to notify user {} about video {}: {:?}", video.user_id, video.id, e);
+    }
+}
+
+/// Applies `event` to `video`. Only the caller whose update wins runs the
+/// side effects, so a webhook, a poll and the cron seeing the same
+/// completion or failure settle the ledger and notify the user
+/// once. Cancellation refunds follow the cancel policy and are left to the
+/// caller.
+pub async fn apply(env: &Env, video: &Video, event: Event) -> Result<Outcome, AppError> {
+    let target = event.target();
+
+    if !can_transition(&video.status, &target) {
+        console_log!("Rejected transition {} -> {} for video {}", video.status, target, video.id);
+        return Ok(Outcome::Rejected);
+    }
+
+    if !compare_and_set(env, video, &event).await? {
+        console_log!("Video {} moved before {} -> {} applied", video.id, video.status, target);
+        return Ok(Outcome::Stale);
+    }
+
+    match event {
+        Event::Completed => {
+            // The copy to R2 is left to the reconciler so webhooks and polls
+            // don't wait on a full download. Until then the proxy serves
+            // from the provider.
+            console_log!("Video completed: {}", video.id);
+            notify(env, video, "video_completed", "Your video is ready", &format!("\"{}\" finished generating.", video.prompt)).await;
+        }
+        Event::Failed { error_message, refund_description } => {
+            let refunded = credits::refund_video_charges(env, &video.user_id, &video.id, refund_description).await?;
+            console_log!("Video {} failed and {} credits were refunded: {}", video.id, refunded, error_message);
+            notify(
+                env,
+                video,
+                "video_failed",
+                "Your video failed",
+                &format!("{} Your {} credits were refunded.", error_message, refunded),
+            )
+            .await;
+        }
+        Event::Progress { .. } | Event::Cancelled => {}
+    }
+
+    Ok(Outcome::Applied)
+}
+
+/// `apply`, then submits the user's next queued job if the video finished.
+pub async fn apply_and_advance(
+    env: &Env,
+    provider: &dyn VideoProvider,
+    video: &Video,
+    event: Event,
+) -> Result<Outcome, AppError> {
+    let finishes = !matches!(event, Event::Progress { .. });
+    let outcome = apply(env, video, event).await?;
+
+    if finishes && outcome.is_applied() {
+        generation_queue::advance(env, provider, &video.user_id).await;
+    }
+
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::OpenAIError;
+
+    fn response(status: &str, progress: Option<i32>, error: Option<&str>) -> OpenAIVideoResponse {
+        OpenAIVideoResponse {
+            id: "video_1".into(),
+            object: "video".into(),
+            created_at: 0,
+            status: status.into(),
+            model: "sora-2".into(),
+            progress,
+            seconds: None,
+            size: None,
+            error: error.map(|message| OpenAIError {
+                code: "error".into(),
+                message: message.into(),
+            }),
+        }
+    }
+
+    #[test]
+    fn terminal_statuses_never_change() {
+        for from in [VideoStatus::Completed, VideoStatus::Failed, VideoStatus::Cancelled] {
+            for to in &ALL_STATUSES {
+                assert!(!can_transition(&from, to), "{} -> {} should be rejected", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn running_videos_can_finish_or_progress() {
+        assert!(can_transition(&VideoStatus::Queued, &VideoStatus::InProgress));
+        assert!(can_transition(&VideoStatus::InProgress, &VideoStatus::InProgress));
+        assert!(can_transition(&VideoStatus::InProgress, &VideoStatus::Completed));
+        assert!(!can_transition(&VideoStatus::InProgress, &VideoStatus::Queued));
+        assert!(!can_transition(&VideoStatus::PendingSubmission, &VideoStatus::Completed));
+        assert_eq!(sources_sql(&VideoStatus::Failed), "'queued', 'in_progress'");
+        assert_eq!(sources_sql(&VideoStatus::Cancelled), "'pending_submission', 'queued', 'in_progress'");
+    }
+
+    #[test]
+    fn reads_provider_statuses() {
+        assert_eq!(
+            Event::from_response(&response("in_progress", Some(40), None)),
+            Some(Event::Progress { status: VideoStatus::InProgress, progress: 40 })
+        );
+        assert_eq!(Event::from_response(&response("completed", None, None)), Some(Event::Completed));
+        assert_eq!(
+            Event::from_response(&response("failed", None, Some("Blocked"))),
+            Some(Event::failed("Blocked"))
+        );
+        assert_eq!(Event::from_response(&response("archived", None, None)), None);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::error::AppError;
+use crate::mock_provider::MockVideoProvider;
+use crate::models::OpenAIVideoResponse;
+use crate::openai_client::OpenAIClient;
+use std::rc::Rc;
+use worker::{async_trait::async_trait, Env, Response};
+
+pub struct InputReference<'a> {
+    pub filename: &'a str,
+    pub mime_type: &'a str,
+    pub bytes: &'a [u8],
+}
+
+/// The video generation backend. `OpenAIClient` talks to the real API, and
+/// `MockVideoProvider` scripts responses so the worker can run offline.
+#[async_trait(?Send)]
+pub trait VideoProvider {
+    /// `request_id` identifies the local job (the video's own id). It's sent
+    /// as the idempotency key, so a retried submission of the same job can't
+    /// start a second generation.
+    async fn create_video(
+        &self,
+        request_id: &str,
+        model: &str,
+        prompt: &str,
+        size: &str,
+        seconds: i32,
+        input_reference: Option<InputReference<'_>>,
+    ) -> Result<OpenAIVideoResponse, AppError>;
+
+    async fn retrieve_video(&self, video_id: &str) -> Result<OpenAIVideoResponse, AppError>;
+
+    /// Fetches a rendered asset (`video`, `thumbnail` or `spritesheet`). The
+    /// response is returned as-is so callers can stream it.
+    async fn download_content(
+        &self,
+        video_id: &str,
+        variant: &str,
+        range: Option<&str>,
+    ) -> Result<Response, AppError>;
+
+    async fn delete_video(&self, video_id: &str) -> Result<(), AppError>;
+
+    async fn remix_video(&self, video_id: &str, prompt: &str) -> Result<OpenAIVideoResponse, AppError>;
+}
+
+/// Picks the provider from `VIDEO_PROVIDER` (`openai` or `mock`, default `openai`).
+pub fn from_env(env: &Env) -> Result<Rc<dyn VideoProvider>, AppError> {
+    let provider = env
+        .var("VIDEO_PROVIDER")
+        .map(|v| v.to_string())
+        .unwrap_or_else(|_| "openai".to_string());
+
+    match provider.as_str() {
+        "openai" => Ok(Rc::new(OpenAIClient::from_env(env))),
+        "mock" => Ok(MockVideoProvider::shared()),
+        other => Err(AppError::InternalError(format!("Unknown VIDEO_PROVIDER: {}", other))),
+    }
+}